@@ -0,0 +1,11 @@
+//! Language server for er files.
+//!
+//! Speaks the language server protocol over stdin and stdout; point an editor's
+//! lsp client at this program for `.erd` files.
+
+fn main() {
+    if let Err(e) = erd_rs::lsp::serve_stdio() {
+        eprintln!("erd-lsp: {}", e);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,784 @@
+//! Command-line program mirroring the haskell `erd`.
+//!
+//! Reads an er file and writes it out as a graphviz dot document, a Mermaid
+//! `erDiagram`, a PlantUML diagram or (when the `dot` program is on the
+//! `PATH`) any of the image formats graphviz can produce.
+//! `erd fmt` formats er files instead, `erd rename` renames entities in them,
+//! `erd diff` compares two of them and `erd convert` converts between them and
+//! JSON, YAML, Mermaid or DBML.
+
+use erd_rs::{
+    dbml, er::ER, import::Warning, mermaid, parser::parse_recovering, schema, validate::Severity,
+    Error,
+};
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+use structopt::StructOpt;
+
+/// Exit code used when the er file couldn't be parsed.
+const EXIT_PARSE_ERROR: i32 = 2;
+/// Exit code used when a formatting option in the er file is bad.
+const EXIT_OPTION_ERROR: i32 = 3;
+/// Exit code used when `--validate` finds errors in the er file.
+const EXIT_VALIDATION_ERROR: i32 = 4;
+/// Exit code used when `erd fmt --check` finds files which aren't formatted.
+const EXIT_NOT_FORMATTED: i32 = 5;
+/// Exit code used for everything else (io, `dot` failing, etc).
+const EXIT_OTHER_ERROR: i32 = 1;
+
+#[derive(StructOpt)]
+#[structopt(name = "erd", about = "Renders entity-relationship diagrams.")]
+struct Opts {
+    /// When set, the er file is read from this path instead of stdin.
+    #[structopt(short = "i", long = "input", parse(from_os_str))]
+    input: Option<PathBuf>,
+    /// When set, output is written to this path instead of stdout.
+    #[structopt(short = "o", long = "output", parse(from_os_str))]
+    output: Option<PathBuf>,
+    /// The output format: dot, mermaid, plantuml, svg, png or pdf.
+    ///
+    /// Defaults to the extension of the output path (`.mmd` for mermaid,
+    /// `.puml` for plantuml), or dot when there isn't one. svg, png and pdf
+    /// require graphviz's `dot` program.
+    #[structopt(short = "f", long = "fmt")]
+    fmt: Option<Format>,
+    /// Check the er file for problems (undefined entities, duplicates, etc)
+    /// before rendering it.
+    ///
+    /// Problems are reported on stderr. Warnings don't stop the diagram from
+    /// being rendered, but errors do.
+    #[structopt(long = "validate")]
+    validate: bool,
+    #[structopt(subcommand)]
+    cmd: Option<Cmd>,
+}
+
+#[derive(StructOpt)]
+enum Cmd {
+    /// Formats er files in place, or stdin to stdout when no files are given.
+    Fmt {
+        /// Don't write anything, just fail if any of the files aren't
+        /// formatted.
+        #[structopt(long = "check")]
+        check: bool,
+        #[structopt(parse(from_os_str))]
+        files: Vec<PathBuf>,
+    },
+    /// Renames an entity, along with every relationship which refers to it.
+    ///
+    /// Files are edited in place, or stdin is written to stdout when no files
+    /// are given. Comments and layout are left as they were.
+    Rename {
+        /// The entity's current name.
+        old: String,
+        /// The entity's new name.
+        new: String,
+        #[structopt(parse(from_os_str))]
+        files: Vec<PathBuf>,
+    },
+    /// Lists the changes between two versions of an er file.
+    ///
+    /// Only changes to the diagram count: formatting, comments and the order
+    /// attributes are listed in are ignored.
+    Diff {
+        /// Write the changes as JSON rather than text.
+        #[structopt(long = "json")]
+        json: bool,
+        /// Draw the changes instead of listing them, as a diagram of the new
+        /// version in which additions are green, removals are red and struck
+        /// through and other changes are orange.
+        ///
+        /// Takes the same formats as rendering an er file, and implied by
+        /// `--output`.
+        #[structopt(short = "f", long = "fmt")]
+        fmt: Option<Format>,
+        /// When set, the diagram is written to this path instead of stdout.
+        #[structopt(short = "o", long = "output", parse(from_os_str))]
+        output: Option<PathBuf>,
+        #[structopt(parse(from_os_str))]
+        old: PathBuf,
+        #[structopt(parse(from_os_str))]
+        new: PathBuf,
+    },
+    /// Converts between er files and JSON, YAML, Mermaid or DBML.
+    ///
+    /// JSON and YAML follow the schema documented in the library's `schema`
+    /// module. Imported documents are checked the same way er files are, so
+    /// they can be rendered or formatted once converted. Anything in a
+    /// Mermaid diagram or DBML document which can't be carried over is
+    /// reported on stderr.
+    Convert {
+        /// The format to read: erd, json, yaml, mermaid or dbml.
+        ///
+        /// Defaults to the extension of the input path, or erd when there
+        /// isn't one.
+        #[structopt(long = "from")]
+        from: Option<DataFormat>,
+        /// The format to write: erd, json, yaml, mermaid or dbml.
+        #[structopt(long = "to")]
+        to: DataFormat,
+        /// When set, output is written to this path instead of stdout.
+        #[structopt(short = "o", long = "output", parse(from_os_str))]
+        output: Option<PathBuf>,
+        /// The file to convert, or stdin when not given.
+        #[structopt(parse(from_os_str))]
+        input: Option<PathBuf>,
+    },
+}
+
+/// The formats we know how to write.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Format {
+    Dot,
+    Mermaid,
+    PlantUml,
+    Svg,
+    Png,
+    Pdf,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "dot" => Ok(Format::Dot),
+            "mermaid" | "mmd" => Ok(Format::Mermaid),
+            "plantuml" | "puml" => Ok(Format::PlantUml),
+            "svg" => Ok(Format::Svg),
+            "png" => Ok(Format::Png),
+            "pdf" => Ok(Format::Pdf),
+            _ => Err(format!("unsupported format `{}`", s)),
+        }
+    }
+}
+
+/// The formats the model can be converted between.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum DataFormat {
+    Erd,
+    Json,
+    Yaml,
+    Mermaid,
+    Dbml,
+}
+
+impl FromStr for DataFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "erd" | "er" => Ok(DataFormat::Erd),
+            "json" => Ok(DataFormat::Json),
+            "yaml" | "yml" => Ok(DataFormat::Yaml),
+            "mermaid" | "mmd" => Ok(DataFormat::Mermaid),
+            "dbml" => Ok(DataFormat::Dbml),
+            _ => Err(format!("unsupported format `{}`", s)),
+        }
+    }
+}
+
+impl DataFormat {
+    /// Guess the format based on the extension of the input path.
+    fn from_path(path: &Path) -> Option<Self> {
+        path.extension()?.to_str()?.parse().ok()
+    }
+}
+
+impl Format {
+    /// The name of the format as understood by `dot -T`.
+    fn name(self) -> &'static str {
+        match self {
+            Format::Dot => "dot",
+            Format::Mermaid => "mermaid",
+            Format::PlantUml => "plantuml",
+            Format::Svg => "svg",
+            Format::Png => "png",
+            Format::Pdf => "pdf",
+        }
+    }
+
+    /// Guess the format based on the extension of the output path.
+    fn from_path(path: &Path) -> Option<Self> {
+        path.extension()?.to_str()?.parse().ok()
+    }
+}
+
+fn main() {
+    let opts = Opts::from_args();
+    if let Err((code, msg)) = run(opts) {
+        eprintln!("{}", msg.trim_end());
+        std::process::exit(code);
+    }
+}
+
+fn run(opts: Opts) -> Result<(), (i32, String)> {
+    match opts.cmd {
+        Some(Cmd::Fmt { check, files }) => return run_fmt(check, &files),
+        Some(Cmd::Rename { old, new, files }) => return run_rename(&old, &new, &files),
+        Some(Cmd::Diff {
+            json,
+            fmt,
+            output,
+            old,
+            new,
+        }) => {
+            let (old, new) = (load(&old)?, load(&new)?);
+            if fmt.is_some() || output.is_some() {
+                if let Format::Mermaid | Format::PlantUml = output_format(fmt, output.as_deref()) {
+                    return Err(other("diffs can only be drawn with graphviz"));
+                }
+                let mut dot = vec![];
+                erd_rs::dot::render_diff(&old, &new, &mut dot).map_err(other)?;
+                let dot = String::from_utf8(dot).map_err(other)?;
+                return write_diagram(&dot, fmt, output);
+            }
+            let diff = list_changes(json, &old, &new)?;
+            return io::stdout().write_all(diff.as_bytes()).map_err(other);
+        }
+        Some(Cmd::Convert {
+            from,
+            to,
+            output,
+            input,
+        }) => {
+            let input_text = read_input(input.as_deref()).map_err(other)?;
+            let origin = match &input {
+                Some(path) => path.display().to_string(),
+                None => "<stdin>".to_string(),
+            };
+            let from = from
+                .or_else(|| input.as_deref().and_then(DataFormat::from_path))
+                .unwrap_or(DataFormat::Erd);
+            let converted = convert(&input_text, &origin, from, to)?;
+            return match output {
+                Some(path) => std::fs::write(path, converted),
+                None => io::stdout().write_all(converted.as_bytes()),
+            }
+            .map_err(other);
+        }
+        None => {}
+    }
+
+    let input = read_input(opts.input.as_deref()).map_err(other)?;
+    let origin = match &opts.input {
+        Some(path) => path.display().to_string(),
+        None => "<stdin>".to_string(),
+    };
+    let er: ER = input
+        .parse()
+        .map_err(|e| (exit_code(&e), report(&e, &input, &origin)))?;
+    if opts.validate {
+        validate(&er, &origin)?;
+    }
+    match output_format(opts.fmt, opts.output.as_deref()) {
+        Format::Mermaid => write_output(er.to_mermaid().into_bytes(), opts.output),
+        Format::PlantUml => write_output(er.to_plantuml().into_bytes(), opts.output),
+        fmt => write_diagram(&er.to_dot(), Some(fmt), opts.output),
+    }
+}
+
+/// The format asked for, defaulting to the extension of `output`.
+fn output_format(fmt: Option<Format>, output: Option<&Path>) -> Format {
+    fmt.or_else(|| output.and_then(Format::from_path))
+        .unwrap_or(Format::Dot)
+}
+
+/// Writes a dot document to `output` (or stdout) in the format asked for,
+/// defaulting to the extension of `output`.
+fn write_diagram(
+    dot: &str,
+    fmt: Option<Format>,
+    output: Option<PathBuf>,
+) -> Result<(), (i32, String)> {
+    let bytes = match output_format(fmt, output.as_deref()) {
+        Format::Dot => dot.as_bytes().to_vec(),
+        fmt => run_dot(fmt, dot)?,
+    };
+    write_output(bytes, output)
+}
+
+/// Writes `bytes` to `output`, or stdout.
+fn write_output(bytes: Vec<u8>, output: Option<PathBuf>) -> Result<(), (i32, String)> {
+    match output {
+        Some(path) => File::create(path).and_then(|mut f| f.write_all(&bytes)),
+        None => io::stdout().write_all(&bytes),
+    }
+    .map_err(other)
+}
+
+/// Describe every problem in an er file which failed to parse, not just the
+/// first one found.
+fn report(err: &Error, input: &str, origin: &str) -> String {
+    let (_, mut diagnostics) = parse_recovering(input);
+    if diagnostics.is_empty() {
+        diagnostics.push(err.diagnostic(input));
+    }
+    diagnostics
+        .iter()
+        .map(|d| d.render(input, origin))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Formats each of `files`, or stdin when there are none.
+fn run_fmt(check: bool, files: &[PathBuf]) -> Result<(), (i32, String)> {
+    if files.is_empty() {
+        let input = read_input(None).map_err(other)?;
+        let formatted = format_input(&input, "<stdin>")?;
+        if check {
+            if formatted != input {
+                return Err((EXIT_NOT_FORMATTED, "erd: <stdin> is not formatted".into()));
+            }
+            return Ok(());
+        }
+        return io::stdout().write_all(formatted.as_bytes()).map_err(other);
+    }
+
+    let mut unformatted = vec![];
+    for path in files {
+        let input = read_input(Some(path)).map_err(other)?;
+        let formatted = format_input(&input, &path.display().to_string())?;
+        if formatted == input {
+            continue;
+        }
+        if check {
+            unformatted.push(format!("erd: {} is not formatted", path.display()));
+        } else {
+            std::fs::write(path, formatted).map_err(other)?;
+        }
+    }
+    if !unformatted.is_empty() {
+        return Err((EXIT_NOT_FORMATTED, unformatted.join("\n")));
+    }
+    Ok(())
+}
+
+fn format_input(input: &str, origin: &str) -> Result<String, (i32, String)> {
+    erd_rs::fmt::format(input).map_err(|e| (exit_code(&e), report(&e, input, origin)))
+}
+
+/// Renames `old` to `new` in each of `files`, or stdin when there are none.
+///
+/// Files which don't mention `old` are left alone, but at least one of them
+/// has to.
+fn run_rename(old: &str, new: &str, files: &[PathBuf]) -> Result<(), (i32, String)> {
+    let unknown = || {
+        other(Error::UnknownEntity {
+            name: old.to_string(),
+        })
+    };
+
+    if files.is_empty() {
+        let input = read_input(None).map_err(other)?;
+        let renamed = rename_input(&input, old, new, "<stdin>")?.ok_or_else(unknown)?;
+        return io::stdout().write_all(renamed.as_bytes()).map_err(other);
+    }
+
+    // Nothing is written until every file has been renamed, so a problem with
+    // one of them doesn't leave the rest half done.
+    let mut renamed = vec![];
+    for path in files {
+        let input = read_input(Some(path)).map_err(other)?;
+        if let Some(text) = rename_input(&input, old, new, &path.display().to_string())? {
+            renamed.push((path, text));
+        }
+    }
+    if renamed.is_empty() {
+        return Err(unknown());
+    }
+    for (path, text) in renamed {
+        std::fs::write(path, text).map_err(other)?;
+    }
+    Ok(())
+}
+
+/// Renames `old` to `new` in `input`, giving `None` when it isn't there.
+fn rename_input(
+    input: &str,
+    old: &str,
+    new: &str,
+    origin: &str,
+) -> Result<Option<String>, (i32, String)> {
+    match erd_rs::rename::rename_entity(input, old, new) {
+        Ok(renamed) => Ok(Some(renamed)),
+        Err(Error::UnknownEntity { .. }) => Ok(None),
+        Err(e @ Error::EntityExists { .. }) | Err(e @ Error::InvalidName { .. }) => {
+            Err(other(format!("{}: {}", origin, e)))
+        }
+        Err(e) => Err((exit_code(&e), report(&e, input, origin))),
+    }
+}
+
+/// Reads and parses the er file at `path`.
+fn load(path: &Path) -> Result<ER, (i32, String)> {
+    let input = read_input(Some(path)).map_err(other)?;
+    input.parse().map_err(|e| {
+        (
+            exit_code(&e),
+            report(&e, &input, &path.display().to_string()),
+        )
+    })
+}
+
+/// Lists the changes from `old` to `new` as text or JSON.
+fn list_changes(json: bool, old: &ER, new: &ER) -> Result<String, (i32, String)> {
+    let diff = erd_rs::er::diff(old, new);
+    if json {
+        let mut out = serde_json::to_string_pretty(&diff).map_err(other)?;
+        out.push('\n');
+        return Ok(out);
+    }
+    Ok(diff.to_string())
+}
+
+/// Converts `input`, an er, JSON, YAML, Mermaid or DBML document, to any of
+/// the others.
+fn convert(
+    input: &str,
+    origin: &str,
+    from: DataFormat,
+    to: DataFormat,
+) -> Result<String, (i32, String)> {
+    let er = match from {
+        DataFormat::Erd => input.parse(),
+        DataFormat::Json => {
+            let mut deserializer = serde_json::Deserializer::from_str(input);
+            schema::import(&mut deserializer).and_then(|er| {
+                deserializer.end().map_err(|e| Error::InvalidDocument {
+                    message: e.to_string(),
+                    span: None,
+                })?;
+                Ok(er)
+            })
+        }
+        DataFormat::Yaml => schema::import(serde_yaml::Deserializer::from_str(input)),
+        DataFormat::Mermaid => mermaid::import(input).map(|(er, warnings)| {
+            print_warnings(&warnings, origin);
+            er
+        }),
+        DataFormat::Dbml => dbml::import(input).map(|(er, warnings)| {
+            print_warnings(&warnings, origin);
+            er
+        }),
+    };
+    let er: ER = er.map_err(|e| {
+        let message = match from {
+            DataFormat::Erd => report(&e, input, origin),
+            _ => e.diagnostic(input).render(input, origin),
+        };
+        (exit_code(&e), message)
+    })?;
+    match to {
+        DataFormat::Erd => Ok(er.to_erd()),
+        DataFormat::Json => {
+            let mut out = serde_json::to_string_pretty(&er).map_err(other)?;
+            out.push('\n');
+            Ok(out)
+        }
+        DataFormat::Yaml => serde_yaml::to_string(&er).map_err(other),
+        DataFormat::Mermaid => Ok(er.to_mermaid()),
+        DataFormat::Dbml => Ok(er.to_dbml()),
+    }
+}
+
+/// Report what an import couldn't carry over.
+fn print_warnings(warnings: &[Warning], origin: &str) {
+    for warning in warnings {
+        eprintln!("{}:{}: warning: {}", origin, warning.span, warning);
+    }
+}
+
+/// Report any issues found by validating `er`, failing if any are errors.
+fn validate(er: &ER, origin: &str) -> Result<(), (i32, String)> {
+    let issues = er.validate();
+    for issue in &issues {
+        eprintln!(
+            "{}:{}: {}: {}",
+            origin,
+            issue.span(),
+            issue.severity(),
+            issue
+        );
+    }
+
+    let errors = issues
+        .iter()
+        .filter(|issue| issue.severity() == Severity::Error)
+        .count();
+    if errors > 0 {
+        return Err((
+            EXIT_VALIDATION_ERROR,
+            format!("erd: validation failed with {} error(s)", errors),
+        ));
+    }
+    Ok(())
+}
+
+/// Pick the exit code for a failure to load an er file.
+fn exit_code(err: &Error) -> i32 {
+    match err {
+        Error::Parser { .. } => EXIT_PARSE_ERROR,
+        Error::UnknownFormatOption { .. }
+        | Error::InvalidInt { .. }
+        | Error::InvalidFloat { .. }
+        | Error::QuoteInValue { .. } => EXIT_OPTION_ERROR,
+        Error::InvalidDocument { .. } => EXIT_PARSE_ERROR,
+        Error::UnknownEntity { .. }
+        | Error::EntityExists { .. }
+        | Error::AttributeExists { .. }
+        | Error::InvalidName { .. }
+        | Error::Io(_) => EXIT_OTHER_ERROR,
+    }
+}
+
+fn other<E: ToString>(err: E) -> (i32, String) {
+    (EXIT_OTHER_ERROR, format!("erd: {}", err.to_string()))
+}
+
+fn read_input(path: Option<&Path>) -> io::Result<String> {
+    let mut buf = String::new();
+    match path {
+        Some(path) => File::open(path)?.read_to_string(&mut buf)?,
+        None => io::stdin().read_to_string(&mut buf)?,
+    };
+    Ok(buf)
+}
+
+/// Pipe the dot document through graphviz to produce the requested format.
+fn run_dot(fmt: Format, dot: &str) -> Result<Vec<u8>, (i32, String)> {
+    let mut child = Command::new("dot")
+        .arg(format!("-T{}", fmt.name()))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| {
+            other(format!(
+                "failed to run `dot` (is graphviz installed?): {}",
+                e
+            ))
+        })?;
+
+    child
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(dot.as_bytes())
+        .map_err(other)?;
+
+    let out = child.wait_with_output().map_err(other)?;
+    if !out.status.success() {
+        return Err(other(format!(
+            "`dot` failed: {}",
+            String::from_utf8_lossy(&out.stderr).trim()
+        )));
+    }
+    Ok(out.stdout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(Format::from_path(Path::new("out.svg")), Some(Format::Svg));
+        assert_eq!(Format::from_path(Path::new("out.PDF")), Some(Format::Pdf));
+        assert_eq!(
+            Format::from_path(Path::new("out.mmd")),
+            Some(Format::Mermaid)
+        );
+        assert_eq!(
+            Format::from_path(Path::new("out.puml")),
+            Some(Format::PlantUml)
+        );
+        assert_eq!(Format::from_path(Path::new("out.txt")), None);
+        assert_eq!(Format::from_path(Path::new("out")), None);
+    }
+
+    #[test]
+    fn test_exit_codes() {
+        let err = "[Foo".parse::<ER>().unwrap_err();
+        assert_eq!(exit_code(&err), EXIT_PARSE_ERROR);
+
+        let err = "[Foo]\na { size: \"big\" }".parse::<ER>().unwrap_err();
+        assert_eq!(exit_code(&err), EXIT_OPTION_ERROR);
+
+        let err = "[Foo]\na { border: \"thick\" }".parse::<ER>().unwrap_err();
+        assert_eq!(exit_code(&err), EXIT_OPTION_ERROR);
+    }
+
+    #[test]
+    fn test_report_lists_every_problem() {
+        let input = "[Foo\n[Bar]\n  a { size: \"big\" }\n";
+        let err = input.parse::<ER>().unwrap_err();
+        let report = report(&err, input, "test.erd");
+        assert!(report.contains("test.erd:1:"));
+        assert!(report.contains("test.erd:3:"));
+    }
+
+    #[test]
+    fn test_validate() {
+        let er: ER = "[Foo]\n*id\nFoo 1--1 Bar".parse().unwrap();
+        let (code, msg) = validate(&er, "test.erd").unwrap_err();
+        assert_eq!(code, EXIT_VALIDATION_ERROR);
+        assert!(msg.contains("1 error"));
+
+        let er: ER = "[Foo]\nid\n".parse().unwrap();
+        assert!(validate(&er, "test.erd").is_ok());
+    }
+
+    #[test]
+    fn test_fmt_check() {
+        let dir = std::env::temp_dir().join(format!("erd-fmt-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("test.erd");
+        std::fs::write(&path, "[Foo]\n*id\n").unwrap();
+
+        let (code, msg) = run_fmt(true, std::slice::from_ref(&path)).unwrap_err();
+        assert_eq!(code, EXIT_NOT_FORMATTED);
+        assert!(msg.contains("test.erd is not formatted"));
+
+        run_fmt(false, std::slice::from_ref(&path)).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "[Foo]\n    *id\n");
+        run_fmt(true, &[path]).unwrap();
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_diff() {
+        let dir = std::env::temp_dir().join(format!("erd-diff-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let old = dir.join("old.erd");
+        let new = dir.join("new.erd");
+        std::fs::write(&old, "[Foo]\n*id\n").unwrap();
+        std::fs::write(&new, "[Foo]\n*id\nname\n").unwrap();
+
+        let (old_er, new_er) = (load(&old).unwrap(), load(&new).unwrap());
+        assert_eq!(
+            list_changes(false, &old_er, &new_er).unwrap(),
+            "+ attribute `name` in `Foo`\n"
+        );
+        let json: serde_json::Value =
+            serde_json::from_str(&list_changes(true, &old_er, &new_er).unwrap()).unwrap();
+        assert_eq!(json["changes"][0]["change"], "attribute_added");
+        assert_eq!(list_changes(false, &old_er, &old_er).unwrap(), "");
+
+        std::fs::write(&new, "[Foo\n").unwrap();
+        let (code, _) = load(&new).unwrap_err();
+        assert_eq!(code, EXIT_PARSE_ERROR);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_convert() {
+        let input = "[Foo]\n*id\n[Bar]\nFoo 1--* Bar\n";
+        let to = |fmt| convert(input, "test.erd", DataFormat::Erd, fmt).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&to(DataFormat::Json)).unwrap();
+        assert_eq!(json["entities"][1]["name"], "Bar");
+        assert_eq!(json["relationships"][0]["card2"], "*");
+
+        let yaml = to(DataFormat::Yaml);
+        assert!(yaml.starts_with("version: 1\n"));
+        assert!(yaml.contains("- name: Foo\n"));
+
+        let (code, _) = convert("[Foo", "test.erd", DataFormat::Erd, DataFormat::Json).unwrap_err();
+        assert_eq!(code, EXIT_PARSE_ERROR);
+
+        // And back again.
+        for (fmt, text) in [
+            (DataFormat::Json, to(DataFormat::Json)),
+            (DataFormat::Yaml, yaml),
+        ] {
+            assert_eq!(
+                convert(&text, "test", fmt, DataFormat::Erd).unwrap(),
+                to(DataFormat::Erd)
+            );
+        }
+        let bad = r#"{"version": 1, "entities": [{"name": "Foo", "options": {"colour": "red"}}]}"#;
+        let (code, message) =
+            convert(bad, "test.json", DataFormat::Json, DataFormat::Erd).unwrap_err();
+        assert_eq!(code, EXIT_OPTION_ERROR);
+        assert!(message.contains("colour"), "{}", message);
+        let (code, _) = convert("{", "test.json", DataFormat::Json, DataFormat::Erd).unwrap_err();
+        assert_eq!(code, EXIT_PARSE_ERROR);
+        let (code, message) = convert(
+            r#"{"entities": []} garbage"#,
+            "test.json",
+            DataFormat::Json,
+            DataFormat::Erd,
+        )
+        .unwrap_err();
+        assert_eq!(code, EXIT_PARSE_ERROR);
+        assert!(message.contains("trailing characters"), "{}", message);
+
+        let mermaid = to(DataFormat::Mermaid);
+        assert!(mermaid.contains("Foo ||--o{ Bar"), "{}", mermaid);
+        assert_eq!(
+            convert(&mermaid, "test.mmd", DataFormat::Mermaid, DataFormat::Erd).unwrap(),
+            to(DataFormat::Erd)
+        );
+        let (code, message) =
+            convert("graph", "test.mmd", DataFormat::Mermaid, DataFormat::Erd).unwrap_err();
+        assert_eq!(code, EXIT_PARSE_ERROR);
+        assert_eq!(
+            message,
+            "error[E0018]: expected `erDiagram`\n --> test.mmd:1:1\n  |\n1 | graph\n  | ^^^^^\n"
+        );
+
+        let dbml = to(DataFormat::Dbml);
+        assert!(dbml.contains("// Foo 1--* Bar has no columns"), "{}", dbml);
+        assert_eq!("dbml".parse(), Ok(DataFormat::Dbml));
+        let (code, message) = convert(
+            "Ref: a.id > b.id",
+            "test.dbml",
+            DataFormat::Dbml,
+            DataFormat::Erd,
+        )
+        .unwrap_err();
+        assert_eq!(code, EXIT_PARSE_ERROR);
+        assert!(message.contains("no table"), "{}", message);
+    }
+
+    #[test]
+    fn test_rename() {
+        let dir = std::env::temp_dir().join(format!("erd-rename-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let a = dir.join("a.erd");
+        let b = dir.join("b.erd");
+        std::fs::write(&a, "[Foo] # the foo\n[Bar]\nFoo 1--* Bar\n").unwrap();
+        std::fs::write(&b, "[Baz]\n").unwrap();
+        let files = [a.clone(), b.clone()];
+
+        run_rename("Foo", "Big Foo", &files).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&a).unwrap(),
+            "[`Big Foo`] # the foo\n[Bar]\n`Big Foo` 1--* Bar\n"
+        );
+        assert_eq!(std::fs::read_to_string(&b).unwrap(), "[Baz]\n");
+
+        let (code, msg) = run_rename("Foo", "Qux", &files).unwrap_err();
+        assert_eq!(code, EXIT_OTHER_ERROR);
+        assert!(msg.contains("Foo"));
+
+        let (_, msg) = run_rename("Bar", "Big Foo", &files).unwrap_err();
+        assert!(msg.contains("a.erd"));
+
+        // The first file would be fine, but nothing's written as the second
+        // already has a `Baz`.
+        std::fs::write(&b, "[Bar]\n[Baz]\n").unwrap();
+        let before = std::fs::read_to_string(&a).unwrap();
+        let (_, msg) = run_rename("Bar", "Baz", &files).unwrap_err();
+        assert!(msg.contains("b.erd"), "{}", msg);
+        assert_eq!(std::fs::read_to_string(&a).unwrap(), before);
+        assert_eq!(std::fs::read_to_string(&b).unwrap(), "[Bar]\n[Baz]\n");
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
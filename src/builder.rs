@@ -0,0 +1,398 @@
+//! Building an [ER](../er/struct.ER.html) in code rather than parsing one.
+//!
+//! ```
+//! use erd_rs::er::{Cardinality, Opt, ER};
+//!
+//! let er = ER::builder()
+//!     .title("People")
+//!     .entity("Person", |e| {
+//!         e.pk("name")
+//!             .attr("height")
+//!             .fk("birth_place_id")
+//!             .option(Opt::BgColor("#ececfc".to_string()))
+//!     })
+//!     .entity("Birth Place", |e| e.pk("id").attr("birth city"))
+//!     .relation("Person", Cardinality::ZeroPlus, Cardinality::One, "Birth Place")
+//!     .build()
+//!     .unwrap();
+//! assert_eq!(er, er.to_erd().parse().unwrap());
+//! ```
+//!
+//! Nothing is checked until [build](struct.ErBuilder.html#method.build), which
+//! fails on anything an er file couldn't express or which
+//! [ER::validate](../er/struct.ER.html#method.validate) would call an error.
+//! The model it gives is the same as parsing the er file it's written out as.
+
+use crate::er::{
+    font_size, Attribute, Cardinality, Directive, Entity, GlobalOptions, Opt, Options, Relation, ER,
+};
+use crate::rename::check_name;
+use crate::{Error, Result};
+use std::collections::HashSet;
+
+impl ER {
+    /// Starts building a diagram in code.
+    pub fn builder() -> ErBuilder {
+        ErBuilder::default()
+    }
+}
+
+/// Builds an [ER](../er/struct.ER.html), see the [module](index.html) docs.
+#[derive(Clone, Debug, Default)]
+pub struct ErBuilder {
+    directives: Vec<(Directive, Options)>,
+    entities: Vec<Entity>,
+    rels: Vec<Relation>,
+}
+
+impl ErBuilder {
+    /// Sets the title of the diagram.
+    pub fn title(self, label: impl Into<String>) -> Self {
+        self.directive(Directive::Title, Opt::Label(label.into()))
+    }
+
+    /// Sets an option for a directive, as if written in the head of the
+    /// document.
+    pub fn directive(mut self, directive: Directive, opt: Opt) -> Self {
+        self.directives
+            .push((directive, std::iter::once(opt).collect()));
+        self
+    }
+
+    /// Adds an entity named `name`, with attributes and options given by `f`.
+    pub fn entity<F>(mut self, name: impl Into<String>, f: F) -> Self
+    where
+        F: FnOnce(EntityBuilder) -> EntityBuilder,
+    {
+        self.entities
+            .push(f(EntityBuilder::new(name.into())).entity);
+        self
+    }
+
+    /// Adds a relationship between the entities named `entity1` and
+    /// `entity2`, with the cardinality `card1` at `entity1` and `card2` at
+    /// `entity2`.
+    pub fn relation(
+        self,
+        entity1: impl Into<String>,
+        card1: Cardinality,
+        card2: Cardinality,
+        entity2: impl Into<String>,
+    ) -> Self {
+        self.relation_with(entity1, card1, card2, entity2, |r| r)
+    }
+
+    /// Adds a relationship like [relation](#method.relation), with options
+    /// given by `f`.
+    pub fn relation_with<F>(
+        mut self,
+        entity1: impl Into<String>,
+        card1: Cardinality,
+        card2: Cardinality,
+        entity2: impl Into<String>,
+        f: F,
+    ) -> Self
+    where
+        F: FnOnce(RelationBuilder) -> RelationBuilder,
+    {
+        let rel = Relation {
+            entity1: entity1.into(),
+            entity2: entity2.into(),
+            card1,
+            card2,
+            options: Options::default(),
+            doc: None,
+            span: Default::default(),
+            entity1_span: Default::default(),
+            entity2_span: Default::default(),
+        };
+        self.rels.push(f(RelationBuilder { rel }).rel);
+        self
+    }
+
+    /// Checks everything added and gives the diagram.
+    ///
+    /// Fails when a name isn't a valid identifier, an option value contains
+    /// a double quote, a font size isn't a finite number of at least zero, an
+    /// entity or attribute name is used twice, or a relationship refers to an
+    /// entity which wasn't added.
+    pub fn build(self) -> Result<ER> {
+        for (_, options) in &self.directives {
+            check_options(options)?;
+        }
+
+        let mut names = HashSet::new();
+        for entity in &self.entities {
+            check_name(&entity.name)?;
+            check_options(&entity.hoptions)?;
+            if !names.insert(entity.name.as_str()) {
+                return Err(Error::EntityExists {
+                    name: entity.name.clone(),
+                });
+            }
+
+            let mut fields = HashSet::new();
+            for attr in &entity.attribs {
+                check_name(&attr.field)?;
+                check_options(&attr.options)?;
+                if !fields.insert(attr.field.as_str()) {
+                    return Err(Error::AttributeExists {
+                        entity: entity.name.clone(),
+                        name: attr.field.clone(),
+                    });
+                }
+            }
+        }
+
+        for rel in &self.rels {
+            for name in [&rel.entity1, &rel.entity2] {
+                check_name(name)?;
+                if !names.contains(name.as_str()) {
+                    return Err(Error::UnknownEntity { name: name.clone() });
+                }
+            }
+            check_options(&rel.options)?;
+        }
+
+        Ok(ER {
+            entities: self.entities,
+            rels: self.rels,
+            globals: GlobalOptions::from_directives(self.directives),
+        })
+    }
+}
+
+/// Builds an entity for [ErBuilder::entity](struct.ErBuilder.html#method.entity).
+#[derive(Clone, Debug)]
+pub struct EntityBuilder {
+    entity: Entity,
+}
+
+impl EntityBuilder {
+    fn new(name: String) -> Self {
+        EntityBuilder {
+            entity: Entity {
+                name,
+                attribs: vec![],
+                hoptions: Options::default(),
+                eoptions: Options::default(),
+                doc: None,
+                span: Default::default(),
+                name_span: Default::default(),
+            },
+        }
+    }
+
+    /// Adds a primary key attribute.
+    pub fn pk(self, name: impl Into<String>) -> Self {
+        self.attribute(name, AttributeBuilder::pk)
+    }
+
+    /// Adds a foreign key attribute.
+    pub fn fk(self, name: impl Into<String>) -> Self {
+        self.attribute(name, AttributeBuilder::fk)
+    }
+
+    /// Adds an attribute which isn't a key.
+    pub fn attr(self, name: impl Into<String>) -> Self {
+        self.attribute(name, |a| a)
+    }
+
+    /// Adds an attribute, with keys and options given by `f`.
+    pub fn attribute<F>(mut self, name: impl Into<String>, f: F) -> Self
+    where
+        F: FnOnce(AttributeBuilder) -> AttributeBuilder,
+    {
+        let attr = Attribute {
+            field: name.into(),
+            pk: false,
+            fk: false,
+            options: Options::default(),
+            doc: None,
+            span: Default::default(),
+        };
+        self.entity.attribs.push(f(AttributeBuilder { attr }).attr);
+        self
+    }
+
+    /// Sets an option, as if written after the entity's header.
+    pub fn option(mut self, opt: Opt) -> Self {
+        // The parser gives header options to the body as well.
+        self.entity.hoptions.set(opt.clone());
+        self.entity.eoptions.set(opt);
+        self
+    }
+
+    /// Sets the documentation, as if written in `##` comments before the
+    /// entity's header.
+    pub fn doc(mut self, doc: impl Into<String>) -> Self {
+        self.entity.doc = Some(doc.into());
+        self
+    }
+}
+
+/// Builds an attribute for
+/// [EntityBuilder::attribute](struct.EntityBuilder.html#method.attribute).
+#[derive(Clone, Debug)]
+pub struct AttributeBuilder {
+    attr: Attribute,
+}
+
+impl AttributeBuilder {
+    /// Makes the attribute part of the primary key.
+    pub fn pk(mut self) -> Self {
+        self.attr.pk = true;
+        self
+    }
+
+    /// Makes the attribute a foreign key.
+    pub fn fk(mut self) -> Self {
+        self.attr.fk = true;
+        self
+    }
+
+    /// Sets an option for the attribute.
+    pub fn option(mut self, opt: Opt) -> Self {
+        self.attr.options.set(opt);
+        self
+    }
+
+    /// Sets the documentation, as if written in `##` comments before the
+    /// attribute.
+    pub fn doc(mut self, doc: impl Into<String>) -> Self {
+        self.attr.doc = Some(doc.into());
+        self
+    }
+}
+
+/// Builds a relationship for
+/// [ErBuilder::relation_with](struct.ErBuilder.html#method.relation_with).
+#[derive(Clone, Debug)]
+pub struct RelationBuilder {
+    rel: Relation,
+}
+
+impl RelationBuilder {
+    /// Sets an option for the relationship.
+    pub fn option(mut self, opt: Opt) -> Self {
+        self.rel.options.set(opt);
+        self
+    }
+
+    /// Sets the documentation, as if written in `##` comments before the
+    /// relationship.
+    pub fn doc(mut self, doc: impl Into<String>) -> Self {
+        self.rel.doc = Some(doc.into());
+        self
+    }
+}
+
+/// There's no way to write a double quote in an option value, and the parser
+/// would reject font sizes graphviz can't use.
+fn check_options(options: &Options) -> Result<()> {
+    if let Some(&size) = options.font_size() {
+        font_size(size)?;
+    }
+    match options.iter().find(|opt| opt.value().contains('"')) {
+        Some(opt) => Err(Error::QuoteInValue {
+            name: opt.html_attr_name().to_string(),
+        }),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::er::{Cardinality, Directive, Opt, ER};
+    use crate::parser::parse;
+    use crate::Error;
+
+    #[test]
+    fn test_build_matches_parse() {
+        let built = ER::builder()
+            .title("People")
+            .directive(Directive::Header, Opt::FontSize(18.0))
+            .entity("Person", |e| {
+                e.doc("Someone.")
+                    .pk("name")
+                    .attr("height")
+                    .attribute("birth place id", |a| {
+                        a.fk().option(Opt::Color("blue".to_string()))
+                    })
+                    .option(Opt::BgColor("#ececfc".to_string()))
+            })
+            .entity("Birth Place", |e| e.pk("id"))
+            .relation_with(
+                "Person",
+                Cardinality::ZeroPlus,
+                Cardinality::One,
+                "Birth Place",
+                |r| r.option(Opt::Label("born in".to_string())),
+            )
+            .build()
+            .unwrap();
+
+        let parsed = parse(
+            r##"title { label: "People" }
+header { size: "18" }
+
+## Someone.
+[Person] { bgcolor: "#ececfc" }
+*name
+height
++`birth place id` { color: "blue" }
+
+[`Birth Place`]
+*id
+
+Person *--1 `Birth Place` { label: "born in" }
+"##,
+        )
+        .unwrap();
+        assert_eq!(built, parsed);
+        assert_eq!(built.to_erd(), parsed.to_erd());
+        assert!(built.validate().is_empty());
+    }
+
+    #[test]
+    fn test_build_errors() {
+        let err = |builder: crate::builder::ErBuilder| builder.build().unwrap_err();
+        assert!(matches!(
+            err(ER::builder().entity("A-B", |e| e)),
+            Error::InvalidName { .. }
+        ));
+        assert!(matches!(
+            err(ER::builder().entity("A", |e| e.attr(""))),
+            Error::InvalidName { .. }
+        ));
+        assert!(matches!(
+            err(ER::builder().entity("A", |e| e).entity("A", |e| e)),
+            Error::EntityExists { .. }
+        ));
+        assert!(matches!(
+            err(ER::builder().entity("A", |e| e.pk("id").fk("id"))),
+            Error::AttributeExists { .. }
+        ));
+        assert!(matches!(
+            err(ER::builder().entity("A", |e| e).relation(
+                "A",
+                Cardinality::One,
+                Cardinality::One,
+                "B"
+            )),
+            Error::UnknownEntity { .. }
+        ));
+        assert!(matches!(
+            err(ER::builder().title("say \"hi\"")),
+            Error::QuoteInValue { .. }
+        ));
+        assert!(matches!(
+            err(ER::builder().entity("A", |e| e.option(Opt::FontSize(f64::NAN)))),
+            Error::InvalidFloat { .. }
+        ));
+        assert!(matches!(
+            err(ER::builder().directive(Directive::Title, Opt::FontSize(-1.0))),
+            Error::InvalidFloat { .. }
+        ));
+    }
+}
@@ -0,0 +1,565 @@
+//! A lossless concrete syntax tree for er files.
+//!
+//! The parser throws away everything which doesn't affect the meaning of a
+//! document: comments, blank lines, how identifiers were quoted and so on.
+//! Tools which rewrite er files need all of that, so a [Cst](struct.Cst.html)
+//! keeps every byte of the input in its tokens. Writing the tree back out with
+//! `to_string()` reproduces the input exactly.
+//!
+//! The tree is shallow. The document holds directives, entities and
+//! relationships, entities hold their header and attributes, and each item
+//! holds its tokens (with any option block gathered into a node of its own).
+//! Whitespace, newlines and comments are tokens like any other, sitting
+//! wherever they were found. Trivia which trails an item (the rest of the line
+//! after it, say) belongs to the item's parent rather than the item.
+
+use crate::er::{directive_by_name, Attribute, Directive, Entity, Relation, ER};
+use crate::parser::{parse_pairs, Rule};
+use crate::span::{LineIndex, Span};
+use crate::Result;
+use pest::iterators::Pair;
+use std::fmt::{Display, Formatter};
+
+/// What a [Node](struct.Node.html) is.
+///
+/// Items carry their position in the [ER](../er/struct.ER.html) parsed from
+/// the same input, see [Node::entity](struct.Node.html#method.entity) and
+/// friends.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum NodeKind {
+    Document,
+    Directive(Directive),
+    /// An entity's header and attributes, holding the `index`th entity.
+    Entity {
+        index: usize,
+    },
+    /// The `index`th attribute of the `entity`th entity.
+    Attribute {
+        entity: usize,
+        index: usize,
+    },
+    /// The `index`th relationship.
+    Relation {
+        index: usize,
+    },
+    /// Everything from `{` to `}`.
+    OptionBlock,
+    /// A single `name: "value"` option.
+    Option,
+
+    // Tokens, which have text rather than children.
+    /// The name of a directive (`title`, `header`, ...).
+    DirectiveName,
+    /// `*` or `+` before an attribute.
+    Key,
+    /// An identifier, including any quotes around it.
+    Ident,
+    /// One end of a relationship (`1`, `?`, `*` or `+`).
+    Cardinality,
+    OptionName,
+    /// An option's value, including the quotes around it.
+    OptionValue,
+    /// Brackets, braces, commas, colons and the `--` of a relationship.
+    Punct,
+    /// A run of whitespace not including newlines.
+    Whitespace,
+    Newline,
+    /// A comment, from the `#` up to (not including) the end of the line.
+    Comment,
+}
+
+impl NodeKind {
+    /// Whether this is whitespace, a newline or a comment.
+    pub fn is_trivia(self) -> bool {
+        matches!(
+            self,
+            NodeKind::Whitespace | NodeKind::Newline | NodeKind::Comment
+        )
+    }
+}
+
+/// A node in a [Cst](struct.Cst.html): either a token holding some text, or
+/// a node holding other nodes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Node {
+    kind: NodeKind,
+    span: Span,
+    /// The text of a token, which is `None` for other nodes.
+    text: Option<String>,
+    children: Vec<Node>,
+}
+
+impl Node {
+    fn token(kind: NodeKind, text: &str, span: Span) -> Self {
+        Node {
+            kind,
+            span,
+            text: Some(text.to_string()),
+            children: vec![],
+        }
+    }
+
+    fn parent(kind: NodeKind, children: Vec<Node>) -> Self {
+        let start = children.first().expect("children").span;
+        let end = children.last().expect("children").span;
+        Node {
+            kind,
+            span: Span {
+                end: end.end,
+                ..start
+            },
+            text: None,
+            children,
+        }
+    }
+
+    pub fn kind(&self) -> NodeKind {
+        self.kind
+    }
+
+    /// Where the node was found in the input.
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    /// The text of a token, or `None` for nodes which have children instead.
+    pub fn text(&self) -> Option<&str> {
+        self.text.as_deref()
+    }
+
+    pub fn children(&self) -> &[Node] {
+        &self.children
+    }
+
+    pub(crate) fn children_mut(&mut self) -> &mut [Node] {
+        &mut self.children
+    }
+
+    /// Replaces the text of a token.
+    ///
+    /// The span is left pointing at the original text.
+    pub(crate) fn set_text(&mut self, text: String) {
+        debug_assert!(self.text.is_some(), "only tokens have text");
+        self.text = Some(text);
+    }
+
+    /// This node and everything under it, depth first and in document order.
+    pub fn descendants(&self) -> Descendants<'_> {
+        Descendants { stack: vec![self] }
+    }
+
+    /// The entity this node stands for, when it's an entity node.
+    pub fn entity<'e>(&self, er: &'e ER) -> Option<&'e Entity> {
+        match self.kind {
+            NodeKind::Entity { index } => er.entities.get(index),
+            _ => None,
+        }
+    }
+
+    /// The attribute this node stands for, when it's an attribute node.
+    pub fn attribute<'e>(&self, er: &'e ER) -> Option<&'e Attribute> {
+        match self.kind {
+            NodeKind::Attribute { entity, index } => er.entities.get(entity)?.attribs.get(index),
+            _ => None,
+        }
+    }
+
+    /// The relationship this node stands for, when it's a relationship node.
+    pub fn relation<'e>(&self, er: &'e ER) -> Option<&'e Relation> {
+        match self.kind {
+            NodeKind::Relation { index } => er.rels.get(index),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Node {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.text {
+            Some(text) => write!(f, "{}", text),
+            None => self.children.iter().try_for_each(|child| child.fmt(f)),
+        }
+    }
+}
+
+/// Iterator returned by [Node::descendants](struct.Node.html#method.descendants).
+pub struct Descendants<'n> {
+    stack: Vec<&'n Node>,
+}
+
+impl<'n> Iterator for Descendants<'n> {
+    type Item = &'n Node;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+        self.stack.extend(node.children.iter().rev());
+        Some(node)
+    }
+}
+
+/// A lossless syntax tree for an er file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cst {
+    root: Node,
+}
+
+impl Cst {
+    /// Builds the syntax tree for `input`, which must be a valid er file.
+    pub fn parse(input: &str) -> Result<Self> {
+        let document = parse_pairs(input)?.next().expect("document");
+        let mut builder = Builder {
+            input,
+            lines: LineIndex::new(input),
+            pos: 0,
+            entities: 0,
+            rels: 0,
+        };
+
+        let mut children = vec![];
+        for pair in document.into_inner() {
+            match pair.as_rule() {
+                Rule::head => {
+                    for pair in pair.into_inner() {
+                        builder.item(pair, None, &mut children);
+                    }
+                }
+                Rule::body => {
+                    for pair in pair.into_inner() {
+                        match pair.as_rule() {
+                            Rule::entity => builder.entity(pair, &mut children),
+                            Rule::rel => builder.item(pair, None, &mut children),
+                            _ => unreachable!(),
+                        }
+                    }
+                }
+                Rule::EOI => (),
+                _ => unreachable!(),
+            }
+        }
+        builder.trivia_to(input.len(), &mut children);
+
+        let root = Node {
+            kind: NodeKind::Document,
+            span: builder.lines.span(0, input.len()),
+            text: None,
+            children,
+        };
+        Ok(Cst { root })
+    }
+
+    pub fn root(&self) -> &Node {
+        &self.root
+    }
+
+    pub(crate) fn root_mut(&mut self) -> &mut Node {
+        &mut self.root
+    }
+}
+
+impl Display for Cst {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.root.fmt(f)
+    }
+}
+
+struct Builder<'i> {
+    input: &'i str,
+    lines: LineIndex<'i>,
+    /// Everything before this offset has been turned into tokens.
+    pos: usize,
+    /// How many entities and relationships have been seen so far.
+    entities: usize,
+    rels: usize,
+}
+
+impl<'i> Builder<'i> {
+    fn entity(&mut self, pair: Pair<'i, Rule>, out: &mut Vec<Node>) {
+        let index = self.entities;
+        self.entities += 1;
+
+        let mut children = vec![];
+        for pair in pair.into_inner() {
+            match pair.as_rule() {
+                Rule::entity_name => self.item(pair, Some(index), &mut children),
+                Rule::attribs => {
+                    for pair in pair.into_inner() {
+                        self.item(pair, Some(index), &mut children);
+                    }
+                }
+                _ => unreachable!(),
+            }
+        }
+
+        let trailing = split_trailing_trivia(&mut children);
+        out.push(Node::parent(NodeKind::Entity { index }, children));
+        out.extend(trailing);
+    }
+
+    /// Adds the node for a directive, entity header, attribute or
+    /// relationship to `out`, along with any trivia before and after it.
+    ///
+    /// An entity's header is added as plain tokens since the entity node
+    /// itself is built by the caller.
+    fn item(&mut self, pair: Pair<'i, Rule>, entity: Option<usize>, out: &mut Vec<Node>) {
+        let span = pair.as_span();
+        self.trivia_to(span.start(), out);
+
+        let kind = match pair.as_rule() {
+            Rule::directive => {
+                let name = pair.clone().into_inner().next().expect("dir_type");
+                NodeKind::Directive(directive_by_name(name.as_str()).expect("valid directive"))
+            }
+            Rule::entity_name => NodeKind::Entity {
+                index: entity.expect("entity"),
+            },
+            Rule::attr => {
+                let entity = entity.expect("entity");
+                let index = out
+                    .iter()
+                    .filter(|n| matches!(n.kind, NodeKind::Attribute { .. }))
+                    .count();
+                NodeKind::Attribute { entity, index }
+            }
+            Rule::rel => {
+                self.rels += 1;
+                NodeKind::Relation {
+                    index: self.rels - 1,
+                }
+            }
+            _ => unreachable!(),
+        };
+
+        let mut tokens = vec![];
+        self.tokens(pair, &mut tokens);
+        self.trivia_to(span.end(), &mut tokens);
+        let trailing = split_trailing_trivia(&mut tokens);
+        let tokens = group_option_block(tokens);
+
+        match kind {
+            NodeKind::Entity { .. } => out.extend(tokens),
+            _ => out.push(Node::parent(kind, tokens)),
+        }
+        out.extend(trailing);
+    }
+
+    /// Turns the pairs under `pair` into tokens, filling in the gaps between
+    /// them.
+    fn tokens(&mut self, pair: Pair<'i, Rule>, out: &mut Vec<Node>) {
+        for pair in pair.into_inner() {
+            let kind = match pair.as_rule() {
+                Rule::dir_type => NodeKind::DirectiveName,
+                Rule::keys => {
+                    self.tokens(pair, out);
+                    continue;
+                }
+                Rule::ispk | Rule::isfk => NodeKind::Key,
+                Rule::ident => NodeKind::Ident,
+                Rule::entity1 | Rule::entity2 | Rule::opt_list => {
+                    self.tokens(pair, out);
+                    continue;
+                }
+                Rule::card1 | Rule::card2 => NodeKind::Cardinality,
+                Rule::option => {
+                    self.trivia_to(pair.as_span().start(), out);
+                    let mut tokens = vec![];
+                    self.tokens(pair.clone(), &mut tokens);
+                    self.trivia_to(pair.as_span().end(), &mut tokens);
+                    out.push(Node::parent(NodeKind::Option, tokens));
+                    continue;
+                }
+                Rule::opt_name => NodeKind::OptionName,
+                Rule::opt_value => NodeKind::OptionValue,
+                _ => unreachable!(),
+            };
+            let span = pair.as_span();
+            self.trivia_to(span.start(), out);
+            out.push(Node::token(
+                kind,
+                span.as_str(),
+                self.lines.span(span.start(), span.end()),
+            ));
+            self.pos = span.end();
+        }
+    }
+
+    /// Adds tokens for everything from where we're up to until `end`, which
+    /// is expected to be only trivia and punctuation.
+    fn trivia_to(&mut self, end: usize, out: &mut Vec<Node>) {
+        while self.pos < end {
+            let rest = &self.input[self.pos..end];
+            let c = rest.chars().next().expect("char");
+            let (kind, len) = match c {
+                '#' => (
+                    NodeKind::Comment,
+                    rest.find(['\r', '\n']).unwrap_or(rest.len()),
+                ),
+                '\n' => (NodeKind::Newline, 1),
+                '\r' if rest.starts_with("\r\n") => (NodeKind::Newline, 2),
+                _ if c.is_whitespace() => {
+                    let len = rest
+                        .find(|c: char| !c.is_whitespace() || c == '\n' || c == '\r')
+                        .unwrap_or(rest.len());
+                    (NodeKind::Whitespace, len.max(c.len_utf8()))
+                }
+                '-' if rest.starts_with("--") => (NodeKind::Punct, 2),
+                _ => (NodeKind::Punct, c.len_utf8()),
+            };
+            let span = self.lines.span(self.pos, self.pos + len);
+            out.push(Node::token(kind, &rest[..len], span));
+            self.pos += len;
+        }
+    }
+}
+
+/// Removes the trivia from the end of `nodes`, returning it.
+fn split_trailing_trivia(nodes: &mut Vec<Node>) -> Vec<Node> {
+    let keep = nodes
+        .iter()
+        .rposition(|n| !n.kind.is_trivia())
+        .map_or(0, |i| i + 1);
+    nodes.split_off(keep)
+}
+
+/// Gathers the tokens from `{` to `}` into an option block node.
+fn group_option_block(mut tokens: Vec<Node>) -> Vec<Node> {
+    let is_punct = |n: &Node, p: &str| n.kind == NodeKind::Punct && n.text() == Some(p);
+    let open = tokens.iter().position(|n| is_punct(n, "{"));
+    let close = tokens.iter().rposition(|n| is_punct(n, "}"));
+    if let (Some(open), Some(close)) = (open, close) {
+        let rest = tokens.split_off(close + 1);
+        let block = tokens.split_off(open);
+        tokens.push(Node::parent(NodeKind::OptionBlock, block));
+        tokens.extend(rest);
+    }
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Cst, NodeKind};
+    use crate::er::Directive;
+    use crate::parser::parse;
+
+    /// Every token's text, in order.
+    fn tokens(cst: &Cst) -> Vec<(NodeKind, &str)> {
+        cst.root()
+            .descendants()
+            .filter_map(|n| Some((n.kind(), n.text()?)))
+            .collect()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let inputs = [
+            include_str!("../samples/basics.erd"),
+            "",
+            "\n\n  # just a comment",
+            "title {label: \"x\"}# no space\r\n[Foo]\r\n  *+'a b' {\n  label: \"y\", # why\n  color: \"#fff\",\n}\n\t`Foo`  ?--+\t\"Foo\"",
+        ];
+        for input in inputs.iter() {
+            let cst = Cst::parse(input).unwrap();
+            assert_eq!(&cst.to_string(), input);
+            let spans_match = cst
+                .root()
+                .descendants()
+                .filter(|n| n.text().is_some())
+                .all(|n| n.span().as_str(input) == n.text().unwrap());
+            assert!(spans_match);
+        }
+    }
+
+    #[test]
+    fn test_structure() {
+        let input = "title { label: \"x\" } # t\n[Foo]\n*`a b` # c\nFoo 1--* Foo\n";
+        let cst = Cst::parse(input).unwrap();
+        let kinds: Vec<_> = cst.root().children().iter().map(|n| n.kind()).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                NodeKind::Directive(Directive::Title),
+                NodeKind::Whitespace,
+                NodeKind::Comment,
+                NodeKind::Newline,
+                NodeKind::Entity { index: 0 },
+                // Trailing trivia belongs to the parent.
+                NodeKind::Whitespace,
+                NodeKind::Comment,
+                NodeKind::Newline,
+                NodeKind::Relation { index: 0 },
+                NodeKind::Newline,
+            ]
+        );
+
+        let entity = &cst.root().children()[4];
+        assert_eq!(entity.to_string(), "[Foo]\n*`a b`");
+        let attr = &entity.children()[4];
+        assert_eq!(
+            attr.kind(),
+            NodeKind::Attribute {
+                entity: 0,
+                index: 0
+            }
+        );
+        assert_eq!(attr.to_string(), "*`a b`");
+
+        let toks = tokens(&cst);
+        assert!(toks.contains(&(NodeKind::Ident, "`a b`")));
+        assert!(toks.contains(&(NodeKind::OptionValue, "\"x\"")));
+        assert!(toks.contains(&(NodeKind::Punct, "--")));
+        assert!(toks.contains(&(NodeKind::Comment, "# c")));
+    }
+
+    #[test]
+    fn test_option_blocks() {
+        let input = "[Foo] { label: \"a\", # i like bright blue\n  color: \"blue\" }\n";
+        let cst = Cst::parse(input).unwrap();
+        let block = cst
+            .root()
+            .descendants()
+            .find(|n| n.kind() == NodeKind::OptionBlock)
+            .unwrap();
+        assert_eq!(block.span().as_str(input), &input[6..input.len() - 1]);
+
+        let options: Vec<_> = block
+            .children()
+            .iter()
+            .filter(|n| n.kind() == NodeKind::Option)
+            .map(|n| n.to_string())
+            .collect();
+        assert_eq!(options, vec!["label: \"a\"", "color: \"blue\""]);
+        assert!(block
+            .children()
+            .iter()
+            .any(|n| n.text() == Some("# i like bright blue")));
+    }
+
+    #[test]
+    fn test_nodes_map_to_model() {
+        let input = include_str!("../samples/basics.erd");
+        let er = parse(input).unwrap();
+        let cst = Cst::parse(input).unwrap();
+
+        let entities: Vec<_> = cst
+            .root()
+            .descendants()
+            .filter_map(|n| n.entity(&er))
+            .map(|e| e.name.as_str())
+            .collect();
+        assert_eq!(entities, vec!["Person", "Birth Place"]);
+
+        let attrs: Vec<_> = cst
+            .root()
+            .descendants()
+            .filter_map(|n| Some((n.span(), n.attribute(&er)?)))
+            .collect();
+        assert_eq!(attrs.len(), 9);
+        assert!(attrs.iter().all(|(span, attr)| *span == attr.span()));
+
+        let rel = cst
+            .root()
+            .descendants()
+            .find_map(|n| n.relation(&er))
+            .unwrap();
+        assert_eq!(rel.entity2, "Birth Place");
+    }
+}
@@ -0,0 +1,1361 @@
+//! Converts between an [ER](../er/struct.ER.html) and DBML, the language used
+//! by dbdiagram.io.
+//!
+//! Entities become tables and attributes become columns. Er files don't have
+//! column types, so an attribute's label is used as its type when it looks
+//! like one, and [ATTRIBUTE_TYPE](../mermaid/constant.ATTRIBUTE_TYPE.html)
+//! otherwise. Primary keys are marked `[pk]` (or listed in `indexes` when
+//! there's more than one), docs become notes and a header's background color
+//! becomes the table's `headercolor` when it's a hex color.
+//!
+//! DBML relates columns rather than tables, so each relationship is written
+//! as a `Ref` from the foreign key [matched](../fk/index.html) to it, or the
+//! primary key when there isn't one, to the primary key at the other end. Only
+//! whether each end is one or many carries over (`<`, `>`, `-` or `<>`), and
+//! a relationship's label becomes the name of its `Ref`. Relationships without
+//! columns to refer to are written as comments.
+//!
+//! [import](fn.import.html) goes the other way, marking the column on the
+//! "many" side of each `Ref` (or the one which isn't a primary key, for
+//! one-to-one refs) as a foreign key. Ends which are "one" become `1` and
+//! those which are "many" become `*`. Er files can't group entities, so the
+//! tables in a `TableGroup` share a background color instead: the group's
+//! `color`, or one picked from [GROUP_COLORS](constant.GROUP_COLORS.html)
+//! when it hasn't got one. A table's own `headercolor` wins over its group's.
+//! Anything else without an equivalent in an er file (enums, indexes other
+//! than primary keys, most column settings) is left out with a
+//! [Warning](../import/struct.Warning.html).
+
+use crate::er::{
+    card_char, Attribute, Cardinality, Directive, Entity, GlobalOptions, Opt, Options, Relation, ER,
+};
+use crate::fmt::needs_quotes;
+use crate::import::{checked_names, invalid, Warning};
+use crate::mermaid::ATTRIBUTE_TYPE;
+use crate::span::{LineIndex, Span};
+use crate::{Error, Result};
+use std::io::Write;
+
+/// The colors given to the tables of each `TableGroup` without a `color`,
+/// in turn.
+pub const GROUP_COLORS: [&str; 6] = [
+    "#d6eaf8", "#d5f5e3", "#fcf3cf", "#fadbd8", "#e8daef", "#fae5d3",
+];
+
+impl ER {
+    /// Writes the diagram as DBML.
+    ///
+    /// See [render](../dbml/fn.render.html).
+    pub fn to_dbml(&self) -> String {
+        let mut buf = vec![];
+        render(self, &mut buf).expect("writing to a Vec can't fail");
+        String::from_utf8(buf).expect("dbml output is always utf-8")
+    }
+}
+
+/// Writes the DBML representation of `er` to `out`.
+pub fn render<W: Write>(er: &ER, mut out: W) -> Result<()> {
+    let mut blocks = vec![];
+    if let Some(title) = er.effective_title_options().label() {
+        blocks.push(format!("Project {} {{\n}}\n", quote(title)));
+    }
+    for entity in &er.entities {
+        blocks.push(table(er, entity));
+    }
+    if !er.rels.is_empty() {
+        blocks.push(refs(er));
+    }
+    write!(out, "{}", blocks.join("\n"))?;
+    Ok(())
+}
+
+fn table(er: &ER, entity: &Entity) -> String {
+    let mut buf = format!("Table {}", quote(&entity.name));
+    let color = er.effective_header_options(entity).bg_color().cloned();
+    if let Some(color) = color.filter(|c| c.starts_with('#')) {
+        buf.push_str(&format!(" [headercolor: {}]", color));
+    }
+    buf.push_str(" {\n");
+    if let Some(doc) = entity.doc() {
+        buf.push_str(&format!("    Note: {}\n", string(doc)));
+    }
+
+    let pks: Vec<_> = entity.attribs.iter().filter(|a| a.pk).collect();
+    for attr in &entity.attribs {
+        let mut settings = vec![];
+        if attr.pk && pks.len() == 1 {
+            settings.push("pk".to_string());
+        }
+        if let Some(doc) = attr.doc() {
+            settings.push(format!("note: {}", string(doc)));
+        }
+        buf.push_str(&format!("    {} {}", quote(&attr.field), column_type(attr)));
+        if !settings.is_empty() {
+            buf.push_str(&format!(" [{}]", settings.join(", ")));
+        }
+        buf.push('\n');
+    }
+    if pks.len() > 1 {
+        let names: Vec<_> = pks.iter().map(|a| quote(&a.field)).collect();
+        buf.push_str("\n    indexes {\n");
+        buf.push_str(&format!("        ({}) [pk]\n", names.join(", ")));
+        buf.push_str("    }\n");
+    }
+    buf.push_str("}\n");
+    buf
+}
+
+/// The `Ref`s for every relationship in `er`.
+fn refs(er: &ER) -> String {
+    let fks = er.foreign_keys();
+    let mut buf = String::new();
+    for (i, rel) in er.rels.iter().enumerate() {
+        for line in rel.doc().into_iter().flat_map(str::lines) {
+            buf.push_str(&format!("// {}\n", line));
+        }
+
+        let fk = fks.iter().find(|fk| fk.relation == Some(i));
+        let (fk1, fk2) = match fk {
+            Some(fk) if fk.entity == rel.entity1 => (Some(fk.field.as_str()), None),
+            Some(fk) => (None, Some(fk.field.as_str())),
+            None => (None, None),
+        };
+        let col1 = ref_column(er, &rel.entity1, fk1);
+        let col2 = ref_column(er, &rel.entity2, fk2);
+        let (col1, col2) = match (col1, col2) {
+            (Some(col1), Some(col2)) => (col1, col2),
+            _ => {
+                buf.push_str(&format!(
+                    "// {} {}--{} {} has no columns to refer to\n",
+                    rel.entity1,
+                    card_char(rel.card1),
+                    card_char(rel.card2),
+                    rel.entity2
+                ));
+                continue;
+            }
+        };
+
+        buf.push_str("Ref");
+        if let Some(label) = er.effective_relation_options(rel).label() {
+            buf.push_str(&format!(" {}", quote(label)));
+        }
+        buf.push_str(&format!(
+            ": {}.{} {} {}.{}\n",
+            quote(&rel.entity1),
+            quote(col1),
+            op(rel.card1, rel.card2),
+            quote(&rel.entity2),
+            quote(col2)
+        ));
+    }
+    buf
+}
+
+/// The column to refer to at the end of a relationship: the foreign key, if
+/// it's on this end, or else the entity's primary key.
+fn ref_column<'a>(er: &'a ER, name: &str, fk: Option<&'a str>) -> Option<&'a str> {
+    let entity = er.entity(name)?;
+    fk.or_else(|| {
+        entity
+            .attribs
+            .iter()
+            .find(|a| a.pk)
+            .map(|a| a.field.as_str())
+    })
+}
+
+/// The DBML relationship between two ends with cardinalities `card1` and
+/// `card2`.
+fn op(card1: Cardinality, card2: Cardinality) -> &'static str {
+    match (is_many(card1), is_many(card2)) {
+        (true, false) => ">",
+        (false, true) => "<",
+        (false, false) => "-",
+        (true, true) => "<>",
+    }
+}
+
+fn is_many(card: Cardinality) -> bool {
+    matches!(card, Cardinality::ZeroPlus | Cardinality::OnePlus)
+}
+
+/// The type to give `attr`'s column.
+fn column_type(attr: &Attribute) -> &str {
+    match attr.options.label() {
+        Some(label) if is_type(label) => label,
+        _ => ATTRIBUTE_TYPE,
+    }
+}
+
+/// Whether `text` can be written as a column type, eg. `int`,
+/// `varchar(255)` or `text[]`.
+fn is_type(text: &str) -> bool {
+    let (base, rest) = text.split_at(text.find(['(', '[']).unwrap_or(text.len()));
+    let rest = rest.strip_suffix("[]").unwrap_or(rest);
+    let args = rest.is_empty()
+        || (rest.starts_with('(')
+            && rest.ends_with(')')
+            && rest[1..rest.len() - 1]
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == ',' || c == ' '));
+    !base.is_empty() && !needs_quotes(base) && args
+}
+
+/// Writes a name, quoting it if need be.
+fn quote(name: &str) -> String {
+    if needs_quotes(name) || name.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("\"{}\"", name)
+    } else {
+        name.to_string()
+    }
+}
+
+/// Writes a string, using triple quotes when it spans more than one line.
+fn string(text: &str) -> String {
+    let escaped = text.replace('\\', "\\\\").replace('\'', "\\'");
+    if text.contains('\n') {
+        format!("'''{}'''", escaped)
+    } else {
+        format!("'{}'", escaped)
+    }
+}
+
+/// Reads DBML, along with a warning for everything which was left out or
+/// changed on the way.
+///
+/// Fails with
+/// [Error::InvalidDocument](../enum.Error.html#variant.InvalidDocument) when
+/// `input` isn't DBML, or refers to tables or columns which aren't there.
+pub fn import(input: &str) -> Result<(ER, Vec<Warning>)> {
+    let index = LineIndex::new(input);
+    let tokens = lex(input, &index)?;
+    let mut reader = Reader::new(input, tokens, index.span(input.len(), input.len()));
+    reader.document()?;
+    reader.finish()
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Tok {
+    Word(String),
+    /// A double quoted name.
+    Quoted(String),
+    /// A single (or triple) quoted string.
+    Str(String),
+    /// A backtick quoted expression.
+    Expr(String),
+    /// A relationship: `<`, `>`, `-` or `<>`.
+    Op(String),
+    Punct(char),
+    Newline,
+    /// Anything else, which is only allowed where it's skipped over.
+    Other(char),
+}
+
+fn lex(input: &str, index: &LineIndex) -> Result<Vec<(Tok, Span)>> {
+    let mut tokens = vec![];
+    let mut pos = 0;
+    while let Some(c) = input[pos..].chars().next() {
+        let rest = &input[pos..];
+        let unclosed =
+            |what: &str| invalid(index.span(pos, pos + 1), &format!("unclosed {}", what));
+        let (tok, len) = if c == '\n' {
+            (Some(Tok::Newline), 1)
+        } else if c.is_whitespace() {
+            (None, c.len_utf8())
+        } else if rest.starts_with("//") {
+            (None, rest.find('\n').unwrap_or(rest.len()))
+        } else if rest.starts_with("/*") {
+            match rest.find("*/") {
+                Some(end) => (None, end + 2),
+                None => return Err(unclosed("comment")),
+            }
+        } else if let Some(body) = rest.strip_prefix("'''") {
+            match quoted(body, "'''") {
+                Some((text, len)) => (Some(Tok::Str(dedent(&text))), len + 3),
+                None => return Err(unclosed("string")),
+            }
+        } else if "'\"`".contains(c) {
+            let (text, len) = match quoted(&rest[1..], &c.to_string()) {
+                Some((text, len)) if !text.contains('\n') => (text, len + 1),
+                _ => return Err(unclosed("quote")),
+            };
+            let tok = match c {
+                '\'' => Tok::Str(text),
+                '"' => Tok::Quoted(text),
+                _ => Tok::Expr(text),
+            };
+            (Some(tok), len)
+        } else if rest.starts_with("<>") {
+            (Some(Tok::Op("<>".to_string())), 2)
+        } else if "<>-".contains(c) {
+            (Some(Tok::Op(c.to_string())), 1)
+        } else if "{}[](),:.".contains(c) {
+            (Some(Tok::Punct(c)), 1)
+        } else if c.is_alphanumeric() || c == '_' || c == '#' {
+            let first = c.len_utf8();
+            let len = rest[first..]
+                .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .map_or(rest.len(), |i| first + i);
+            (Some(Tok::Word(rest[..len].to_string())), len)
+        } else {
+            (Some(Tok::Other(c)), c.len_utf8())
+        };
+        if let Some(tok) = tok {
+            tokens.push((tok, index.span(pos, pos + len)));
+        }
+        pos += len;
+    }
+    Ok(tokens)
+}
+
+/// Reads up to `close`, giving the unescaped text and how much of `text` was
+/// read (including `close`).
+fn quoted(text: &str, close: &str) -> Option<(String, usize)> {
+    let mut out = String::new();
+    let mut chars = text.char_indices();
+    while let Some((i, c)) = chars.next() {
+        if text[i..].starts_with(close) {
+            return Some((out, i + close.len()));
+        }
+        if c == '\\' {
+            out.push(chars.next()?.1);
+        } else {
+            out.push(c);
+        }
+    }
+    None
+}
+
+/// Strips the line break after the opening `'''` of a multi-line string, and
+/// the indentation shared by its lines.
+fn dedent(text: &str) -> String {
+    let text = text.strip_prefix('\n').unwrap_or(text).trim_end();
+    let indent = text
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.len() - line.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    text.lines()
+        .map(|line| line.get(indent..).unwrap_or("").trim_end())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// A table as it's found, before its names are checked.
+struct Table {
+    name: String,
+    alias: Option<String>,
+    span: Span,
+    attribs: Vec<Attribute>,
+    doc: Option<String>,
+    color: Option<String>,
+    /// The `TableGroup` it's in, if any.
+    group: Option<String>,
+}
+
+/// A `TableGroup`, with the names of its tables.
+struct Group {
+    name: String,
+    color: Option<String>,
+    tables: Vec<(String, Span)>,
+}
+
+/// One end of a `Ref`: a table and some of its columns.
+struct End {
+    table: String,
+    columns: Vec<String>,
+    span: Span,
+}
+
+struct Ref {
+    name: Option<String>,
+    left: End,
+    op: String,
+    right: End,
+}
+
+/// A column or table setting, eg. `pk` or `note: 'text'`.
+struct Setting {
+    /// The name, lowercased.
+    key: String,
+    /// The tokens after the `:`, if there was one.
+    value: Vec<(Tok, Span)>,
+    span: Span,
+}
+
+struct Reader<'s> {
+    input: &'s str,
+    tokens: Vec<(Tok, Span)>,
+    pos: usize,
+    /// Where the input ends, for errors about things missing from the end.
+    end: Span,
+    tables: Vec<Table>,
+    groups: Vec<Group>,
+    refs: Vec<Ref>,
+    title: Option<String>,
+    warnings: Vec<Warning>,
+}
+
+impl<'s> Reader<'s> {
+    fn new(input: &'s str, tokens: Vec<(Tok, Span)>, end: Span) -> Self {
+        Reader {
+            input,
+            tokens,
+            pos: 0,
+            end,
+            tables: vec![],
+            groups: vec![],
+            refs: vec![],
+            title: None,
+            warnings: vec![],
+        }
+    }
+
+    fn peek(&self) -> Option<&Tok> {
+        self.tokens.get(self.pos).map(|(tok, _)| tok)
+    }
+
+    /// The token after the next one.
+    fn peek2(&self) -> Option<&Tok> {
+        self.tokens.get(self.pos + 1).map(|(tok, _)| tok)
+    }
+
+    /// Whether the next token is the keyword `word`, in any case.
+    fn at_keyword(&self, word: &str) -> bool {
+        matches!(self.peek(), Some(Tok::Word(w)) if w.eq_ignore_ascii_case(word))
+    }
+
+    fn span(&self) -> Span {
+        self.tokens
+            .get(self.pos)
+            .map_or(self.end, |(_, span)| *span)
+    }
+
+    fn next(&mut self) -> Option<(Tok, Span)> {
+        let tok = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        tok
+    }
+
+    fn expect(&mut self, punct: char) -> Result<()> {
+        match self.next() {
+            Some((Tok::Punct(c), _)) if c == punct => Ok(()),
+            _ => Err(self.expected(&format!("`{}`", punct))),
+        }
+    }
+
+    /// An error for the last token taken not being what was expected.
+    fn expected(&self, what: &str) -> Error {
+        let span = match self.tokens.get(self.pos.saturating_sub(1)) {
+            Some((_, span)) if self.pos <= self.tokens.len() => *span,
+            _ => self.end,
+        };
+        invalid(span, &format!("expected {}", what))
+    }
+
+    fn warn(&mut self, span: Span, message: String) {
+        self.warnings.push(Warning { message, span });
+    }
+
+    fn skip_newlines(&mut self) {
+        while self.peek() == Some(&Tok::Newline) {
+            self.pos += 1;
+        }
+    }
+
+    /// Skips up to and including the `}` closing the next `{`, giving
+    /// whether there was anything in between.
+    fn skip_block(&mut self) -> Result<bool> {
+        while !matches!(self.peek(), None | Some(Tok::Punct('{'))) {
+            self.pos += 1;
+        }
+        self.expect('{')?;
+        let (mut depth, mut empty) = (1, true);
+        while depth > 0 {
+            match self.next() {
+                Some((Tok::Punct('{'), _)) => depth += 1,
+                Some((Tok::Punct('}'), _)) => depth -= 1,
+                Some((Tok::Newline, _)) => {}
+                Some(_) => empty = false,
+                None => return Err(self.expected("`}`")),
+            }
+        }
+        Ok(empty)
+    }
+
+    /// A name: a word, or anything in double quotes.
+    fn name(&mut self) -> Result<(String, Span)> {
+        match self.next() {
+            Some((Tok::Word(name), span)) | Some((Tok::Quoted(name), span)) => Ok((name, span)),
+            _ => Err(self.expected("a name")),
+        }
+    }
+
+    /// A table's name, dropping any schema.
+    fn table_name(&mut self) -> Result<(String, Span)> {
+        let (name, span) = self.name()?;
+        if self.peek() != Some(&Tok::Punct('.')) {
+            return Ok((name, span));
+        }
+        self.pos += 1;
+        self.warn(
+            span,
+            format!("schemas aren't supported, so `{}` was dropped", name),
+        );
+        self.name()
+    }
+
+    fn string(&mut self) -> Result<String> {
+        match self.next() {
+            Some((Tok::Str(text), _)) => Ok(text),
+            _ => Err(self.expected("a string")),
+        }
+    }
+
+    fn document(&mut self) -> Result<()> {
+        loop {
+            self.skip_newlines();
+            let span = self.span();
+            let keyword = match self.peek() {
+                None => return Ok(()),
+                Some(Tok::Word(w)) => w.to_lowercase(),
+                _ => {
+                    self.pos += 1;
+                    return Err(self.expected("`Table`, `Ref` or `Project`"));
+                }
+            };
+            match keyword.as_str() {
+                "table" => self.table()?,
+                "ref" => self.reference()?,
+                "project" => {
+                    self.pos += 1;
+                    if !matches!(self.peek(), Some(Tok::Punct('{'))) {
+                        self.title = Some(self.name()?.0);
+                    }
+                    if !self.skip_block()? {
+                        self.warn(span, "project settings aren't supported".to_string());
+                    }
+                }
+                "tablegroup" => self.table_group()?,
+                "enum" | "note" | "tablepartial" | "records" => {
+                    let word = self.next().map(|(tok, _)| tok);
+                    if let Some(Tok::Word(word)) = word {
+                        self.warn(span, format!("`{}` isn't supported", word));
+                    }
+                    self.skip_block()?;
+                }
+                _ => {
+                    self.pos += 1;
+                    return Err(self.expected("`Table`, `Ref` or `Project`"));
+                }
+            }
+        }
+    }
+
+    fn table(&mut self) -> Result<()> {
+        self.pos += 1;
+        let (name, span) = self.table_name()?;
+        let mut table = Table {
+            name,
+            alias: None,
+            span,
+            attribs: vec![],
+            doc: None,
+            color: None,
+            group: None,
+        };
+        if self.at_keyword("as") {
+            self.pos += 1;
+            table.alias = Some(self.name()?.0);
+        }
+        for setting in self.settings()? {
+            match setting.key.as_str() {
+                "headercolor" => match setting_color(&setting) {
+                    Some(color) => table.color = Some(color),
+                    None => self.unsupported(&setting),
+                },
+                "note" => table.doc = setting_string(&setting),
+                _ => self.unsupported(&setting),
+            }
+        }
+
+        self.expect('{')?;
+        loop {
+            self.skip_newlines();
+            if self.peek() == Some(&Tok::Punct('}')) {
+                self.pos += 1;
+                break;
+            }
+            let is_block = |tok: Option<&Tok>| matches!(tok, Some(Tok::Punct(':' | '{')));
+            if self.at_keyword("note") && is_block(self.peek2()) {
+                self.pos += 1;
+                table.doc = Some(if self.peek() == Some(&Tok::Punct(':')) {
+                    self.pos += 1;
+                    self.string()?
+                } else {
+                    self.expect('{')?;
+                    self.skip_newlines();
+                    let doc = self.string()?;
+                    self.skip_newlines();
+                    self.expect('}')?;
+                    doc
+                });
+            } else if self.at_keyword("indexes") && is_block(self.peek2()) {
+                self.pos += 1;
+                self.indexes(&mut table)?;
+            } else {
+                let attr = self.column(&table.name)?;
+                table.attribs.push(attr);
+            }
+        }
+        self.tables.push(table);
+        Ok(())
+    }
+
+    /// Reads a `TableGroup`, leaving its tables to be colored by
+    /// [finish](#method.finish).
+    fn table_group(&mut self) -> Result<()> {
+        self.pos += 1;
+        let (name, _) = self.name()?;
+        let mut group = Group {
+            name,
+            color: None,
+            tables: vec![],
+        };
+        for setting in self.settings()? {
+            match setting.key.as_str() {
+                "color" => match setting_color(&setting) {
+                    Some(color) => group.color = Some(color),
+                    None => self.unsupported(&setting),
+                },
+                _ => self.unsupported(&setting),
+            }
+        }
+
+        self.expect('{')?;
+        loop {
+            self.skip_newlines();
+            if self.peek() == Some(&Tok::Punct('}')) {
+                self.pos += 1;
+                break;
+            }
+            if self.at_keyword("note") && matches!(self.peek2(), Some(Tok::Punct(':' | '{'))) {
+                let span = self.span();
+                self.pos += 1;
+                if self.peek() == Some(&Tok::Punct(':')) {
+                    self.pos += 1;
+                    self.string()?;
+                } else {
+                    self.skip_block()?;
+                }
+                self.warn(span, "notes on table groups aren't supported".to_string());
+                continue;
+            }
+            let table = self.table_name()?;
+            group.tables.push(table);
+        }
+        self.groups.push(group);
+        Ok(())
+    }
+
+    /// Reads a column of the table named `table`.
+    fn column(&mut self, table: &str) -> Result<Attribute> {
+        let (field, span) = self.name()?;
+        let ty = match self.next() {
+            Some((Tok::Quoted(ty), _)) => ty,
+            Some((Tok::Word(_), start)) => {
+                let mut end = start.end;
+                if self.peek() == Some(&Tok::Punct('(')) {
+                    end = loop {
+                        match self.next() {
+                            Some((Tok::Punct(')'), close)) => break close.end,
+                            Some(_) => {}
+                            None => return Err(self.expected("`)`")),
+                        }
+                    };
+                }
+                if self.peek() == Some(&Tok::Punct('[')) && self.peek2() == Some(&Tok::Punct(']')) {
+                    self.pos += 2;
+                    end = self.tokens[self.pos - 1].1.end;
+                }
+                self.input[start.start..end].to_string()
+            }
+            _ => return Err(self.expected("a column type")),
+        };
+
+        let mut attr = Attribute {
+            field,
+            pk: false,
+            fk: false,
+            options: Options::default(),
+            doc: None,
+            span,
+        };
+        if ty != ATTRIBUTE_TYPE {
+            attr.options.set(Opt::Label(ty));
+        }
+        for setting in self.settings()? {
+            match setting.key.as_str() {
+                "pk" | "primary key" => attr.pk = true,
+                "note" => attr.doc = setting_string(&setting),
+                "ref" => {
+                    let mut value = Reader::new(self.input, setting.value, setting.span);
+                    let op = value.op()?;
+                    let right = value.end()?;
+                    self.warnings.append(&mut value.warnings);
+                    self.refs.push(Ref {
+                        name: None,
+                        left: End {
+                            table: table.to_string(),
+                            columns: vec![attr.field.clone()],
+                            span,
+                        },
+                        op,
+                        right,
+                    });
+                }
+                _ => self.unsupported(&setting),
+            }
+        }
+        match self.peek() {
+            None | Some(Tok::Newline) | Some(Tok::Punct('}')) => Ok(attr),
+            _ => {
+                self.pos += 1;
+                Err(self.expected("the end of the line"))
+            }
+        }
+    }
+
+    /// Reads an `indexes { ... }` block, marking primary keys.
+    fn indexes(&mut self, table: &mut Table) -> Result<()> {
+        self.expect('{')?;
+        loop {
+            self.skip_newlines();
+            let span = self.span();
+            let columns = match self.next() {
+                Some((Tok::Punct('}'), _)) => return Ok(()),
+                Some((Tok::Punct('('), _)) => {
+                    let mut columns = vec![];
+                    loop {
+                        match self.next() {
+                            Some((Tok::Word(c), _)) | Some((Tok::Quoted(c), _)) => {
+                                columns.push(Some(c))
+                            }
+                            Some((Tok::Expr(_), _)) => columns.push(None),
+                            _ => return Err(self.expected("a column")),
+                        }
+                        match self.next() {
+                            Some((Tok::Punct(')'), _)) => break,
+                            Some((Tok::Punct(','), _)) => {}
+                            _ => return Err(self.expected("`,` or `)`")),
+                        }
+                    }
+                    columns
+                }
+                Some((Tok::Word(c), _)) | Some((Tok::Quoted(c), _)) => vec![Some(c)],
+                Some((Tok::Expr(_), _)) => vec![None],
+                _ => return Err(self.expected("an index")),
+            };
+            let settings = self.settings()?;
+            if !settings
+                .iter()
+                .any(|s| s.key == "pk" || s.key == "primary key")
+            {
+                self.warn(span, "indexes aren't supported".to_string());
+                continue;
+            }
+            for column in columns {
+                let attr = column
+                    .as_ref()
+                    .and_then(|c| table.attribs.iter_mut().find(|a| a.field == *c));
+                match attr {
+                    Some(attr) => attr.pk = true,
+                    None => return Err(invalid(span, "primary keys must be columns of the table")),
+                }
+            }
+        }
+    }
+
+    /// Reads a `[...]` list of settings, if there is one.
+    fn settings(&mut self) -> Result<Vec<Setting>> {
+        if self.peek() != Some(&Tok::Punct('[')) {
+            return Ok(vec![]);
+        }
+        self.pos += 1;
+        let mut settings = vec![];
+        loop {
+            let span = self.span();
+            let mut key = vec![];
+            while let Some(Tok::Word(w)) = self.peek() {
+                key.push(w.to_lowercase());
+                self.pos += 1;
+            }
+            if key.is_empty() {
+                return Err(self.expected("a setting"));
+            }
+            let mut value = vec![];
+            if self.peek() == Some(&Tok::Punct(':')) {
+                self.pos += 1;
+                while !matches!(self.peek(), None | Some(Tok::Punct(',' | ']'))) {
+                    value.push(self.next().unwrap());
+                }
+            }
+            settings.push(Setting {
+                key: key.join(" "),
+                value,
+                span,
+            });
+            match self.next() {
+                Some((Tok::Punct(']'), _)) => return Ok(settings),
+                Some((Tok::Punct(','), _)) => {}
+                _ => return Err(self.expected("`,` or `]`")),
+            }
+        }
+    }
+
+    fn unsupported(&mut self, setting: &Setting) {
+        self.warn(
+            setting.span,
+            format!("the `{}` setting isn't supported", setting.key),
+        );
+    }
+
+    /// Reads a `Ref`, either on one line or a block of them.
+    fn reference(&mut self) -> Result<()> {
+        self.pos += 1;
+        let name = match self.peek() {
+            Some(Tok::Word(_)) | Some(Tok::Quoted(_)) => Some(self.name()?.0),
+            _ => None,
+        };
+        match self.next() {
+            Some((Tok::Punct(':'), _)) => self.ref_line(name),
+            Some((Tok::Punct('{'), _)) => {
+                self.skip_newlines();
+                self.ref_line(name)?;
+                self.skip_newlines();
+                self.expect('}')
+            }
+            _ => Err(self.expected("`:` or `{`")),
+        }
+    }
+
+    fn ref_line(&mut self, name: Option<String>) -> Result<()> {
+        let left = self.end()?;
+        let op = self.op()?;
+        let right = self.end()?;
+        for setting in self.settings()? {
+            self.unsupported(&setting);
+        }
+        self.refs.push(Ref {
+            name,
+            left,
+            op,
+            right,
+        });
+        Ok(())
+    }
+
+    fn op(&mut self) -> Result<String> {
+        match self.next() {
+            Some((Tok::Op(op), _)) => Ok(op),
+            _ => Err(self.expected("`<`, `>`, `-` or `<>`")),
+        }
+    }
+
+    /// One end of a `Ref`: `table.column`, `table.(column, ...)` or either of
+    /// those with a schema.
+    fn end(&mut self) -> Result<End> {
+        let span = self.span();
+        let mut parts = vec![self.name()?.0];
+        let mut columns = None;
+        while self.peek() == Some(&Tok::Punct('.')) {
+            self.pos += 1;
+            if self.peek() == Some(&Tok::Punct('(')) {
+                self.pos += 1;
+                let mut list = vec![self.name()?.0];
+                while self.peek() == Some(&Tok::Punct(',')) {
+                    self.pos += 1;
+                    list.push(self.name()?.0);
+                }
+                self.expect(')')?;
+                columns = Some(list);
+                break;
+            }
+            parts.push(self.name()?.0);
+        }
+        let columns = match columns {
+            Some(columns) => columns,
+            None if parts.len() > 1 => vec![parts.pop().unwrap()],
+            None => return Err(self.expected("a column")),
+        };
+        if parts.len() > 1 {
+            self.warn(
+                span,
+                format!("schemas aren't supported, so `{}` was dropped", parts[0]),
+            );
+        }
+        Ok(End {
+            table: parts.pop().unwrap(),
+            columns,
+            span,
+        })
+    }
+
+    /// Checks the names of everything found and puts together the model.
+    fn finish(mut self) -> Result<(ER, Vec<Warning>)> {
+        let mut rels = vec![];
+        for r in std::mem::take(&mut self.refs) {
+            let left = self.find(&r.left)?;
+            let right = self.find(&r.right)?;
+            let (card1, card2) = match r.op.as_str() {
+                ">" => (Cardinality::ZeroPlus, Cardinality::One),
+                "<" => (Cardinality::One, Cardinality::ZeroPlus),
+                "-" => (Cardinality::One, Cardinality::One),
+                _ => (Cardinality::ZeroPlus, Cardinality::ZeroPlus),
+            };
+            let is_pk = |(table, columns): &(usize, Vec<usize>)| {
+                columns.iter().all(|&c| self.tables[*table].attribs[c].pk)
+            };
+            let fk = match r.op.as_str() {
+                ">" => Some(&left),
+                "<" => Some(&right),
+                "-" if is_pk(&left) && !is_pk(&right) => Some(&right),
+                "-" => Some(&left),
+                _ => None,
+            };
+            if let Some((table, columns)) = fk {
+                for &c in columns {
+                    self.tables[*table].attribs[c].fk = true;
+                }
+            }
+            rels.push((left.0, right.0, card1, card2, r.name));
+        }
+
+        let mut palette = GROUP_COLORS.iter().cycle();
+        for group in std::mem::take(&mut self.groups) {
+            let color = match group.color {
+                Some(color) => color,
+                None => palette.next().unwrap().to_string(),
+            };
+            for (name, span) in group.tables {
+                let index = self.find_table(&name, span)?;
+                let table = &mut self.tables[index];
+                if let Some(other) = &table.group {
+                    let message = format!("`{}` is already in table group `{}`", name, other);
+                    return Err(invalid(span, &message));
+                }
+                table.group = Some(group.name.clone());
+                if table.color.is_some() {
+                    let message = format!(
+                        "`{}` has its own headercolor, so it isn't colored as part of `{}`",
+                        name, group.name
+                    );
+                    self.warn(span, message);
+                } else {
+                    table.color = Some(color.clone());
+                }
+            }
+        }
+
+        let tables = std::mem::take(&mut self.tables);
+        let table_names: Vec<_> = tables.iter().map(|t| (t.name.as_str(), t.span)).collect();
+        let table_names = checked_names(&table_names, &mut self.warnings);
+        let mut entities = vec![];
+        for (table, name) in tables.into_iter().zip(table_names) {
+            let fields: Vec<_> = table
+                .attribs
+                .iter()
+                .map(|a| (a.field.as_str(), a.span))
+                .collect();
+            let fields = checked_names(&fields, &mut self.warnings);
+            let mut attribs = vec![];
+            for (mut attr, field) in table.attribs.into_iter().zip(fields) {
+                attr.field = field;
+                attr.span = Default::default();
+                attribs.push(attr);
+            }
+            let options: Options = table.color.into_iter().map(Opt::BgColor).collect();
+            entities.push(Entity {
+                name,
+                attribs,
+                hoptions: options.clone(),
+                eoptions: options,
+                doc: table.doc,
+                span: Default::default(),
+                name_span: Default::default(),
+            });
+        }
+
+        let rels = rels
+            .into_iter()
+            .map(|(left, right, card1, card2, name)| Relation {
+                entity1: entities[left].name.clone(),
+                entity2: entities[right].name.clone(),
+                card1,
+                card2,
+                options: name.into_iter().map(Opt::Label).collect(),
+                doc: None,
+                span: Default::default(),
+                entity1_span: Default::default(),
+                entity2_span: Default::default(),
+            })
+            .collect();
+
+        let title = self.title.map(|label| {
+            (
+                Directive::Title,
+                std::iter::once(Opt::Label(label)).collect(),
+            )
+        });
+        let er = ER {
+            entities,
+            rels,
+            globals: GlobalOptions::from_directives(title),
+        };
+        Ok((er, self.warnings))
+    }
+
+    /// The indexes of the table and columns at one end of a `Ref`.
+    fn find(&self, end: &End) -> Result<(usize, Vec<usize>)> {
+        let table = self.find_table(&end.table, end.span)?;
+        let attribs = &self.tables[table].attribs;
+        let columns = end
+            .columns
+            .iter()
+            .map(|name| {
+                attribs
+                    .iter()
+                    .position(|a| a.field == *name)
+                    .ok_or_else(|| {
+                        let message = format!("`{}` has no column `{}`", end.table, name);
+                        invalid(end.span, &message)
+                    })
+            })
+            .collect::<Result<_>>()?;
+        Ok((table, columns))
+    }
+
+    /// The index of the table called `name`, or with `name` as its alias.
+    fn find_table(&self, name: &str, span: Span) -> Result<usize> {
+        self.tables
+            .iter()
+            .position(|t| t.name == name || t.alias.as_deref() == Some(name))
+            .ok_or_else(|| invalid(span, &format!("there's no table `{}`", name)))
+    }
+}
+
+/// The value of a setting which should be a string, eg. `note: 'text'`.
+fn setting_string(setting: &Setting) -> Option<String> {
+    match setting.value.first() {
+        Some((Tok::Str(text), _)) => Some(text.clone()),
+        _ => None,
+    }
+}
+
+/// The value of a setting which should be a hex color, eg. `color: #3498db`.
+fn setting_color(setting: &Setting) -> Option<String> {
+    match setting.value.first() {
+        Some((Tok::Word(color), _)) if color.starts_with('#') => Some(color.clone()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{import, GROUP_COLORS};
+    use crate::er::ER;
+    use crate::parser::parse;
+    use crate::Error;
+
+    fn basics() -> ER {
+        parse(include_str!("../samples/basics.erd")).unwrap()
+    }
+
+    #[test]
+    fn test_render() {
+        assert_eq!(
+            basics().to_dbml(),
+            r#"Table Person {
+    name attr [pk]
+    height attr
+    weight attr
+    "birth date" attr
+    birth_place_id attr
+}
+
+Table "Birth Place" {
+    id attr [pk]
+    "birth city" attr
+    "birth state" attr
+    "birth country" attr
+}
+
+Ref: Person.birth_place_id > "Birth Place".id
+"#
+        );
+    }
+
+    #[test]
+    fn test_basics_round_trips() {
+        let er = basics();
+        let (imported, warnings) = import(&er.to_dbml()).unwrap();
+        assert!(warnings.is_empty(), "{:?}", warnings);
+        assert_eq!(imported, er);
+
+        let dbml = er.to_dbml();
+        assert_eq!(import(&dbml).unwrap().0.to_dbml(), dbml);
+    }
+
+    #[test]
+    fn test_round_trips_the_rest() {
+        let er = parse(
+            r##"title { label: "Orders" }
+
+## Someone who buys things.
+[Customer] { bgcolor: "#3498db" }
+*id { label: "int" }
+## What they're called,
+## in full.
+name { label: "varchar(255)" }
+
+[`Line Item`]
+*+order_id { label: "int" }
+*number { label: "int" }
+
+[Order]
+*id
++customer_id
+
+[Account]
+*id
++customer_id
+
+Customer 1--* Order { label: "places" }
+Order 1--* `Line Item`
+Customer 1--1 Account
+"##,
+        )
+        .unwrap();
+        let dbml = er.to_dbml();
+        assert!(dbml.contains("Table Customer [headercolor: #3498db] {\n"));
+        assert!(dbml.contains("        (order_id, number) [pk]\n"));
+        assert!(dbml.contains("Ref places: Customer.id < Order.customer_id\n"));
+        let (imported, warnings) = import(&dbml).unwrap();
+        assert!(warnings.is_empty(), "{:?}", warnings);
+        assert_eq!(imported, er);
+    }
+
+    #[test]
+    fn test_import() {
+        let (er, warnings) = import(
+            r#"
+Project shop {
+  database_type: 'PostgreSQL'
+}
+
+// The people.
+Table public.users as U [note: 'People'] {
+  id integer [primary key, increment]
+  "full name" varchar [not null, note: 'Given then family']
+  Note {
+    '''
+    Anyone with an account.
+      Even the admins.
+    '''
+  }
+}
+
+Table posts {
+  id int [pk]
+  user_id int [ref: > U.id]
+  tags "text[]"
+  "body-text" text
+
+  indexes {
+    user_id [unique]
+  }
+}
+
+Enum status { draft }
+TableGroup content { posts }
+
+Ref follows {
+  U.id <> U.id
+}
+"#,
+        )
+        .unwrap();
+
+        let expected = parse(
+            r##"title { label: "shop" }
+
+## Anyone with an account.
+##   Even the admins.
+[users]
+*id { label: "integer" }
+## Given then family
+`full name` { label: "varchar" }
+
+[posts] { bgcolor: "#d6eaf8" }
+*id { label: "int" }
++user_id { label: "int" }
+tags { label: "text[]" }
+body_text { label: "text" }
+
+posts *--1 users
+users *--* users { label: "follows" }
+"##,
+        )
+        .unwrap();
+        assert_eq!(er, expected);
+
+        let warnings: Vec<_> = warnings
+            .iter()
+            .map(|w| format!("{}: {}", w.span, w))
+            .collect();
+        assert_eq!(
+            warnings,
+            [
+                "2:1: project settings aren't supported",
+                "7:7: schemas aren't supported, so `public` was dropped",
+                "8:28: the `increment` setting isn't supported",
+                "9:24: the `not null` setting isn't supported",
+                "25:5: indexes aren't supported",
+                "29:1: `Enum` isn't supported",
+                "22:3: `body-text` isn't a valid er name, so it was renamed to `body_text`",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_import_table_groups() {
+        let (er, warnings) = import(
+            r#"Table a [headercolor: #ff0000] {
+}
+Table b {
+}
+Table c as C {
+}
+Table d {
+}
+TableGroup first {
+  a
+  b
+  Note: 'The first two'
+}
+TableGroup second [color: #3498db] { C }
+TableGroup third { d }
+"#,
+        )
+        .unwrap();
+        let colors: Vec<_> = er
+            .entities()
+            .iter()
+            .map(|e| e.header_options().bg_color().unwrap().as_str())
+            .collect();
+        assert_eq!(
+            colors,
+            ["#ff0000", GROUP_COLORS[0], "#3498db", GROUP_COLORS[1]]
+        );
+        assert_eq!(er.to_dbml().matches("[headercolor: ").count(), 4);
+
+        let warnings: Vec<_> = warnings
+            .iter()
+            .map(|w| format!("{}: {}", w.span, w))
+            .collect();
+        assert_eq!(
+            warnings,
+            [
+                "12:3: notes on table groups aren't supported",
+                "10:3: `a` has its own headercolor, so it isn't colored as part of `first`",
+            ]
+        );
+
+        let err = import("Table a {\n}\nTableGroup x { a }\nTableGroup y { a }\n").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "4:16: Invalid document: `a` is already in table group `x`"
+        );
+        assert!(import("TableGroup x { nope }").is_err());
+    }
+
+    #[test]
+    fn test_import_renames_clashes() {
+        let (er, warnings) = import(
+            r#"Table "a-b" {
+  "x-y" int
+  x_y int
+}
+Table a_b {
+  id int
+}
+Ref: "a-b"."x-y" > a_b.id
+"#,
+        )
+        .unwrap();
+        let expected = parse(
+            r#"[a_b_2]
++x_y_2 { label: "int" }
+x_y { label: "int" }
+
+[a_b]
+id { label: "int" }
+
+a_b_2 *--1 a_b
+"#,
+        )
+        .unwrap();
+        assert_eq!(er, expected);
+        let warnings: Vec<_> = warnings.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            warnings,
+            [
+                "`a-b` isn't a valid er name and `a_b` is taken, so it was renamed to `a_b_2`",
+                "`x-y` isn't a valid er name and `x_y` is taken, so it was renamed to `x_y_2`",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_import_renames_non_ascii() {
+        let (er, warnings) = import("Table éa {\n  ñame int [pk]\n}\n").unwrap();
+        assert_eq!(er, parse("[_a]\n*_ame { label: \"int\" }\n").unwrap());
+        let warnings: Vec<_> = warnings.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            warnings,
+            [
+                "`éa` isn't a valid er name, so it was renamed to `_a`",
+                "`ñame` isn't a valid er name, so it was renamed to `_ame`",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_import_errors() {
+        let err = |input| match import(input) {
+            Err(Error::InvalidDocument {
+                message,
+                span: Some(span),
+            }) => format!("{}: {}", span, message),
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(
+            err("Table a {\n  id int\n}\nRef: a.id > b.id\n"),
+            "4:13: there's no table `b`"
+        );
+        assert_eq!(
+            err("Table a {\n  id int\n}\nRef: a.id > a.nope\n"),
+            "4:13: `a` has no column `nope`"
+        );
+        assert_eq!(err("Table a {\n  id\n}\n"), "2:5: expected a column type");
+        assert_eq!(err("Table a {\n  id int\n"), "3:1: expected a name");
+        assert_eq!(err("Note: 'hi"), "1:7: unclosed quote");
+        assert_eq!(
+            err("View v {}"),
+            "1:1: expected `Table`, `Ref` or `Project`"
+        );
+        assert!(err("Table a {\n  id varchar(").ends_with("expected `)`"));
+    }
+}
@@ -0,0 +1,636 @@
+//! Compares two versions of an [ER](../er/struct.ER.html).
+//!
+//! The comparison is of what the documents describe, not how they're written:
+//! formatting, comments and the order attributes are listed in don't count as
+//! changes. Entities are matched by name, attributes by field name within
+//! their entity and relationships by the pair of entities they join (in
+//! either direction).
+
+use crate::er::{card_char, Attribute, Cardinality, Directive, Entity, Options, ER, OPTION_NAMES};
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+
+/// Everything which changed between two versions of an er document.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ErDiff {
+    changes: Vec<Change>,
+}
+
+impl ErDiff {
+    /// The changes, grouped into the directives, then each entity, then the
+    /// relationships.
+    pub fn changes(&self) -> &[Change] {
+        &self.changes
+    }
+
+    /// Returns `true` when the two documents describe the same diagram.
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+/// One line per change, each marked with `+`, `-` or `~` for things which
+/// were added, removed or changed.
+impl Display for ErDiff {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for change in &self.changes {
+            writeln!(f, "{} {}", change.kind(), change)?;
+        }
+        Ok(())
+    }
+}
+
+/// Whether a [Change](enum.Change.html) adds, removes or alters something.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Changed,
+}
+
+impl Display for ChangeKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChangeKind::Added => write!(f, "+"),
+            ChangeKind::Removed => write!(f, "-"),
+            ChangeKind::Changed => write!(f, "~"),
+        }
+    }
+}
+
+/// A single difference between two versions of an er document.
+///
+/// Entities are always named as they are in the newer version, so the
+/// changes to an entity which was renamed use its new name.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(tag = "change", rename_all = "snake_case")
+)]
+pub enum Change {
+    EntityAdded {
+        entity: String,
+    },
+    EntityRemoved {
+        entity: String,
+    },
+    /// An entity was removed and another with exactly the same attributes
+    /// was added.
+    EntityRenamed {
+        from: String,
+        to: String,
+    },
+    AttributeAdded {
+        entity: String,
+        field: String,
+    },
+    AttributeRemoved {
+        entity: String,
+        field: String,
+    },
+    /// An attribute became, or stopped being, part of the primary key.
+    PrimaryKeyChanged {
+        entity: String,
+        field: String,
+        pk: bool,
+    },
+    /// An attribute became, or stopped being, a foreign key.
+    ForeignKeyChanged {
+        entity: String,
+        field: String,
+        fk: bool,
+    },
+    RelationAdded {
+        entity1: String,
+        entity2: String,
+        card1: Cardinality,
+        card2: Cardinality,
+    },
+    RelationRemoved {
+        entity1: String,
+        entity2: String,
+        card1: Cardinality,
+        card2: Cardinality,
+    },
+    /// The cardinalities of a relationship changed. Both `from` and `to`
+    /// are in the order of `entity1` and `entity2`, even if the older
+    /// version had them the other way around.
+    CardinalityChanged {
+        entity1: String,
+        entity2: String,
+        from: (Cardinality, Cardinality),
+        to: (Cardinality, Cardinality),
+    },
+    /// An option was set, unset or given a different value. Values are as
+    /// they'd be written in an er file, less the quotes.
+    OptionChanged {
+        item: Item,
+        name: String,
+        from: Option<String>,
+        to: Option<String>,
+    },
+}
+
+impl Change {
+    /// Whether the change adds, removes or alters something.
+    pub fn kind(&self) -> ChangeKind {
+        match self {
+            Change::EntityAdded { .. }
+            | Change::AttributeAdded { .. }
+            | Change::RelationAdded { .. } => ChangeKind::Added,
+            Change::EntityRemoved { .. }
+            | Change::AttributeRemoved { .. }
+            | Change::RelationRemoved { .. } => ChangeKind::Removed,
+            Change::EntityRenamed { .. }
+            | Change::PrimaryKeyChanged { .. }
+            | Change::ForeignKeyChanged { .. }
+            | Change::CardinalityChanged { .. }
+            | Change::OptionChanged { .. } => ChangeKind::Changed,
+        }
+    }
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Change::EntityAdded { entity } | Change::EntityRemoved { entity } => {
+                write!(f, "entity `{}`", entity)
+            }
+            Change::EntityRenamed { from, to } => {
+                write!(f, "entity `{}` renamed to `{}`", from, to)
+            }
+            Change::AttributeAdded { entity, field }
+            | Change::AttributeRemoved { entity, field } => {
+                write!(f, "attribute `{}` in `{}`", field, entity)
+            }
+            Change::PrimaryKeyChanged { entity, field, pk } => write!(
+                f,
+                "attribute `{}` in `{}` is {} part of the primary key",
+                field,
+                entity,
+                if *pk { "now" } else { "no longer" }
+            ),
+            Change::ForeignKeyChanged { entity, field, fk } => write!(
+                f,
+                "attribute `{}` in `{}` is {} a foreign key",
+                field,
+                entity,
+                if *fk { "now" } else { "no longer" }
+            ),
+            Change::RelationAdded {
+                entity1,
+                entity2,
+                card1,
+                card2,
+            }
+            | Change::RelationRemoved {
+                entity1,
+                entity2,
+                card1,
+                card2,
+            } => write!(
+                f,
+                "relationship `{}` {}--{} `{}`",
+                entity1,
+                card_char(*card1),
+                card_char(*card2),
+                entity2
+            ),
+            Change::CardinalityChanged {
+                entity1,
+                entity2,
+                from,
+                to,
+            } => write!(
+                f,
+                "relationship between `{}` and `{}` changed from {}--{} to {}--{}",
+                entity1,
+                entity2,
+                card_char(from.0),
+                card_char(from.1),
+                card_char(to.0),
+                card_char(to.1)
+            ),
+            Change::OptionChanged {
+                item,
+                name,
+                from,
+                to,
+            } => {
+                write!(f, "option `{}` on {}", name, item)?;
+                match (from, to) {
+                    (None, Some(to)) => write!(f, " set to {:?}", to),
+                    (Some(from), None) => write!(f, " unset (was {:?})", from),
+                    (Some(from), Some(to)) => write!(f, " changed from {:?} to {:?}", from, to),
+                    (None, None) => Ok(()),
+                }
+            }
+        }
+    }
+}
+
+/// The part of a diagram an option belongs to.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(tag = "kind", rename_all = "snake_case")
+)]
+pub enum Item {
+    Directive { directive: Directive },
+    Entity { entity: String },
+    Attribute { entity: String, field: String },
+    Relation { entity1: String, entity2: String },
+}
+
+impl Display for Item {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Item::Directive { directive } => write!(f, "the `{}` directive", directive.name()),
+            Item::Entity { entity } => write!(f, "entity `{}`", entity),
+            Item::Attribute { entity, field } => {
+                write!(f, "attribute `{}` in `{}`", field, entity)
+            }
+            Item::Relation { entity1, entity2 } => write!(
+                f,
+                "the relationship between `{}` and `{}`",
+                entity1, entity2
+            ),
+        }
+    }
+}
+
+/// Compares `old` with `new`, listing everything which changed.
+pub fn diff(old: &ER, new: &ER) -> ErDiff {
+    let mut changes = vec![];
+
+    for &directive in &Directive::ALL {
+        diff_options(
+            Item::Directive { directive },
+            old.globals.get(directive),
+            new.globals.get(directive),
+            &mut changes,
+        );
+    }
+
+    let old_entities = by_name(&old.entities);
+    let new_entities = by_name(&new.entities);
+    let renames = renames(old, new, &old_entities, &new_entities);
+    for (i, entity) in old.entities.iter().enumerate() {
+        if old_entities[entity.name.as_str()] != i {
+            continue;
+        }
+        let name = renames
+            .get(entity.name.as_str())
+            .copied()
+            .unwrap_or(&entity.name);
+        match new_entities.get(name) {
+            Some(&j) => {
+                let new_entity = &new.entities[j];
+                if name != entity.name {
+                    changes.push(Change::EntityRenamed {
+                        from: entity.name.clone(),
+                        to: name.to_string(),
+                    });
+                }
+                diff_entity(entity, new_entity, &mut changes);
+            }
+            None => changes.push(Change::EntityRemoved {
+                entity: entity.name.clone(),
+            }),
+        }
+    }
+    let renamed: HashSet<&str> = renames.values().copied().collect();
+    for (i, entity) in new.entities.iter().enumerate() {
+        let is_new = !old_entities.contains_key(entity.name.as_str())
+            && !renamed.contains(entity.name.as_str())
+            && new_entities[entity.name.as_str()] == i;
+        if is_new {
+            changes.push(Change::EntityAdded {
+                entity: entity.name.clone(),
+            });
+        }
+    }
+
+    diff_relations(old, new, &renames, &mut changes);
+    ErDiff { changes }
+}
+
+/// The index of the first entity with each name, so that a duplicate
+/// declaration (which validation reports anyway) doesn't show up as a change.
+fn by_name(entities: &[Entity]) -> HashMap<&str, usize> {
+    let mut names = HashMap::new();
+    for (i, entity) in entities.iter().enumerate() {
+        names.entry(entity.name.as_str()).or_insert(i);
+    }
+    names
+}
+
+/// Pairs up removed entities with added ones which have exactly the same
+/// attributes, giving the new name for each old one.
+///
+/// A pair only counts when neither entity could be paired with anything else.
+fn renames<'a>(
+    old: &'a ER,
+    new: &'a ER,
+    old_entities: &HashMap<&str, usize>,
+    new_entities: &HashMap<&str, usize>,
+) -> HashMap<&'a str, &'a str> {
+    let fields = |e: &Entity| {
+        let mut fields: Vec<(String, bool, bool)> = e
+            .attribs
+            .iter()
+            .map(|a| (a.field.clone(), a.pk, a.fk))
+            .collect();
+        fields.sort();
+        fields
+    };
+    let removed: Vec<&Entity> = old
+        .entities
+        .iter()
+        .filter(|e| !e.attribs.is_empty() && !new_entities.contains_key(e.name.as_str()))
+        .collect();
+    let added: Vec<&Entity> = new
+        .entities
+        .iter()
+        .filter(|e| !e.attribs.is_empty() && !old_entities.contains_key(e.name.as_str()))
+        .collect();
+
+    let mut renames = HashMap::new();
+    for old_entity in &removed {
+        let matches = |a: &Entity, b: &Entity| fields(a) == fields(b);
+        let candidates: Vec<_> = added.iter().filter(|e| matches(old_entity, e)).collect();
+        if let [new_entity] = candidates.as_slice() {
+            let rivals = removed.iter().filter(|e| matches(e, new_entity)).count();
+            if rivals == 1 {
+                renames.insert(old_entity.name.as_str(), new_entity.name.as_str());
+            }
+        }
+    }
+    renames
+}
+
+fn diff_entity(old: &Entity, new: &Entity, changes: &mut Vec<Change>) {
+    let entity = &new.name;
+    // The options written on an entity apply to both its header and its
+    // body, so the same change usually turns up in each.
+    let item = Item::Entity {
+        entity: entity.clone(),
+    };
+    let mut option_changes = vec![];
+    diff_options(
+        item.clone(),
+        &old.hoptions,
+        &new.hoptions,
+        &mut option_changes,
+    );
+    diff_options(item, &old.eoptions, &new.eoptions, &mut option_changes);
+    for change in option_changes {
+        if !changes.contains(&change) {
+            changes.push(change);
+        }
+    }
+
+    let find = |attribs: &'_ [Attribute], field: &str| -> Option<usize> {
+        attribs.iter().position(|a| a.field == field)
+    };
+    for (i, attr) in old.attribs.iter().enumerate() {
+        if find(&old.attribs, &attr.field) != Some(i) {
+            continue;
+        }
+        if find(&new.attribs, &attr.field).is_none() {
+            changes.push(Change::AttributeRemoved {
+                entity: entity.clone(),
+                field: attr.field.clone(),
+            });
+        }
+    }
+    for (i, attr) in new.attribs.iter().enumerate() {
+        if find(&new.attribs, &attr.field) != Some(i) {
+            continue;
+        }
+        let old_attr = match find(&old.attribs, &attr.field) {
+            Some(j) => &old.attribs[j],
+            None => {
+                changes.push(Change::AttributeAdded {
+                    entity: entity.clone(),
+                    field: attr.field.clone(),
+                });
+                continue;
+            }
+        };
+        if old_attr.pk != attr.pk {
+            changes.push(Change::PrimaryKeyChanged {
+                entity: entity.clone(),
+                field: attr.field.clone(),
+                pk: attr.pk,
+            });
+        }
+        if old_attr.fk != attr.fk {
+            changes.push(Change::ForeignKeyChanged {
+                entity: entity.clone(),
+                field: attr.field.clone(),
+                fk: attr.fk,
+            });
+        }
+        diff_options(
+            Item::Attribute {
+                entity: entity.clone(),
+                field: attr.field.clone(),
+            },
+            &old_attr.options,
+            &attr.options,
+            changes,
+        );
+    }
+}
+
+/// Matches up relationships by the entities they join, renaming the ends of
+/// the old ones first so a renamed entity doesn't take its relationships
+/// with it.
+fn diff_relations(old: &ER, new: &ER, renames: &HashMap<&str, &str>, changes: &mut Vec<Change>) {
+    let rename = |name: &str| renames.get(name).copied().unwrap_or(name).to_string();
+    let mut unmatched: Vec<_> = new.rels.iter().map(Some).collect();
+
+    for rel in &old.rels {
+        let (entity1, entity2) = (rename(&rel.entity1), rename(&rel.entity2));
+        let found = unmatched.iter_mut().find_map(|slot| {
+            let new_rel = (*slot)?;
+            let cards = if new_rel.entity1 == entity1 && new_rel.entity2 == entity2 {
+                (rel.card1, rel.card2)
+            } else if new_rel.entity1 == entity2 && new_rel.entity2 == entity1 {
+                (rel.card2, rel.card1)
+            } else {
+                return None;
+            };
+            *slot = None;
+            Some((new_rel, cards))
+        });
+
+        let (new_rel, from) = match found {
+            Some(found) => found,
+            None => {
+                changes.push(Change::RelationRemoved {
+                    entity1,
+                    entity2,
+                    card1: rel.card1,
+                    card2: rel.card2,
+                });
+                continue;
+            }
+        };
+        let to = (new_rel.card1, new_rel.card2);
+        if from != to {
+            changes.push(Change::CardinalityChanged {
+                entity1: new_rel.entity1.clone(),
+                entity2: new_rel.entity2.clone(),
+                from,
+                to,
+            });
+        }
+        diff_options(
+            Item::Relation {
+                entity1: new_rel.entity1.clone(),
+                entity2: new_rel.entity2.clone(),
+            },
+            &rel.options,
+            &new_rel.options,
+            changes,
+        );
+    }
+
+    for rel in unmatched.into_iter().flatten() {
+        changes.push(Change::RelationAdded {
+            entity1: rel.entity1.clone(),
+            entity2: rel.entity2.clone(),
+            card1: rel.card1,
+            card2: rel.card2,
+        });
+    }
+}
+
+fn diff_options(item: Item, old: &Options, new: &Options, changes: &mut Vec<Change>) {
+    let value = |options: &Options, name: &str| {
+        options
+            .iter()
+            .find(|opt| opt.html_attr_name() == name)
+            .map(|opt| opt.value())
+    };
+    for name in &OPTION_NAMES {
+        let (from, to) = (value(old, name), value(new, name));
+        if from != to {
+            changes.push(Change::OptionChanged {
+                item: item.clone(),
+                name: name.to_string(),
+                from,
+                to,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+
+    fn changes(old: &str, new: &str) -> Vec<String> {
+        let diff = diff(&parse(old).unwrap(), &parse(new).unwrap());
+        diff.to_string().lines().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn test_no_changes() {
+        let old = "[Person]\n*id\nname\n[Place]\n*id\nPerson *--1 Place\n";
+        let new = "# Reordered.\n[Person]\n  name\n  *id\n\n[Place]\n  *id\n\nPlace 1--* Person\n";
+        assert!(diff(&parse(old).unwrap(), &parse(new).unwrap()).is_empty());
+    }
+
+    #[test]
+    fn test_entities_and_attributes() {
+        let old = "[Person]\n*id\nname\nage\n[Pet]\nname\n";
+        let new = "[Person]\n*id\n*name\n+place_id\nbirthday\n[Place]\n*id\n";
+        assert_eq!(
+            changes(old, new),
+            vec![
+                "- attribute `age` in `Person`",
+                "~ attribute `name` in `Person` is now part of the primary key",
+                "+ attribute `place_id` in `Person`",
+                "+ attribute `birthday` in `Person`",
+                "- entity `Pet`",
+                "+ entity `Place`",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_renamed_entity() {
+        let old = "[Person]\n*id\n[Place]\n*id\nname\nPerson *--1 Place\n";
+        let new = "[Person]\n*id\n[Location]\nname\n*id\nLocation 1--+ Person\n";
+        assert_eq!(
+            changes(old, new),
+            vec![
+                "~ entity `Place` renamed to `Location`",
+                "~ relationship between `Location` and `Person` changed from 1--* to 1--+",
+            ]
+        );
+
+        // Too ambiguous to call a rename.
+        let new = "[Person]\n*id\n[A]\n*id\nname\n[B]\n*id\nname\n";
+        assert_eq!(
+            changes(old, new)[..2],
+            ["- entity `Place`".to_string(), "+ entity `A`".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_relations() {
+        let old = "[A]\n[B]\n[C]\nA 1--* B\nB 1--1 C\n";
+        let new = "[A]\n[B]\n[C]\nA 1--* B {label: \"has\"}\nA ?--? C\n";
+        assert_eq!(
+            changes(old, new),
+            vec![
+                "~ option `label` on the relationship between `A` and `B` set to \"has\"",
+                "- relationship `B` 1--1 `C`",
+                "+ relationship `A` ?--? `C`",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_options() {
+        let old = "title {label: \"old\"}\n[A] {bgcolor: \"#fff\"}\nx {color: \"red\"}\n";
+        let new = "title {label: \"new\"}\nentity {size: \"12\"}\n[A]\nx {color: \"red\", size: \"10\"}\n";
+        assert_eq!(
+            changes(old, new),
+            vec![
+                "~ option `label` on the `title` directive changed from \"old\" to \"new\"",
+                "~ option `size` on the `entity` directive set to \"12\"",
+                "~ option `bgcolor` on entity `A` unset (was \"#fff\")",
+                "~ option `size` on attribute `x` in `A` set to \"10\"",
+            ]
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_json() {
+        let old = parse("[A]\n[B]\nA 1--* B\n").unwrap();
+        let new = parse("[A]\n[B]\nA ?--+ B\n").unwrap();
+        let json = serde_json::to_value(diff(&old, &new)).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "changes": [{
+                    "change": "cardinality_changed",
+                    "entity1": "A",
+                    "entity2": "B",
+                    "from": ["1", "*"],
+                    "to": ["?", "+"],
+                }]
+            })
+        );
+    }
+}
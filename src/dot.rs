@@ -0,0 +1,648 @@
+//! Renders an [ER](../er/struct.ER.html) as a graphviz dot document.
+//!
+//! The output follows the haskell erd closely: each entity becomes a node with
+//! an html-like table for a label (the entity name in a header row, followed
+//! by a row per attribute), and each relationship becomes an undirected edge
+//! with the cardinality of each side written at either end.
+//!
+//! [render_diff](fn.render_diff.html) draws two versions of a diagram as one,
+//! coloring whatever was added, removed or changed. The colors are injected as
+//! ordinary options on a merged copy of the diagram, so they're rendered the
+//! same way as any other.
+//!
+//! We don't have a dot parser on hand, so option values are passed through
+//! as-is and it's left to the `dot` cli to complain about anything it doesn't
+//! like (an unknown color name, for example).
+
+use crate::diff::{diff, Change};
+use crate::er::{
+    opt_to_font, opt_to_html, opt_to_label, options_to, Attribute, Entity, Opt, Options, Relation,
+    ER,
+};
+use crate::Result;
+use std::collections::HashSet;
+use std::io::Write;
+
+/// The font and background colors for things which were added by a change.
+pub const ADDED_COLORS: (&str, &str) = ("darkgreen", "palegreen");
+/// The font and background colors for things which were removed by a change.
+pub const REMOVED_COLORS: (&str, &str) = ("red3", "mistyrose");
+/// The font and background colors for things which were changed.
+pub const CHANGED_COLORS: (&str, &str) = ("darkorange3", "moccasin");
+
+impl ER {
+    /// Renders the diagram as a graphviz dot document.
+    ///
+    /// See [render](../dot/fn.render.html).
+    pub fn to_dot(&self) -> String {
+        let mut buf = vec![];
+        render(self, &mut buf).expect("writing to a Vec can't fail");
+        String::from_utf8(buf).expect("dot output is always utf-8")
+    }
+}
+
+/// Writes the dot representation of `er` to `out`.
+pub fn render<W: Write>(er: &ER, out: W) -> Result<()> {
+    render_with(er, &Marks::default(), out)
+}
+
+/// Writes a single diagram showing the changes from `old` to `new` to `out`.
+///
+/// The diagram is `new`, plus anything which was only in `old`. Entities,
+/// attributes and relationships which were added are colored green, those
+/// which were removed are colored red and struck through, and renamed
+/// entities, changed keys and changed cardinalities are colored orange.
+pub fn render_diff<W: Write>(old: &ER, new: &ER, out: W) -> Result<()> {
+    let (er, marks) = merge(old, new);
+    render_with(&er, &marks, out)
+}
+
+/// Things to draw differently which can't be expressed with options.
+#[derive(Default)]
+struct Marks {
+    /// Entities whose names are struck through.
+    struck_entities: HashSet<String>,
+    /// Attributes (by entity and field) which are struck through.
+    struck_attributes: HashSet<(String, String)>,
+    /// Relationships (by index) whose font color is used for their line as
+    /// well.
+    colored_edges: HashSet<usize>,
+}
+
+fn render_with<W: Write>(er: &ER, marks: &Marks, mut out: W) -> Result<()> {
+    writeln!(out, "graph {{")?;
+
+    let title = er.effective_title_options();
+    if let Some(label) = label(&title) {
+        writeln!(
+            out,
+            "    graph [labeljust=l; labelloc=t; label=<{}>];",
+            font(&title, &escape_html(&label))
+        )?;
+    }
+    writeln!(out, "    graph [rankdir=LR];")?;
+    writeln!(out, "    node [shape=plaintext];")?;
+    writeln!(out, "    edge [color=gray50; minlen=2; style=dashed];")?;
+    writeln!(out)?;
+
+    for entity in &er.entities {
+        writeln!(
+            out,
+            "    {} [label=<{}>{}];",
+            quote_id(&entity.name),
+            entity_table(er, entity, marks),
+            tooltip(entity.doc())
+        )?;
+    }
+
+    if !er.rels.is_empty() {
+        writeln!(out)?;
+    }
+
+    for (i, rel) in er.rels.iter().enumerate() {
+        writeln!(
+            out,
+            "    {} -- {} [{}];",
+            quote_id(&rel.entity1),
+            quote_id(&rel.entity2),
+            edge_attrs(er, rel, marks.colored_edges.contains(&i))
+        )?;
+    }
+
+    writeln!(out, "}}")?;
+    Ok(())
+}
+
+/// Builds the html-like table used as the label for an entity's node.
+fn entity_table(er: &ER, entity: &Entity, marks: &Marks) -> String {
+    let eopts = er.effective_entity_options(entity);
+    let hopts = er.effective_header_options(entity);
+
+    let mut header = escape_html(&entity.name);
+    if let Some(label) = label(&hopts) {
+        header.push_str(&format!(" ({})", escape_html(&label)));
+    }
+    if marks.struck_entities.contains(&entity.name) {
+        header = format!("<S>{}</S>", header);
+    }
+
+    let mut rows = format!(
+        "<TR><TD{}><B>{}</B></TD></TR>",
+        html_attrs(&hopts, false),
+        font(&hopts, &header)
+    );
+    for attr in &entity.attribs {
+        let struck = marks
+            .struck_attributes
+            .contains(&(entity.name.clone(), attr.field.clone()));
+        rows.push_str(&attribute_row(er, attr, struck));
+    }
+
+    font(
+        &eopts,
+        &format!("<TABLE{}>{}</TABLE>", html_attrs(&eopts, true), rows),
+    )
+}
+
+/// Builds a table row for an attribute.
+///
+/// Primary keys are underlined and foreign keys are italicized.
+fn attribute_row(er: &ER, attr: &Attribute, struck: bool) -> String {
+    let opts = er.effective_attribute_options(attr);
+
+    let mut text = escape_html(&attr.field);
+    if let Some(label) = label(&opts) {
+        text.push_str(&format!(" [{}]", escape_html(&label)));
+    }
+    if attr.pk {
+        text = format!("<U>{}</U>", text);
+    }
+    if attr.fk {
+        text = format!("<I>{}</I>", text);
+    }
+    if struck {
+        text = format!("<S>{}</S>", text);
+    }
+
+    let mut attrs = html_attrs(&opts, false);
+    if let Some(doc) = attr.doc() {
+        attrs.push_str(&format!(
+            r#" TOOLTIP="{}""#,
+            escape_html(doc).replace('\n', "&#10;")
+        ));
+    }
+    format!("<TR><TD{}>{}</TD></TR>", attrs, font(&opts, &text))
+}
+
+/// Builds the attribute list for a relationship's edge, drawing its line in
+/// its font color when `colored`.
+fn edge_attrs(er: &ER, rel: &Relation, colored: bool) -> String {
+    let opts = er.effective_relation_options(rel);
+
+    let mut attrs = vec![
+        format!("taillabel={}", quote_id(&rel.card1.to_string())),
+        format!("headlabel={}", quote_id(&rel.card2.to_string())),
+    ];
+    if let Some(label) = label(&opts) {
+        attrs.push(format!("label={}", quote_id(&label)));
+    }
+    if let Some(doc) = rel.doc() {
+        attrs.push(format!("tooltip={}", quote_id(doc).replace('\n', "\\n")));
+    }
+    for opt in options_to(opt_to_font, &opts).iter() {
+        attrs.push(match opt {
+            Opt::Color(c) => format!("fontcolor={}", quote_id(&c)),
+            Opt::FontFace(f) => format!("fontname={}", quote_id(&f)),
+            Opt::FontSize(s) => format!("fontsize={}", s),
+            _ => unreachable!(),
+        });
+    }
+    if colored {
+        if let Some(color) = opts.color() {
+            attrs.push(format!("color={}; style=solid", quote_id(color)));
+        }
+    }
+    attrs.join("; ")
+}
+
+/// Builds the diagram drawn by [render_diff](fn.render_diff.html): `new`,
+/// with whatever was removed from `old` put back and everything which changed
+/// colored.
+fn merge(old: &ER, new: &ER) -> (ER, Marks) {
+    let mut er = new.clone();
+    let mut marks = Marks::default();
+    // Relationships which have already been colored, so that a second
+    // relationship between the same entities gets its own change.
+    let mut colored = vec![false; er.rels.len()];
+    let mut restored = vec![false; old.rels.len()];
+
+    let diff = diff(old, new);
+    let renamed = |name: &str| {
+        diff.changes()
+            .iter()
+            .find_map(|change| match change {
+                Change::EntityRenamed { from, to } if from == name => Some(to.clone()),
+                _ => None,
+            })
+            .unwrap_or_else(|| name.to_string())
+    };
+    for change in diff.changes() {
+        match change {
+            Change::EntityAdded { entity } => {
+                if let Some(entity) = find_entity(&mut er, entity) {
+                    color_header(entity, ADDED_COLORS);
+                    for attr in &mut entity.attribs {
+                        color(&mut attr.options, ADDED_COLORS);
+                    }
+                }
+            }
+            Change::EntityRemoved { entity: name } => {
+                let mut entity = match old.entities.iter().find(|e| &e.name == name) {
+                    Some(entity) => entity.clone(),
+                    None => continue,
+                };
+                color_header(&mut entity, REMOVED_COLORS);
+                for attr in &mut entity.attribs {
+                    color(&mut attr.options, REMOVED_COLORS);
+                    marks
+                        .struck_attributes
+                        .insert((name.clone(), attr.field.clone()));
+                }
+                marks.struck_entities.insert(name.clone());
+                er.entities.push(entity);
+            }
+            Change::EntityRenamed { to, .. } => {
+                if let Some(entity) = find_entity(&mut er, to) {
+                    color_header(entity, CHANGED_COLORS);
+                }
+            }
+            Change::AttributeAdded { entity, field } => {
+                if let Some(attr) = find_attribute(&mut er, entity, field) {
+                    color(&mut attr.options, ADDED_COLORS);
+                }
+            }
+            Change::AttributeRemoved {
+                entity: name,
+                field,
+            } => {
+                // Entities are only taken as renamed when none of their
+                // attributes changed, so this one has the same name as
+                // before.
+                let old_attr = old
+                    .entities
+                    .iter()
+                    .find(|e| &e.name == name)
+                    .and_then(|e| e.attribs.iter().find(|a| &a.field == field));
+                let (entity, old_attr) = match (find_entity(&mut er, name), old_attr) {
+                    (Some(entity), Some(old_attr)) => (entity, old_attr),
+                    _ => continue,
+                };
+                let mut attr = old_attr.clone();
+                color(&mut attr.options, REMOVED_COLORS);
+                entity.attribs.push(attr);
+                marks
+                    .struck_attributes
+                    .insert((name.clone(), field.clone()));
+            }
+            Change::PrimaryKeyChanged { entity, field, .. }
+            | Change::ForeignKeyChanged { entity, field, .. } => {
+                if let Some(attr) = find_attribute(&mut er, entity, field) {
+                    color(&mut attr.options, CHANGED_COLORS);
+                }
+            }
+            Change::RelationAdded {
+                entity1,
+                entity2,
+                card1,
+                card2,
+            } => {
+                let found = er.rels.iter().enumerate().position(|(i, rel)| {
+                    !colored[i]
+                        && &rel.entity1 == entity1
+                        && &rel.entity2 == entity2
+                        && rel.card1 == *card1
+                        && rel.card2 == *card2
+                });
+                if let Some(i) = found {
+                    colored[i] = true;
+                    color(&mut er.rels[i].options, ADDED_COLORS);
+                }
+            }
+            Change::RelationRemoved {
+                entity1,
+                entity2,
+                card1,
+                card2,
+            } => {
+                let found = old.rels.iter().enumerate().position(|(i, rel)| {
+                    !restored[i]
+                        && &renamed(&rel.entity1) == entity1
+                        && &renamed(&rel.entity2) == entity2
+                        && rel.card1 == *card1
+                        && rel.card2 == *card2
+                });
+                let mut rel = match found {
+                    Some(i) => {
+                        restored[i] = true;
+                        old.rels[i].clone()
+                    }
+                    None => continue,
+                };
+                rel.entity1 = entity1.clone();
+                rel.entity2 = entity2.clone();
+                color(&mut rel.options, REMOVED_COLORS);
+                er.rels.push(rel);
+                colored.push(true);
+            }
+            Change::CardinalityChanged {
+                entity1, entity2, ..
+            } => {
+                let found = er.rels.iter().enumerate().position(|(i, rel)| {
+                    !colored[i] && &rel.entity1 == entity1 && &rel.entity2 == entity2
+                });
+                if let Some(i) = found {
+                    colored[i] = true;
+                    color(&mut er.rels[i].options, CHANGED_COLORS);
+                }
+            }
+            Change::OptionChanged { .. } => {}
+        }
+    }
+    marks.colored_edges = (0..colored.len()).filter(|&i| colored[i]).collect();
+    (er, marks)
+}
+
+fn find_entity<'e>(er: &'e mut ER, name: &str) -> Option<&'e mut Entity> {
+    er.entities.iter_mut().find(|e| e.name == name)
+}
+
+fn find_attribute<'e>(er: &'e mut ER, entity: &str, field: &str) -> Option<&'e mut Attribute> {
+    find_entity(er, entity)?
+        .attribs
+        .iter_mut()
+        .find(|a| a.field == field)
+}
+
+fn color_header(entity: &mut Entity, colors: (&str, &str)) {
+    color(&mut entity.hoptions, colors);
+}
+
+/// Sets the font and background colors, replacing any given in the er file.
+fn color(options: &mut Options, (font, background): (&str, &str)) {
+    options.set(Opt::Color(font.to_string()));
+    options.set(Opt::BgColor(background.to_string()));
+}
+
+/// Builds the `tooltip` attribute for a node from its doc comment, when it has
+/// one.
+fn tooltip(doc: Option<&str>) -> String {
+    match doc {
+        Some(doc) => format!("; tooltip={}", quote_id(doc).replace('\n', "\\n")),
+        None => String::new(),
+    }
+}
+
+/// Pulls the text of the label out of a set of options, if there is one.
+fn label(opts: &Options) -> Option<String> {
+    options_to(opt_to_label, opts)
+        .iter()
+        .find_map(|opt| match opt {
+            Opt::Label(text) => Some(text),
+            _ => None,
+        })
+}
+
+/// Wraps `html` in a `<FONT>` tag carrying any font options, or returns it
+/// unchanged when there are none.
+fn font(opts: &Options, html: &str) -> String {
+    let attrs: String = options_to(opt_to_font, opts)
+        .iter()
+        .map(|opt| match opt {
+            Opt::Color(c) => format!(r#" COLOR="{}""#, escape_html(&c)),
+            Opt::FontFace(f) => format!(r#" FACE="{}""#, escape_html(&f)),
+            Opt::FontSize(s) => format!(r#" POINT-SIZE="{}""#, s),
+            _ => unreachable!(),
+        })
+        .collect();
+
+    if attrs.is_empty() {
+        html.to_string()
+    } else {
+        format!("<FONT{}>{}</FONT>", attrs, html)
+    }
+}
+
+/// Renders the html options as attributes for a `<TABLE>` or `<TD>` tag.
+///
+/// `CELLBORDER` is only valid on tables so it's dropped for cells.
+fn html_attrs(opts: &Options, table: bool) -> String {
+    options_to(opt_to_html, opts)
+        .iter()
+        .filter_map(|opt| {
+            let (name, value) = match opt {
+                Opt::BgColor(c) => ("BGCOLOR", escape_html(&c)),
+                Opt::Border(n) => ("BORDER", n.to_string()),
+                Opt::BorderColor(c) => ("COLOR", escape_html(&c)),
+                Opt::CellSpacing(n) => ("CELLSPACING", n.to_string()),
+                Opt::CellBorder(n) if table => ("CELLBORDER", n.to_string()),
+                Opt::CellBorder(_) => return None,
+                Opt::CellPadding(n) => ("CELLPADDING", n.to_string()),
+                Opt::TextAlignment(a) => ("ALIGN", escape_html(&a)),
+                _ => unreachable!(),
+            };
+            Some(format!(r#" {}="{}""#, name, value))
+        })
+        .collect()
+}
+
+/// Quotes a string for use as a dot ID.
+fn quote_id(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Escapes text for inclusion in an html-like label.
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::render_diff;
+    use crate::parser::parse;
+
+    #[test]
+    fn test_render_basics_sample() {
+        let er = parse(include_str!("../samples/basics.erd")).unwrap();
+        let dot = er.to_dot();
+
+        assert!(dot.starts_with("graph {\n"));
+        assert!(dot.ends_with("}\n"));
+        assert!(dot.contains(r#""Person" [label=<"#));
+        assert!(dot.contains(r#""Birth Place" [label=<"#));
+        assert!(dot.contains(r#""Person" -- "Birth Place" [taillabel="0..N"; headlabel="1"];"#));
+    }
+
+    #[test]
+    fn test_render_keys() {
+        let er = parse(
+            r#"
+            [Person]
+                *name
+                +group_id
+                *+both
+                plain
+            "#,
+        )
+        .unwrap();
+        let dot = er.to_dot();
+
+        assert!(dot.contains("<U>name</U>"));
+        assert!(dot.contains("<I>group_id</I>"));
+        assert!(dot.contains("<I><U>both</U></I>"));
+        assert!(dot.contains(r#"<TD ALIGN="LEFT">plain</TD>"#));
+    }
+
+    #[test]
+    fn test_render_header_uses_default_and_local_options() {
+        let er = parse(
+            r##"
+            [Person] { bgcolor: "#d0e0d0" }
+                name { label: "string" }
+            "##,
+        )
+        .unwrap();
+        let dot = er.to_dot();
+
+        assert!(dot.contains(
+            r##"<TR><TD BGCOLOR="#d0e0d0"><B><FONT POINT-SIZE="16">Person</FONT></B></TD></TR>"##
+        ));
+        assert!(dot.contains("name [string]"));
+        assert!(dot.contains(
+            r##"<FONT FACE="Helvetica"><TABLE BGCOLOR="#d0e0d0" BORDER="0" CELLSPACING="0" CELLBORDER="1" CELLPADDING="4">"##
+        ));
+    }
+
+    #[test]
+    fn test_render_title() {
+        let er = parse(r#"title { label: "People & Places" }"#).unwrap();
+        let dot = er.to_dot();
+
+        assert!(dot.contains(r#"label=<<FONT POINT-SIZE="30">People &amp; Places</FONT>>"#));
+    }
+
+    #[test]
+    fn test_render_relationship_label() {
+        let er = parse(r#"Person *--1 `Birth Place` { label: "born in" }"#).unwrap();
+        let dot = er.to_dot();
+
+        assert!(dot.contains(
+            r#""Person" -- "Birth Place" [taillabel="0..N"; headlabel="1"; label="born in"];"#
+        ));
+    }
+
+    #[test]
+    fn test_render_uses_global_options() {
+        let er = parse(
+            r##"
+            header { bgcolor: "#ececfc" }
+            entity { font: "Courier" }
+            relationship { color: "red" }
+
+            [Person] { bgcolor: "#d0e0d0" }
+                name
+            [Group]
+                id
+            Person *--* Group
+            "##,
+        )
+        .unwrap();
+        let dot = er.to_dot();
+
+        assert!(dot.contains(r##"<TD BGCOLOR="#d0e0d0"><B>"##));
+        assert!(dot.contains(r##"<TD BGCOLOR="#ececfc"><B>"##));
+        assert!(!dot.contains("Helvetica"));
+        assert!(dot.contains(r#"<FONT FACE="Courier"><TABLE"#));
+        assert!(dot.contains(r#"fontcolor="red""#));
+    }
+
+    #[test]
+    fn test_render_diff() {
+        let old = parse(
+            r#"
+            [Person]
+                *name
+                age
+            [Pet]
+                name
+            [Place]
+                *id
+            Person *--1 Place
+            Person 1--* Pet
+            "#,
+        )
+        .unwrap();
+        let new = parse(
+            r#"
+            [Person]
+                *name
+                +place_id
+            [Place]
+                *id
+            [Company]
+                *id
+            Person *--? Place
+            Person *--1 Company
+            "#,
+        )
+        .unwrap();
+        let mut buf = vec![];
+        render_diff(&old, &new, &mut buf).unwrap();
+        let dot = String::from_utf8(buf).unwrap();
+
+        assert!(dot.contains(
+            r#"<TR><TD BGCOLOR="palegreen"><B><FONT COLOR="darkgreen" POINT-SIZE="16">Company</FONT></B></TD></TR>"#
+        ));
+        assert!(dot.contains(
+            r#"<TD BGCOLOR="palegreen" ALIGN="LEFT"><FONT COLOR="darkgreen"><I>place_id</I></FONT></TD>"#
+        ));
+        assert!(dot.contains(
+            r#"<TD BGCOLOR="mistyrose" ALIGN="LEFT"><FONT COLOR="red3"><S>age</S></FONT></TD>"#
+        ));
+        assert!(dot.contains(r#"<FONT COLOR="red3" POINT-SIZE="16"><S>Pet</S></FONT>"#));
+        assert!(dot.contains(r#"<FONT COLOR="red3"><S>name</S></FONT>"#));
+        assert!(dot.contains(
+            r#""Person" -- "Place" [taillabel="0..N"; headlabel="{0,1}"; fontcolor="darkorange3"; color="darkorange3"; style=solid];"#
+        ));
+        assert!(dot.contains(
+            r#""Person" -- "Company" [taillabel="0..N"; headlabel="1"; fontcolor="darkgreen"; color="darkgreen"; style=solid];"#
+        ));
+        assert!(dot.contains(
+            r#""Person" -- "Pet" [taillabel="1"; headlabel="0..N"; fontcolor="red3"; color="red3"; style=solid];"#
+        ));
+        // Unchanged things are drawn as usual.
+        assert!(dot.contains(r#"<TD ALIGN="LEFT"><U>name</U></TD>"#));
+    }
+
+    #[test]
+    fn test_render_diff_leaves_unchanged_edges() {
+        let old = parse("relationship { color: \"blue\" }\n[A]\n[B]\nA *--1 B\n").unwrap();
+        let new =
+            parse("relationship { color: \"blue\" }\n[A]\n[B]\n[C]\nA *--1 B\nA *--1 C\n").unwrap();
+        let mut buf = vec![];
+        render_diff(&old, &new, &mut buf).unwrap();
+        let dot = String::from_utf8(buf).unwrap();
+
+        assert!(
+            dot.contains(r#""A" -- "B" [taillabel="0..N"; headlabel="1"; fontcolor="blue"];"#),
+            "{}",
+            dot
+        );
+        assert!(dot.contains(
+            r#""A" -- "C" [taillabel="0..N"; headlabel="1"; fontcolor="darkgreen"; color="darkgreen"; style=solid];"#
+        ));
+    }
+
+    #[test]
+    fn test_render_doc_comments_as_tooltips() {
+        let er = parse(
+            r#"
+            ## People.
+            ## All of them.
+            [Person]
+                ## What they're called.
+                *name
+            ## Where they were born.
+            Person *--1 Place
+            "#,
+        )
+        .unwrap();
+        let dot = er.to_dot();
+
+        assert!(dot.contains(r#"</TABLE></FONT>>; tooltip="People.\nAll of them."];"#));
+        assert!(dot.contains(r#"<TD ALIGN="LEFT" TOOLTIP="What they're called.">"#));
+        assert!(dot.contains(r#"headlabel="1"; tooltip="Where they were born."];"#));
+    }
+}
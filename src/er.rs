@@ -6,28 +6,171 @@
 //! do with the structure of the diagram (our final output will be a dot
 //! language representation of this).
 
-use crate::{Error, Result};
+pub use crate::diff::{diff, ErDiff};
+use crate::{Error, FloatError, Result, Span};
 use std::cmp::Ordering;
-use std::collections::hash_map::{Entry, HashMap};
 use std::fmt::{Display, Formatter};
+use std::str::FromStr;
 
 /// Represents a single schema.
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct ER {
-    entities: Vec<Entity>,
-    rels: Vec<Relation>,
-    title: Options,
+    pub(crate) entities: Vec<Entity>,
+    pub(crate) rels: Vec<Relation>,
+    /// The options given by the directives in the head of the document.
+    pub(crate) globals: GlobalOptions,
+}
+
+impl ER {
+    /// The entities, in the order they were written.
+    pub fn entities(&self) -> &[Entity] {
+        &self.entities
+    }
+
+    /// The relationships, in the order they were written.
+    pub fn relations(&self) -> &[Relation] {
+        &self.rels
+    }
+
+    /// The options given by the directives in the head of the document.
+    pub fn globals(&self) -> &GlobalOptions {
+        &self.globals
+    }
+
+    /// The entity named `name`, without any quotes.
+    ///
+    /// When the name is used more than once this is the first of them.
+    pub fn entity(&self, name: &str) -> Option<&Entity> {
+        self.entities.iter().find(|e| e.name == name)
+    }
+}
+
+/// Effective options are what's actually used when rendering an item: the
+/// item's own options, falling back to those given by the matching directive,
+/// falling back to the hard-coded defaults.
+impl ER {
+    /// The effective options for the title of the graph.
+    pub fn effective_title_options(&self) -> Options {
+        merge_opts(&self.globals.title, &default_title_opts())
+    }
+
+    /// The effective options for the header of `entity`.
+    pub fn effective_header_options(&self, entity: &Entity) -> Options {
+        merge_opts(
+            &entity.hoptions,
+            &merge_opts(&self.globals.header, &default_header_opts()),
+        )
+    }
+
+    /// The effective options for the body of `entity`.
+    pub fn effective_entity_options(&self, entity: &Entity) -> Options {
+        merge_opts(
+            &entity.eoptions,
+            &merge_opts(&self.globals.entity, &default_entity_opts()),
+        )
+    }
+
+    /// The effective options for `attr`.
+    ///
+    /// There's no directive for attributes, so these only fall back to the
+    /// defaults.
+    pub fn effective_attribute_options(&self, attr: &Attribute) -> Options {
+        merge_opts(&attr.options, &default_attr_opts())
+    }
+
+    /// The effective options for `rel`.
+    pub fn effective_relation_options(&self, rel: &Relation) -> Options {
+        merge_opts(
+            &rel.options,
+            &merge_opts(&self.globals.relationship, &default_rel_opts()),
+        )
+    }
+}
+
+/// Parses the text of an er file into an `ER`.
+///
+/// This is the same as calling [parser::parse](../parser/fn.parse.html).
+impl FromStr for ER {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        crate::parser::parse(s)
+    }
 }
 
 /// Represents a single entity in a schema.
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Debug)]
 pub struct Entity {
-    name: String,
-    attribs: Vec<Attribute>,
+    pub(crate) name: String,
+    pub(crate) attribs: Vec<Attribute>,
     /// Formatting options for the header.
-    hoptions: Options,
+    pub(crate) hoptions: Options,
     /// Formatting options for the entity "body."
-    eoptions: Options,
+    pub(crate) eoptions: Options,
+    /// Taken from the `##` comments just before the header.
+    pub(crate) doc: Option<String>,
+    /// Where the entity header (`[Name] {...}`) was written.
+    pub(crate) span: Span,
+    /// Where the name was written in the header, including any quotes.
+    pub(crate) name_span: Span,
+}
+
+/// Entities are compared by what they describe, not where they were written.
+impl PartialEq for Entity {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+            && self.attribs == other.attribs
+            && self.hoptions == other.hoptions
+            && self.eoptions == other.eoptions
+            && self.doc == other.doc
+    }
+}
+
+impl Eq for Entity {}
+
+impl Entity {
+    /// The name of the entity, without any quotes.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The attributes, in the order they were written.
+    pub fn attributes(&self) -> &[Attribute] {
+        &self.attribs
+    }
+
+    /// The formatting options given for the header.
+    pub fn header_options(&self) -> &Options {
+        &self.hoptions
+    }
+
+    /// The formatting options given for the entity body.
+    pub fn entity_options(&self) -> &Options {
+        &self.eoptions
+    }
+
+    /// The relationships in `er` with this entity at either end.
+    pub fn relations_in<'a>(&'a self, er: &'a ER) -> impl Iterator<Item = &'a Relation> {
+        er.rels
+            .iter()
+            .filter(move |rel| rel.entity1 == self.name || rel.entity2 == self.name)
+    }
+
+    /// The documentation given in `##` comments just before the entity
+    /// header, if any.
+    pub fn doc(&self) -> Option<&str> {
+        self.doc.as_deref()
+    }
+
+    /// Where the entity header (`[Name] {...}`) was written.
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    /// Where the name was written in the entity header, including any quotes.
+    pub fn name_span(&self) -> Span {
+        self.name_span
+    }
 }
 
 /// Default ordering for `Entity` (by name).
@@ -39,17 +182,67 @@ impl Ord for Entity {
 /// Default ordering for `Entity` (by name).
 impl PartialOrd for Entity {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.name.cmp(&other.name))
+        Some(self.cmp(other))
     }
 }
 
 /// Represents an attribute on a particular entity.
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Debug)]
 pub struct Attribute {
-    field: String,
-    pk: bool,
-    fk: bool,
-    options: Options,
+    pub(crate) field: String,
+    pub(crate) pk: bool,
+    pub(crate) fk: bool,
+    pub(crate) options: Options,
+    /// Taken from the `##` comments just before the attribute.
+    pub(crate) doc: Option<String>,
+    /// Where the attribute (including its options) was written.
+    pub(crate) span: Span,
+}
+
+/// Attributes are compared by what they describe, not where they were written.
+impl PartialEq for Attribute {
+    fn eq(&self, other: &Self) -> bool {
+        self.field == other.field
+            && self.pk == other.pk
+            && self.fk == other.fk
+            && self.options == other.options
+            && self.doc == other.doc
+    }
+}
+
+impl Eq for Attribute {}
+
+impl Attribute {
+    /// The name of the attribute, without any quotes.
+    pub fn name(&self) -> &str {
+        &self.field
+    }
+
+    /// Whether the attribute is part of the primary key (written with `*`).
+    pub fn is_pk(&self) -> bool {
+        self.pk
+    }
+
+    /// Whether the attribute is a foreign key (written with `+`).
+    pub fn is_fk(&self) -> bool {
+        self.fk
+    }
+
+    /// The formatting options given for the attribute.
+    pub fn options(&self) -> &Options {
+        &self.options
+    }
+
+    /// The documentation given in `##` comments just before the attribute,
+    /// if any.
+    pub fn doc(&self) -> Option<&str> {
+        self.doc.as_deref()
+    }
+
+    /// Where the attribute (including its options) was written.
+    pub fn span(&self) -> Span {
+        self.span
+    }
 }
 
 /// Default ordering for `Attribute` (by field name).
@@ -61,7 +254,7 @@ impl Ord for Attribute {
 /// Default ordering for `Attribute` (by field name).
 impl PartialOrd for Attribute {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.field.cmp(&other.field))
+        Some(self.cmp(other))
     }
 }
 
@@ -72,15 +265,77 @@ impl PartialOrd for Attribute {
 /// header section of the er file.
 /// The options will provide a fallback for each when rendering the various
 /// object types in the graph.
+#[derive(Clone, Debug, Default)]
 pub struct GlobalOptions {
     title: Options,
     header: Options,
     entity: Options,
     relationship: Options,
+    /// Where each directive was written, in document order.
+    pub(crate) spans: Vec<(Directive, Span)>,
 }
 
-/// Used as a key for the [GlobalOptions](type.GlobalOptions.html) type.
-#[derive(Debug, PartialEq)]
+/// Compared by the options given, not where the directives were written.
+impl PartialEq for GlobalOptions {
+    fn eq(&self, other: &Self) -> bool {
+        self.title == other.title
+            && self.header == other.header
+            && self.entity == other.entity
+            && self.relationship == other.relationship
+    }
+}
+
+impl Eq for GlobalOptions {}
+
+impl GlobalOptions {
+    /// Builds the global options from the directives in the head of a
+    /// document, in the order they were written.
+    ///
+    /// When a directive is repeated, options given later take precedence.
+    pub fn from_directives<I>(directives: I) -> Self
+    where
+        I: IntoIterator<Item = (Directive, Options)>,
+    {
+        let mut globals = GlobalOptions::default();
+        for (directive, options) in directives {
+            let slot = globals.get_mut(directive);
+            *slot = merge_opts(&options, slot);
+        }
+        globals
+    }
+
+    /// The options given for a directive.
+    pub fn get(&self, directive: Directive) -> &Options {
+        match directive {
+            Directive::Title => &self.title,
+            Directive::Header => &self.header,
+            Directive::Entity => &self.entity,
+            Directive::Relationship => &self.relationship,
+        }
+    }
+
+    /// Where each directive was written in the er file, in document order.
+    pub fn directive_spans(&self) -> &[(Directive, Span)] {
+        &self.spans
+    }
+
+    fn get_mut(&mut self, directive: Directive) -> &mut Options {
+        match directive {
+            Directive::Title => &mut self.title,
+            Directive::Header => &mut self.header,
+            Directive::Entity => &mut self.entity,
+            Directive::Relationship => &mut self.relationship,
+        }
+    }
+}
+
+/// Used as a key for the [GlobalOptions](struct.GlobalOptions.html) type.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(rename_all = "lowercase")
+)]
 pub enum Directive {
     Title,
     Header,
@@ -88,28 +343,203 @@ pub enum Directive {
     Relationship,
 }
 
-/// A collection of formatting options.
-#[derive(Debug)]
-// FIXME:
-//  Seems like the `Options`/`Opt` type might be all wrong.
-//  We need specific key names matched against specific value types.
-//  This sounds like a struct to me, but a struct would not give us the
-//  filter-ability needed to run selectors over without a common type for the
-//  values.
-//  Perhaps we need to change `Opt`s to carry a typed value in each
-//  variant. Each variant would correspond to a string name. The `Options` type
-//  would then become `HashMap<String, Opt>` (if still relevant).
-pub struct Options(HashMap<String, Opt>);
-
-impl PartialEq for Options {
-    fn eq(&self, other: &Self) -> bool {
-        self.0 == other.0
+impl Directive {
+    /// Every directive, in the order they're listed in the docs for the
+    /// haskell erd.
+    pub const ALL: [Directive; 4] = [
+        Directive::Title,
+        Directive::Header,
+        Directive::Entity,
+        Directive::Relationship,
+    ];
+
+    /// The name of the directive as written in the er file.
+    pub fn name(self) -> &'static str {
+        match self {
+            Directive::Title => "title",
+            Directive::Header => "header",
+            Directive::Entity => "entity",
+            Directive::Relationship => "relationship",
+        }
+    }
+}
+
+pub(crate) fn directive_by_name(name: &str) -> Option<Directive> {
+    use Directive::*;
+    match name {
+        "title" => Some(Title),
+        "header" => Some(Header),
+        "entity" => Some(Entity),
+        "relationship" => Some(Relationship),
+        _ => None,
+    }
+}
+
+/// Declares the `Options` struct with one field per `Opt` variant, along with
+/// the accessors needed to move between the two representations.
+///
+/// The order fields are listed in here is the order options are yielded by
+/// `Options::iter`, and therefore the order they appear in rendered output.
+macro_rules! options {
+    ($($(#[$doc:meta])* $field:ident: $variant:ident($ty:ty),)*) => {
+        /// A collection of formatting options.
+        ///
+        /// Each option can be set at most once. Setting an option that's
+        /// already set replaces its value.
+        #[derive(Clone, Debug, Default)]
+        pub struct Options {
+            $($field: Option<$ty>,)*
+            /// Where each option was written, keyed by the option name.
+            spans: Vec<(&'static str, Span)>,
+        }
+
+        /// Options are compared by their values, not where they were written.
+        impl PartialEq for Options {
+            fn eq(&self, other: &Self) -> bool {
+                true $(&& SameValue::same_value(&self.$field, &other.$field))*
+            }
+        }
+
+        impl Options {
+            $(
+                $(#[$doc])*
+                pub fn $field(&self) -> Option<&$ty> {
+                    self.$field.as_ref()
+                }
+            )*
+
+            /// Sets an option, returning the previous value if there was one.
+            pub fn set(&mut self, opt: Opt) -> Option<Opt> {
+                let name = opt.html_attr_name();
+                self.spans.retain(|(n, _)| *n != name);
+                match opt {
+                    $(Opt::$variant(value) => self.$field.replace(value).map(Opt::$variant),)*
+                }
+            }
+
+            /// Iterates over the options which have been set.
+            ///
+            /// The order is always the same regardless of the order the options
+            /// were set in.
+            pub fn iter(&self) -> impl Iterator<Item = Opt> {
+                vec![$(self.$field.clone().map(Opt::$variant),)*]
+                    .into_iter()
+                    .flatten()
+            }
+
+            /// Layers `self` over `fallback`, field by field.
+            fn merge(&self, fallback: &Options) -> Options {
+                let mut spans = self.spans.clone();
+                spans.extend(
+                    fallback
+                        .spans
+                        .iter()
+                        .filter(|(name, _)| !self.iter().any(|opt| opt.html_attr_name() == *name))
+                        .cloned(),
+                );
+                Options {
+                    $($field: self.$field.clone().or_else(|| fallback.$field.clone()),)*
+                    spans,
+                }
+            }
+        }
+    };
+}
+
+options! {
+    /// The text label.
+    label: Label(Text),
+    /// The background color.
+    bg_color: BgColor(Color),
+    /// The font color.
+    color: Color(Color),
+    /// The font face.
+    font_face: FontFace(Text),
+    /// The font size, in points.
+    font_size: FontSize(Double),
+    /// The border width.
+    border: Border(Word8),
+    /// The border color.
+    border_color: BorderColor(Color),
+    /// The spacing between cells.
+    cell_spacing: CellSpacing(Word8),
+    /// The cell border width.
+    cell_border: CellBorder(Word8),
+    /// The padding within cells.
+    cell_padding: CellPadding(Word8),
+    /// The horizontal alignment of text within cells.
+    text_alignment: TextAlignment(Align),
+}
+
+impl Options {
+    /// Returns `true` when no options have been set.
+    pub fn is_empty(&self) -> bool {
+        self.iter().next().is_none()
+    }
+
+    /// Where the option named `name` was written in the er file, if it was
+    /// set from one.
+    pub fn span(&self, name: &str) -> Option<Span> {
+        self.spans
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, span)| *span)
+    }
+
+    /// Sets an option, recording where it was written.
+    pub(crate) fn set_at(&mut self, opt: Opt, span: Span) -> Option<Opt> {
+        let name = opt.html_attr_name();
+        let prev = self.set(opt);
+        self.spans.push((name, span));
+        prev
     }
 }
 
+// Option values are compared with `SameValue`, which treats a `NaN` font size
+// as equal to itself, so the comparison is reflexive.
 impl Eq for Options {}
 
-type OptEntry<'m> = Entry<'m, String, Opt>;
+/// Equality for option values which, unlike `f64`'s, is reflexive.
+trait SameValue {
+    fn same_value(&self, other: &Self) -> bool;
+}
+
+impl SameValue for String {
+    fn same_value(&self, other: &Self) -> bool {
+        self == other
+    }
+}
+
+impl SameValue for Word8 {
+    fn same_value(&self, other: &Self) -> bool {
+        self == other
+    }
+}
+
+impl SameValue for Double {
+    fn same_value(&self, other: &Self) -> bool {
+        self.to_bits() == other.to_bits()
+    }
+}
+
+impl<T: SameValue> SameValue for Option<T> {
+    fn same_value(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Some(a), Some(b)) => a.same_value(b),
+            (a, b) => a.is_none() && b.is_none(),
+        }
+    }
+}
+
+impl std::iter::FromIterator<Opt> for Options {
+    fn from_iter<I: IntoIterator<Item = Opt>>(iter: I) -> Self {
+        let mut options = Options::default();
+        for opt in iter {
+            options.set(opt);
+        }
+        options
+    }
+}
 
 // The following type aliases are stubs matching the Haskell types (mostly).
 // In many cases, the types used to represent these formatting options are
@@ -148,7 +578,8 @@ type Word8 = u8;
 //  > centered.
 type Align = String;
 
-/// Used as a key in the [Options](type.Options.html) type.
+/// A single formatting option, as stored in the
+/// [Options](struct.Options.html) type.
 #[derive(Clone, Debug, PartialEq)]
 pub enum Opt {
     Label(Text),
@@ -165,8 +596,27 @@ pub enum Opt {
 }
 
 impl Opt {
+    /// The option's value as it would be written (less the quotes) in an er
+    /// file.
+    pub fn value(&self) -> String {
+        match self {
+            Opt::Label(s)
+            | Opt::BgColor(s)
+            | Opt::Color(s)
+            | Opt::FontFace(s)
+            | Opt::BorderColor(s)
+            | Opt::TextAlignment(s) => s.clone(),
+            Opt::FontSize(n) => n.to_string(),
+            Opt::Border(n) | Opt::CellSpacing(n) | Opt::CellBorder(n) | Opt::CellPadding(n) => {
+                n.to_string()
+            }
+        }
+    }
+
     /// The html attr name for the option.
-    fn html_attr_name(&self) -> &str {
+    ///
+    /// This is also the name used for the option in the er file.
+    pub fn html_attr_name(&self) -> &'static str {
         match self {
             Opt::Label(_) => "label",
             Opt::Color(_) => "color",
@@ -185,66 +635,102 @@ impl Opt {
 
 /// Given two sets of options, merge the second into first, where elements
 /// in the first take precedence.
-fn merge_opts(a: &Options, b: &Options) -> Options {
-    Options(
-        b.0.iter()
-            .chain(a.0.iter())
-            .map(|(key, val)| (key.clone(), val.clone()))
-            .collect(),
-    )
+///
+/// To layer more than two sets, merge from the top down:
+/// `merge_opts(&local, &merge_opts(&global, &defaults))`.
+pub(crate) fn merge_opts(a: &Options, b: &Options) -> Options {
+    a.merge(b)
 }
 
 /// Given a set of options and a selector function, return the list of
 /// only those options which matched. Examples of the selector function are
 /// `opt_to_font`, `opt_to_html` and `opt_to_label`.
-fn options_to<F>(selector: F, options: &Options) -> Options
+pub(crate) fn options_to<F>(selector: F, options: &Options) -> Options
 where
     F: Fn(&Opt) -> Option<&Opt>,
 {
-    Options(
-        options
-            .0
-            .iter()
-            .filter_map(|(name, opt)| selector(opt).map(|opt| (name.clone(), opt.clone())))
-            .collect(),
-    )
+    options
+        .iter()
+        .filter(|opt| selector(opt).is_some())
+        .collect()
 }
 
+/// The names of all the formatting options, as written in an er file.
+pub(crate) const OPTION_NAMES: [&str; 11] = [
+    "label",
+    "color",
+    "bgcolor",
+    "size",
+    "font",
+    "border",
+    "border-color",
+    "cellspacing",
+    "cellborder",
+    "cellpadding",
+    "text-alignment",
+];
+
 /// Given an option name and a string representation of its value,
 /// `option_by_name` will attempt to parse the string as a value corresponding
 /// to the option. If the option doesn't exist or there was a problem parsing
 /// the value, an error is returned.
-fn option_by_name(name: &str, value: &str) -> Result<Opt> {
-    let value = value; // FIXME: trim quotes
+///
+/// The value is expected to have already had its surrounding quotes removed.
+///
+/// Errors returned from here have no span; it's up to the caller to attach
+/// one if the value came from an er file.
+pub(crate) fn option_by_name(name: &str, value: &str) -> Result<Opt> {
+    let int = |value: &str| {
+        value.parse().map_err(|source| Error::InvalidInt {
+            name: name.to_string(),
+            source,
+            span: None,
+        })
+    };
+    let float = |value: &str| {
+        value.parse().map_err(|source| Error::InvalidFloat {
+            name: name.to_string(),
+            source: FloatError::Parse(source),
+            span: None,
+        })
+    };
     let parsed = match name {
         "label" => Opt::Label(value.to_string()),
         "color" => Opt::Color(value.to_string()),
         "bgcolor" => Opt::BgColor(value.to_string()),
-        "size" => Opt::FontSize(value.parse()?),
+        "size" => Opt::FontSize(font_size(float(value)?)?),
         "font" => Opt::FontFace(value.to_string()),
-        "border" => Opt::Border(value.parse()?),
+        "border" => Opt::Border(int(value)?),
         "border-color" => Opt::BorderColor(value.to_string()),
-        "cellspacing" => Opt::CellSpacing(value.parse()?),
-        "cellborder" => Opt::CellBorder(value.parse()?),
-        "cellpadding" => Opt::CellPadding(value.parse()?),
+        "cellspacing" => Opt::CellSpacing(int(value)?),
+        "cellborder" => Opt::CellBorder(int(value)?),
+        "cellpadding" => Opt::CellPadding(int(value)?),
         "text-alignment" => Opt::TextAlignment(value.to_string()),
-        _ => Err(Error::UnknownFormatOption(name.to_string()))?,
+        _ => {
+            return Err(Error::UnknownFormatOption {
+                name: name.to_string(),
+                span: None,
+            })
+        }
     };
     Ok(parsed)
 }
 
-/// A wrapper around the GraphViz's parser for any particular option.
-fn option_parse() -> Result<()> {
-    // FIXME: this one is probably not going to help us if we don't have a dot
-    //  parser. It also seems like our pest parser should already know how to
-    //  break up the quoted strings.
-    //  For us, we'll probably have to generate the dot blind and let the dot
-    //  cli handle this sort of validation.
-    unimplemented!()
+/// Checks that `size` is one graphviz can draw text at.
+pub(crate) fn font_size(size: Double) -> Result<Double> {
+    if size.is_finite() && size >= 0.0 {
+        Ok(size)
+    } else {
+        Err(Error::InvalidFloat {
+            name: "size".to_string(),
+            source: FloatError::OutOfRange,
+            span: None,
+        })
+    }
 }
 
 /// Selects an option if and only if it corresponds to a font attribute.
-fn opt_to_font(opt: &Opt) -> Option<&Opt> {
+pub(crate) fn opt_to_font(opt: &Opt) -> Option<&Opt> {
     use self::Opt::{Color, FontFace, FontSize};
     match opt {
         Color(_) | FontFace(_) | FontSize(_) => Some(opt),
@@ -254,7 +740,7 @@ fn opt_to_font(opt: &Opt) -> Option<&Opt> {
 
 /// Selects an option if and only if it corresponds to an HTML attribute.
 /// In particular, for tables or table cells.
-fn opt_to_html(opt: &Opt) -> Option<&Opt> {
+pub(crate) fn opt_to_html(opt: &Opt) -> Option<&Opt> {
     use self::Opt::{
         BgColor, Border, BorderColor, CellBorder, CellPadding, CellSpacing, TextAlignment,
     };
@@ -266,29 +752,92 @@ fn opt_to_html(opt: &Opt) -> Option<&Opt> {
 }
 
 /// Selects an option if and only if it corresponds to a label.
-fn opt_to_label(opt: &Opt) -> Option<&Opt> {
+pub(crate) fn opt_to_label(opt: &Opt) -> Option<&Opt> {
     match opt {
         Opt::Label(_) => Some(opt),
         _ => None,
     }
 }
 
-#[derive(Debug, PartialEq)]
+/// A relationship between two entities.
+#[derive(Clone, Debug)]
 pub struct Relation {
-    entity1: String,
-    entity2: String,
-    card1: Cardinality,
-    card2: Cardinality,
-    options: Options,
+    pub(crate) entity1: String,
+    pub(crate) entity2: String,
+    pub(crate) card1: Cardinality,
+    pub(crate) card2: Cardinality,
+    pub(crate) options: Options,
+    /// Taken from the `##` comments just before the relationship.
+    pub(crate) doc: Option<String>,
+    /// Where the relationship (including its options) was written.
+    pub(crate) span: Span,
+    /// Where `entity1` was written, including any quotes.
+    pub(crate) entity1_span: Span,
+    /// Where `entity2` was written, including any quotes.
+    pub(crate) entity2_span: Span,
+}
+
+/// Relations are compared by what they describe, not where they were written.
+impl PartialEq for Relation {
+    fn eq(&self, other: &Self) -> bool {
+        self.entity1 == other.entity1
+            && self.entity2 == other.entity2
+            && self.card1 == other.card1
+            && self.card2 == other.card2
+            && self.options == other.options
+            && self.doc == other.doc
+    }
+}
+
+impl Relation {
+    /// The names of the entities at each end, in the order they were
+    /// written.
+    pub fn endpoints(&self) -> (&str, &str) {
+        (&self.entity1, &self.entity2)
+    }
+
+    /// The cardinality at each end, in the same order as
+    /// [endpoints](#method.endpoints).
+    pub fn cardinalities(&self) -> (Cardinality, Cardinality) {
+        (self.card1, self.card2)
+    }
+
+    /// The formatting options given for the relationship.
+    pub fn options(&self) -> &Options {
+        &self.options
+    }
+
+    /// The documentation given in `##` comments just before the
+    /// relationship, if any.
+    pub fn doc(&self) -> Option<&str> {
+        self.doc.as_deref()
+    }
+
+    /// Where the relationship (including its options) was written.
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    /// Where each of the entity names were written, including any quotes.
+    pub fn endpoint_spans(&self) -> (Span, Span) {
+        (self.entity1_span, self.entity2_span)
+    }
 }
 
 /// Defined at each side of a [Relation](struct.Relation.html) a cardinality
 /// describes the count constraints for each entity.
-#[derive(Debug, PartialEq)]
+///
+/// When serialized, cardinalities are written the same way as in an er file.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Cardinality {
+    #[cfg_attr(feature = "serde", serde(rename = "?"))]
     ZeroOne,
+    #[cfg_attr(feature = "serde", serde(rename = "1"))]
     One,
+    #[cfg_attr(feature = "serde", serde(rename = "*"))]
     ZeroPlus,
+    #[cfg_attr(feature = "serde", serde(rename = "+"))]
     OnePlus,
 }
 
@@ -305,7 +854,7 @@ impl Display for Cardinality {
     }
 }
 
-fn card_by_name(c: char) -> Option<Cardinality> {
+pub(crate) fn card_by_name(c: char) -> Option<Cardinality> {
     use Cardinality::*;
     match c {
         '?' => Some(ZeroOne),
@@ -316,27 +865,30 @@ fn card_by_name(c: char) -> Option<Cardinality> {
     }
 }
 
+/// The character used for a cardinality in an er file.
+pub(crate) fn card_char(card: Cardinality) -> char {
+    use Cardinality::*;
+    match card {
+        ZeroOne => '?',
+        One => '1',
+        ZeroPlus => '*',
+        OnePlus => '+',
+    }
+}
+
 /// Hard-coded default options for all graph titles.
-fn default_title_opts() -> Options {
-    let defaults = vec![Opt::FontSize(30.0)]
-        .into_iter()
-        .map(|opt| (opt.html_attr_name().to_string(), opt))
-        .collect();
-    Options(defaults)
+pub(crate) fn default_title_opts() -> Options {
+    vec![Opt::FontSize(30.0)].into_iter().collect()
 }
 
 /// Hard-coded default options for all entity headers.
-fn default_header_opts() -> Options {
-    let defaults = vec![Opt::FontSize(16.0)]
-        .into_iter()
-        .map(|opt| (opt.html_attr_name().to_string(), opt))
-        .collect();
-    Options(defaults)
+pub(crate) fn default_header_opts() -> Options {
+    vec![Opt::FontSize(16.0)].into_iter().collect()
 }
 
 /// Hard-coded default options for all entities.
-fn default_entity_opts() -> Options {
-    let defaults = vec![
+pub(crate) fn default_entity_opts() -> Options {
+    vec![
         Opt::Border(0),
         Opt::CellBorder(1),
         Opt::CellSpacing(0),
@@ -344,21 +896,229 @@ fn default_entity_opts() -> Options {
         Opt::FontFace("Helvetica".to_string()),
     ]
     .into_iter()
-    .map(|opt| (opt.html_attr_name().to_string(), opt))
-    .collect();
-    Options(defaults)
+    .collect()
 }
 
 /// Hard-coded default options for all relationships.
-fn default_rel_opts() -> Options {
-    Options(Default::default())
+pub(crate) fn default_rel_opts() -> Options {
+    Options::default()
 }
 
 /// Hard-coded default options for all attributes.
-fn default_attr_opts() -> Options {
-    let defaults = vec![Opt::TextAlignment("LEFT".to_string())]
+pub(crate) fn default_attr_opts() -> Options {
+    vec![Opt::TextAlignment("LEFT".to_string())]
+        .into_iter()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_accessors() {
+        let er: ER = r#"
+[Person] { bgcolor: "red" }
+*name
++`birth place id`
+[`Birth Place`]
+*id
+[Other]
+Person *--1 `Birth Place` { label: "born in" }
+Other ?--+ Other
+"#
+        .parse()
+        .unwrap();
+        let names: Vec<_> = er.entities().iter().map(Entity::name).collect();
+        assert_eq!(names, ["Person", "Birth Place", "Other"]);
+
+        let person = er.entity("Person").unwrap();
+        let attrs: Vec<_> = person
+            .attributes()
+            .iter()
+            .map(|a| (a.name(), a.is_pk(), a.is_fk()))
+            .collect();
+        assert_eq!(
+            attrs,
+            [("name", true, false), ("birth place id", false, true)]
+        );
+        assert_eq!(
+            person.header_options().bg_color().map(String::as_str),
+            Some("red")
+        );
+        assert!(er.entity("`Birth Place`").is_none());
+        assert!(er.entity("Nobody").is_none());
+
+        let rels: Vec<_> = person.relations_in(&er).collect();
+        assert_eq!(rels.len(), 1);
+        assert_eq!(rels[0].endpoints(), ("Person", "Birth Place"));
+        assert_eq!(
+            rels[0].cardinalities(),
+            (Cardinality::ZeroPlus, Cardinality::One)
+        );
+        assert_eq!(
+            rels[0].options().label().map(String::as_str),
+            Some("born in")
+        );
+        let other = er.entity("Other").unwrap();
+        assert_eq!(other.relations_in(&er).count(), 1);
+        assert_eq!(er.relations().len(), 2);
+    }
+
+    #[test]
+    fn test_global_options_later_directives_win() {
+        let globals = GlobalOptions::from_directives(vec![
+            (
+                Directive::Header,
+                vec![Opt::BgColor("red".to_string()), Opt::FontSize(20.0)]
+                    .into_iter()
+                    .collect(),
+            ),
+            (
+                Directive::Header,
+                vec![Opt::BgColor("blue".to_string())].into_iter().collect(),
+            ),
+        ]);
+        let header = globals.get(Directive::Header);
+        assert_eq!(header.bg_color().map(String::as_str), Some("blue"));
+        assert_eq!(header.font_size(), Some(&20.0));
+        assert!(globals.get(Directive::Title).is_empty());
+    }
+
+    #[test]
+    fn test_effective_options() {
+        let er: ER = r##"
+            title { label: "Schema" }
+            header { bgcolor: "#ececfc", size: "20" }
+            entity { cellpadding: "8" }
+            relationship { label: "default" }
+
+            [Person] { bgcolor: "#d0e0d0" }
+                name { size: "12" }
+            [Group]
+                id
+            Person *--* Group
+            Group 1--1 Person { label: "owner" }
+            "##
+        .parse()
+        .unwrap();
+
+        let title = er.effective_title_options();
+        assert_eq!(title.label().map(String::as_str), Some("Schema"));
+        assert_eq!(title.font_size(), Some(&30.0));
+
+        let person = &er.entities[0];
+        let group = &er.entities[1];
+        let header = er.effective_header_options(person);
+        assert_eq!(header.bg_color().map(String::as_str), Some("#d0e0d0"));
+        assert_eq!(header.font_size(), Some(&20.0));
+        let header = er.effective_header_options(group);
+        assert_eq!(header.bg_color().map(String::as_str), Some("#ececfc"));
+
+        let body = er.effective_entity_options(group);
+        assert_eq!(body.cell_padding(), Some(&8));
+        assert_eq!(body.cell_border(), Some(&1));
+
+        let attr = er.effective_attribute_options(&person.attribs[0]);
+        assert_eq!(attr.font_size(), Some(&12.0));
+        assert_eq!(attr.text_alignment().map(String::as_str), Some("LEFT"));
+
+        let rel = er.effective_relation_options(&er.rels[0]);
+        assert_eq!(rel.label().map(String::as_str), Some("default"));
+        let rel = er.effective_relation_options(&er.rels[1]);
+        assert_eq!(rel.label().map(String::as_str), Some("owner"));
+    }
+
+    #[test]
+    fn test_options_iter_order_is_stable() {
+        let a: Options = vec![
+            Opt::CellPadding(4),
+            Opt::Label("x".to_string()),
+            Opt::Border(0),
+        ]
+        .into_iter()
+        .collect();
+        let b: Options = vec![
+            Opt::Border(0),
+            Opt::CellPadding(4),
+            Opt::Label("x".to_string()),
+        ]
+        .into_iter()
+        .collect();
+
+        let expected = vec![
+            Opt::Label("x".to_string()),
+            Opt::Border(0),
+            Opt::CellPadding(4),
+        ];
+        assert_eq!(a.iter().collect::<Vec<_>>(), expected);
+        assert_eq!(b.iter().collect::<Vec<_>>(), expected);
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn test_options_set_replaces() {
+        let mut opts = Options::default();
+        assert_eq!(opts.set(Opt::FontSize(10.0)), None);
+        assert_eq!(opts.set(Opt::FontSize(12.0)), Some(Opt::FontSize(10.0)));
+        assert_eq!(opts.font_size(), Some(&12.0));
+    }
+
+    #[test]
+    fn test_options_eq_is_reflexive() {
+        let nan: Options = vec![Opt::FontSize(f64::NAN)].into_iter().collect();
+        assert_eq!(nan, nan.clone());
+        let twelve: Options = vec![Opt::FontSize(12.0)].into_iter().collect();
+        assert_ne!(nan, twelve);
+        assert_ne!(twelve, Options::default());
+    }
+
+    #[test]
+    fn test_merge_opts_layers() {
+        let defaults = default_entity_opts();
+        let global: Options = vec![Opt::CellPadding(8), Opt::BgColor("red".to_string())]
+            .into_iter()
+            .collect();
+        let local: Options = vec![Opt::BgColor("blue".to_string())].into_iter().collect();
+
+        let merged = merge_opts(&local, &merge_opts(&global, &defaults));
+        assert_eq!(merged.bg_color().map(String::as_str), Some("blue"));
+        assert_eq!(merged.cell_padding(), Some(&8));
+        assert_eq!(merged.border(), Some(&0));
+        assert_eq!(merged.font_face().map(String::as_str), Some("Helvetica"));
+    }
+
+    #[test]
+    fn test_options_to_filters() {
+        let opts: Options = vec![
+            Opt::Label("x".to_string()),
+            Opt::FontSize(10.0),
+            Opt::BgColor("red".to_string()),
+        ]
         .into_iter()
-        .map(|opt| (opt.html_attr_name().to_string(), opt))
         .collect();
-    Options(defaults)
+
+        let font: Vec<_> = options_to(opt_to_font, &opts).iter().collect();
+        assert_eq!(font, vec![Opt::FontSize(10.0)]);
+        let html: Vec<_> = options_to(opt_to_html, &opts).iter().collect();
+        assert_eq!(html, vec![Opt::BgColor("red".to_string())]);
+        let label: Vec<_> = options_to(opt_to_label, &opts).iter().collect();
+        assert_eq!(label, vec![Opt::Label("x".to_string())]);
+    }
+
+    #[test]
+    fn test_opt_value_round_trips() {
+        let opts = vec![
+            Opt::Label("a label".to_string()),
+            Opt::FontSize(12.5),
+            Opt::CellPadding(4),
+            Opt::TextAlignment("LEFT".to_string()),
+        ];
+        for opt in opts {
+            assert_eq!(
+                option_by_name(opt.html_attr_name(), &opt.value()).unwrap(),
+                opt
+            );
+        }
+    }
 }
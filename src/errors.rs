@@ -1,11 +1,824 @@
+//! Errors, and the diagnostics layer for describing them to the people
+//! writing er files.
+
+use crate::er::{Directive, OPTION_NAMES};
+use crate::parser::Rule;
+use crate::span::{LineIndex, Span};
+use pest::error::{ErrorVariant, InputLocation, LineColLocation};
+use std::fmt::{Display, Formatter};
+
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
+    #[error("{source}")]
+    Parser {
+        source: Box<pest::error::Error<Rule>>,
+        span: Span,
+    },
+    #[error("{}Invalid value for `{name}`: {source}", prefix(.span))]
+    InvalidInt {
+        name: String,
+        source: std::num::ParseIntError,
+        span: Option<Span>,
+    },
+    #[error("{}Invalid value for `{name}`: {source}", prefix(.span))]
+    InvalidFloat {
+        name: String,
+        source: FloatError,
+        span: Option<Span>,
+    },
+    #[error("{}Unknown formatting option: `{name}`", prefix(.span))]
+    UnknownFormatOption { name: String, span: Option<Span> },
+    #[error("There's no entity named `{name}`")]
+    UnknownEntity { name: String },
+    #[error("There's already an entity named `{name}`")]
+    EntityExists { name: String },
+    #[error("`{entity}` already has an attribute named `{name}`")]
+    AttributeExists { entity: String, name: String },
+    #[error("`{name}` can't be used as a name")]
+    InvalidName { name: String },
+    #[error("The value for `{name}` can't contain a double quote")]
+    QuoteInValue { name: String },
+    #[error("{}Invalid document: {message}", prefix(.span))]
+    InvalidDocument { message: String, span: Option<Span> },
     #[error(transparent)]
-    Parser(#[from] pest::error::Error<crate::parser::Rule>),
-    #[error(transparent)]
-    InvalidInt(#[from] std::num::ParseIntError),
+    Io(#[from] std::io::Error),
+}
+
+/// Why a value couldn't be used for an option which takes a number.
+#[derive(thiserror::Error, Debug)]
+pub enum FloatError {
     #[error(transparent)]
-    InvalidFloat(#[from] std::num::ParseFloatError),
-    #[error("Unknown formatting option: `{0}`")]
-    UnknownFormatOption(String),
+    Parse(std::num::ParseFloatError),
+    #[error("must be a finite number, and not negative")]
+    OutOfRange,
+}
+
+impl Error {
+    /// Where in the er file the problem was found.
+    ///
+    /// This is `None` for problems that didn't come from the text of an er
+    /// file (values given programmatically, or io errors).
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Error::Parser { span, .. } => Some(*span),
+            Error::InvalidInt { span, .. }
+            | Error::InvalidFloat { span, .. }
+            | Error::UnknownFormatOption { span, .. }
+            | Error::InvalidDocument { span, .. } => *span,
+            Error::UnknownEntity { .. }
+            | Error::EntityExists { .. }
+            | Error::AttributeExists { .. }
+            | Error::InvalidName { .. }
+            | Error::QuoteInValue { .. }
+            | Error::Io(_) => None,
+        }
+    }
+
+    /// Attaches a location to an error which doesn't yet have one.
+    pub(crate) fn at(mut self, at: Span) -> Self {
+        match &mut self {
+            Error::InvalidInt { span, .. }
+            | Error::InvalidFloat { span, .. }
+            | Error::UnknownFormatOption { span, .. }
+            | Error::InvalidDocument { span, .. } => {
+                span.get_or_insert(at);
+            }
+            Error::Parser { .. }
+            | Error::UnknownEntity { .. }
+            | Error::EntityExists { .. }
+            | Error::AttributeExists { .. }
+            | Error::InvalidName { .. }
+            | Error::QuoteInValue { .. }
+            | Error::Io(_) => (),
+        }
+        self
+    }
+}
+
+impl From<pest::error::Error<Rule>> for Error {
+    fn from(err: pest::error::Error<Rule>) -> Self {
+        let (start, end) = match err.location {
+            InputLocation::Pos(pos) => (pos, pos),
+            InputLocation::Span(span) => span,
+        };
+        let (line, col) = match err.line_col {
+            LineColLocation::Pos(pos) | LineColLocation::Span(pos, _) => pos,
+        };
+        Error::Parser {
+            source: Box::new(err),
+            span: Span {
+                start,
+                end,
+                line,
+                col,
+            },
+        }
+    }
+}
+
+/// Prefix for error messages giving the location, when there is one.
+fn prefix(span: &Option<Span>) -> String {
+    match span {
+        Some(span) => format!("{}: ", span),
+        None => String::new(),
+    }
+}
+
+/// Stable codes identifying each kind of problem we know how to describe.
+///
+/// These are shown alongside every diagnostic so people (and their tools) can
+/// search for or filter on them. Once a code has been handed out it must keep
+/// meaning the same thing.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Code {
+    /// A syntax error we don't have anything more specific to say about.
+    Syntax,
+    /// An options block starting on a line of its own.
+    OptionsOnOwnLine,
+    /// Something other than a comment following the `}` of an options block.
+    TextAfterOptions,
+    /// An options block with no closing `}`.
+    UnclosedOptions,
+    /// An option value that isn't wrapped in double quotes.
+    UnquotedOptionValue,
+    /// An option name we don't recognize.
+    UnknownOption,
+    /// Two options not separated by a comma or a newline.
+    MissingOptionSeparator,
+    /// An option value which can't be parsed as the type the option needs.
+    InvalidOptionValue,
+    /// A directive written after the first entity or relationship.
+    DirectiveAfterBody,
+    /// A directive name we don't recognize.
+    UnknownDirective,
+    /// A relationship with a missing or unknown cardinality.
+    InvalidCardinality,
+    /// An entity header with no closing `]`.
+    UnclosedEntityHeader,
+    /// A quoted identifier with no closing quote.
+    UnclosedQuote,
+    /// The er file couldn't be read or the output couldn't be written.
+    Io,
+    /// An entity was asked for by a name which isn't in the er file.
+    UnknownEntity,
+    /// An entity was to be given a name another entity already has.
+    EntityExists,
+    /// Something which isn't a valid identifier was given as a name.
+    InvalidName,
+    /// A model being imported from another format doesn't follow the schema.
+    InvalidDocument,
+    /// An attribute was to be given a name another attribute of the same
+    /// entity already has.
+    AttributeExists,
+}
+
+impl Code {
+    /// The code as shown to people, eg. `E0001`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Code::Syntax => "E0001",
+            Code::OptionsOnOwnLine => "E0002",
+            Code::TextAfterOptions => "E0003",
+            Code::UnclosedOptions => "E0004",
+            Code::UnquotedOptionValue => "E0005",
+            Code::UnknownOption => "E0006",
+            Code::MissingOptionSeparator => "E0007",
+            Code::InvalidOptionValue => "E0008",
+            Code::DirectiveAfterBody => "E0009",
+            Code::UnknownDirective => "E0010",
+            Code::InvalidCardinality => "E0011",
+            Code::UnclosedEntityHeader => "E0012",
+            Code::UnclosedQuote => "E0013",
+            Code::Io => "E0014",
+            Code::UnknownEntity => "E0015",
+            Code::EntityExists => "E0016",
+            Code::InvalidName => "E0017",
+            Code::InvalidDocument => "E0018",
+            Code::AttributeExists => "E0019",
+        }
+    }
+}
+
+impl Display for Code {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A problem with an er file, described in terms of the er file rather than
+/// the grammar used to parse it.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Diagnostic {
+    pub code: Code,
+    pub message: String,
+    pub span: Option<Span>,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}error[{}]: {}",
+            prefix(&self.span),
+            self.code,
+            self.message
+        )
+    }
+}
+
+impl Diagnostic {
+    fn new(code: Code, message: impl Into<String>, span: Option<Span>) -> Self {
+        Diagnostic {
+            code,
+            message: message.into(),
+            span,
+        }
+    }
+
+    /// Renders the diagnostic along with the offending line of `source`,
+    /// with the problem underlined.
+    ///
+    /// `origin` names where the source came from (a file path, usually).
+    pub fn render(&self, source: &str, origin: &str) -> String {
+        let mut out = format!("error[{}]: {}\n", self.code, self.message);
+        let span = match self.span {
+            Some(span) => span,
+            None => {
+                out.push_str(&format!(" --> {}\n", origin));
+                return out;
+            }
+        };
+
+        let (start, end) = line_bounds(source, span.start);
+        let line = &source[start..end];
+        let gutter = " ".repeat(span.line.to_string().len());
+        // Underline the span, or as much of it as there is on the first line.
+        let width = source[span.start..span.end.min(end).max(span.start)]
+            .chars()
+            .count()
+            .max(1);
+
+        out.push_str(&format!("{}--> {}:{}\n", gutter, origin, span));
+        out.push_str(&format!("{} |\n", gutter));
+        out.push_str(&format!("{} | {}\n", span.line, line));
+        out.push_str(&format!(
+            "{} | {}{}\n",
+            gutter,
+            " ".repeat(span.col - 1),
+            "^".repeat(width)
+        ));
+        out
+    }
+}
+
+impl Error {
+    /// Describes the error in terms of the er file it came from.
+    ///
+    /// `source` must be the text that produced the error.
+    pub fn diagnostic(&self, source: &str) -> Diagnostic {
+        match self {
+            Error::Parser { source: err, span } => classify(source, err, *span),
+            Error::InvalidInt { .. } | Error::InvalidFloat { .. } => {
+                Diagnostic::new(Code::InvalidOptionValue, self.message(), self.span())
+            }
+            Error::UnknownFormatOption { .. } => {
+                Diagnostic::new(Code::UnknownOption, self.message(), self.span())
+            }
+            Error::UnknownEntity { name } => Diagnostic::new(
+                Code::UnknownEntity,
+                format!("there's no entity named `{}`", name),
+                None,
+            ),
+            Error::EntityExists { name } => Diagnostic::new(
+                Code::EntityExists,
+                format!("there's already an entity named `{}`", name),
+                None,
+            ),
+            Error::AttributeExists { entity, name } => Diagnostic::new(
+                Code::AttributeExists,
+                format!("`{}` already has an attribute named `{}`", entity, name),
+                None,
+            ),
+            Error::InvalidName { name } => Diagnostic::new(
+                Code::InvalidName,
+                format!("`{}` can't be used as a name", name),
+                None,
+            ),
+            Error::QuoteInValue { name } => Diagnostic::new(
+                Code::InvalidOptionValue,
+                format!("the value for `{}` can't contain a double quote", name),
+                None,
+            ),
+            Error::InvalidDocument { message, span } => {
+                Diagnostic::new(Code::InvalidDocument, message.clone(), *span)
+            }
+            Error::Io(err) => Diagnostic::new(Code::Io, err.to_string(), None),
+        }
+    }
+
+    /// The error message, sans location.
+    fn message(&self) -> String {
+        match self {
+            Error::InvalidInt { name, source, .. } => {
+                format!("invalid value for `{}`: {}", name, source)
+            }
+            Error::InvalidFloat { name, source, .. } => {
+                format!("invalid value for `{}`: {}", name, source)
+            }
+            Error::UnknownFormatOption { name, .. } => format!("unknown option `{}`", name),
+            _ => self.to_string(),
+        }
+    }
+}
+
+/// Works out what went wrong based on where pest gave up, and what the source
+/// looks like around there.
+///
+/// Pest reports failures inside atomic rules at the start of the rule (so a
+/// bad option value is reported at the `{` of its options block), so rather
+/// than trusting the position, we go looking for the actual problem.
+fn classify(source: &str, err: &pest::error::Error<Rule>, span: Span) -> Diagnostic {
+    let lines = LineIndex::new(source);
+    let pos = span.start.min(source.len());
+    let blocks = option_blocks(source);
+
+    // Failures somewhere inside an options block, or on the line the block
+    // starts on.
+    let (_, pos_line_end) = line_bounds(source, pos);
+    let block = blocks.iter().find(|(open, close)| {
+        let encloses = *open <= pos && close.is_none_or(|c| c >= pos);
+        let follows = *open > pos && *open <= pos_line_end;
+        encloses || follows
+    });
+    if let Some(&(open, close)) = block {
+        let (line_start, _) = line_bounds(source, open);
+        if source[line_start..open].trim().is_empty() {
+            return Diagnostic::new(
+                Code::OptionsOnOwnLine,
+                "options must start on the same line as the attribute, entity or \
+                 relationship they belong to",
+                Some(lines.span(open, open + 1)),
+            );
+        }
+        if let Some(diagnostic) = lint_options(source, &lines, open, close) {
+            return diagnostic;
+        }
+    }
+
+    let (line_start, line_end) = line_bounds(source, pos);
+    let line = &source[line_start..line_end];
+    let indent = line.len() - line.trim_start().len();
+    let first_word: String = line
+        .trim_start()
+        .chars()
+        .take_while(|c| c.is_ascii_alphanumeric() || *c == '_')
+        .collect();
+    let word_span = lines.span(line_start + indent, line_start + indent + first_word.len());
+    let after_word = line.trim_start()[first_word.len()..].trim_start();
+
+    // A line that looks like a directive (`name { ... }`).
+    if !first_word.is_empty() && after_word.starts_with('{') {
+        if !body_started(source, &blocks, line_start) {
+            if Directive::ALL.iter().any(|d| d.name() == first_word) {
+                // Nothing wrong with the directive itself, so whatever is
+                // wrong comes after it.
+            } else {
+                return Diagnostic::new(
+                    Code::UnknownDirective,
+                    format!(
+                        "unknown directive `{}`; expected one of {}",
+                        first_word,
+                        Directive::ALL
+                            .iter()
+                            .map(|d| format!("`{}`", d.name()))
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    ),
+                    Some(word_span),
+                );
+            }
+        } else if Directive::ALL.iter().any(|d| d.name() == first_word) {
+            return Diagnostic::new(
+                Code::DirectiveAfterBody,
+                "directives must appear before all entities and relationships",
+                Some(word_span),
+            );
+        }
+    }
+
+    if line.trim_start().starts_with('[') && !line.contains(']') {
+        return Diagnostic::new(
+            Code::UnclosedEntityHeader,
+            "entity header is missing its closing `]`",
+            Some(lines.trimmed_span(line_start + indent, line_end)),
+        );
+    }
+
+    if let Some(quote) = unclosed_quote(source, line_start, line_end) {
+        return Diagnostic::new(
+            Code::UnclosedQuote,
+            "quoted name is missing its closing quote",
+            Some(lines.span(quote, quote + 1)),
+        );
+    }
+    // Pest tends to report an unclosed quote at the start of the next line.
+    if pos == line_start && line_start > 0 {
+        let (prev_start, prev_end) = line_bounds(source, line_start - 1);
+        if let Some(quote) = unclosed_quote(source, prev_start, prev_end) {
+            return Diagnostic::new(
+                Code::UnclosedQuote,
+                "quoted name is missing its closing quote",
+                Some(lines.span(quote, quote + 1)),
+            );
+        }
+    }
+
+    let expected = match &err.variant {
+        ErrorVariant::ParsingError { positives, .. } => positives.clone(),
+        ErrorVariant::CustomError { .. } => vec![],
+    };
+
+    if expected.contains(&Rule::card_type) && line.contains("--") {
+        return Diagnostic::new(
+            Code::InvalidCardinality,
+            "relationships are written as `A <card>--<card> B`, where each card \
+             is one of `?` (0 or 1), `1` (exactly 1), `*` (0 or more) or `+` \
+             (1 or more)",
+            Some(lines.span(pos, (pos + 1).min(line_end).max(pos))),
+        );
+    }
+
+    let found: String = source[pos..]
+        .chars()
+        .take_while(|c| !c.is_whitespace())
+        .take(20)
+        .collect();
+    let found = if found.is_empty() {
+        if pos >= source.len() {
+            "end of file".to_string()
+        } else {
+            "end of line".to_string()
+        }
+    } else {
+        format!("`{}`", found)
+    };
+    let expected: Vec<_> = expected.into_iter().filter_map(describe).collect();
+    let message = if expected.is_empty() {
+        format!("unexpected {}", found)
+    } else {
+        format!("unexpected {}; expected {}", found, expected.join(" or "))
+    };
+    Diagnostic::new(Code::Syntax, message, Some(span))
+}
+
+/// A description of the things the grammar rules match, for telling people
+/// what we expected to see.
+///
+/// Rules that don't mean anything to someone writing an er file are skipped.
+fn describe(rule: Rule) -> Option<String> {
+    let desc = match rule {
+        Rule::EOI => "end of file",
+        Rule::document | Rule::body => "an entity or relationship",
+        Rule::entity | Rule::entity_name => "an entity header",
+        Rule::attr | Rule::attribs => "an attribute",
+        Rule::rel => "a relationship",
+        Rule::directive | Rule::dir_type => "a directive",
+        Rule::word | Rule::ident | Rule::ident_no_space | Rule::ident_quoted => "a name",
+        Rule::card_type | Rule::card1 | Rule::card2 => "a cardinality",
+        Rule::opt_name => "an option name",
+        Rule::quoted_text | Rule::opt_value => "a double-quoted value",
+        Rule::options | Rule::opt_list | Rule::option => "options",
+        _ => return None,
+    };
+    Some(desc.to_string())
+}
+
+/// Looks for the first problem in the options block opened at `open`.
+fn lint_options(
+    source: &str,
+    lines: &LineIndex<'_>,
+    open: usize,
+    close: Option<usize>,
+) -> Option<Diagnostic> {
+    let bytes = source.as_bytes();
+    let close = match close {
+        Some(close) => close,
+        None => {
+            return Some(Diagnostic::new(
+                Code::UnclosedOptions,
+                "options are missing their closing `}`",
+                Some(lines.span(open, open + 1)),
+            ))
+        }
+    };
+
+    let mut pos = open + 1;
+    loop {
+        pos = skip_trivia(source, pos, true);
+        if pos >= close {
+            break;
+        }
+
+        let name_end = pos
+            + source[pos..]
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == '_'))
+                .unwrap_or(source.len() - pos);
+        let name = &source[pos..name_end];
+        if name.is_empty() {
+            let end = token_end(source, pos, close);
+            return Some(Diagnostic::new(
+                Code::Syntax,
+                format!(
+                    "expected an option name, such as `label`, but found `{}`",
+                    &source[pos..end]
+                ),
+                Some(lines.span(pos, end)),
+            ));
+        }
+        if !OPTION_NAMES.contains(&name) {
+            return Some(Diagnostic::new(
+                Code::UnknownOption,
+                format!("unknown option `{}`", name),
+                Some(lines.span(pos, name_end)),
+            ));
+        }
+        pos = name_end;
+        if bytes.get(pos) != Some(&b':') {
+            return Some(Diagnostic::new(
+                Code::Syntax,
+                format!("expected `:` after the option name `{}`", name),
+                Some(lines.span(pos, pos + 1)),
+            ));
+        }
+
+        pos = skip_trivia(source, pos + 1, false);
+        if bytes.get(pos) != Some(&b'"') {
+            let end = token_end(source, pos, close);
+            return Some(Diagnostic::new(
+                Code::UnquotedOptionValue,
+                "option values must be double-quoted, even when they're numbers",
+                Some(lines.span(pos, end.max(pos + 1))),
+            ));
+        }
+        pos = match source[pos + 1..].find('"') {
+            Some(i) => pos + i + 2,
+            None => {
+                return Some(Diagnostic::new(
+                    Code::UnclosedQuote,
+                    "option value is missing its closing `\"`",
+                    Some(lines.span(pos, pos + 1)),
+                ))
+            }
+        };
+
+        pos = skip_trivia(source, pos, false);
+        match bytes.get(pos) {
+            Some(b',') => pos += 1,
+            Some(b'\n') | Some(b'\r') | Some(b'#') | Some(b'}') => (),
+            _ => {
+                let end = token_end(source, pos, close);
+                return Some(Diagnostic::new(
+                    Code::MissingOptionSeparator,
+                    "options must be separated by a comma or a new line",
+                    Some(lines.span(pos, end.max(pos + 1))),
+                ));
+            }
+        }
+    }
+
+    // The block itself is fine, but nothing other than a comment is allowed to
+    // follow it on the same line.
+    let (_, line_end) = line_bounds(source, close);
+    let rest = skip_trivia(source, close + 1, false);
+    if rest < line_end {
+        return Some(Diagnostic::new(
+            Code::TextAfterOptions,
+            "nothing but a comment can follow options on the same line",
+            Some(lines.trimmed_span(rest, line_end)),
+        ));
+    }
+    None
+}
+
+/// Finds the options blocks in `source`, giving the offsets of the `{` and
+/// (when there is one) the matching `}`.
+pub(crate) fn option_blocks(source: &str) -> Vec<(usize, Option<usize>)> {
+    let mut blocks = vec![];
+    let mut open = None;
+    let mut chars = source.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '#' => {
+                for (_, c) in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            '"' if open.is_some() => {
+                for (_, c) in chars.by_ref() {
+                    if c == '"' {
+                        break;
+                    }
+                }
+            }
+            '{' if open.is_none() => open = Some(i),
+            '}' => {
+                if let Some(start) = open.take() {
+                    blocks.push((start, Some(i)));
+                }
+            }
+            _ => (),
+        }
+    }
+    if let Some(start) = open {
+        blocks.push((start, None));
+    }
+    blocks
+}
+
+/// Whether anything other than directives appears before `pos`.
+fn body_started(source: &str, blocks: &[(usize, Option<usize>)], pos: usize) -> bool {
+    let mut offset = 0;
+    for line in source[..pos].split_inclusive('\n') {
+        let start = offset;
+        offset += line.len();
+        let inside_block = blocks
+            .iter()
+            .any(|(open, close)| *open < start && close.is_none_or(|c| c >= start));
+        let line = line.trim();
+        if inside_block || line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let word: String = line
+            .chars()
+            .take_while(|c| c.is_ascii_alphabetic())
+            .collect();
+        if !Directive::ALL.iter().any(|d| d.name() == word) {
+            return true;
+        }
+    }
+    false
+}
+
+/// Finds the opening quote of a quoted name left unclosed between `start` and
+/// `end`.
+fn unclosed_quote(source: &str, start: usize, end: usize) -> Option<usize> {
+    let line = &source[start..end];
+    let line = match line.find('{') {
+        Some(i) => &line[..i],
+        None => line,
+    };
+    let mut open: Option<(usize, char)> = None;
+    for (i, c) in line.char_indices() {
+        match (open, c) {
+            (None, '`') | (None, '\'') | (None, '"') => open = Some((start + i, c)),
+            (Some((_, q)), c) if c == q => open = None,
+            _ => (),
+        }
+    }
+    open.map(|(i, _)| i)
+}
+
+/// Skips whitespace and comments, stopping at the end of the line unless
+/// `newlines` is set.
+fn skip_trivia(source: &str, mut pos: usize, newlines: bool) -> usize {
+    while let Some(c) = source[pos..].chars().next() {
+        if c == '#' {
+            pos += source[pos..].find('\n').unwrap_or(source.len() - pos);
+        } else if c == '\n' && !newlines {
+            break;
+        } else if c.is_whitespace() {
+            pos += c.len_utf8();
+        } else {
+            break;
+        }
+    }
+    pos
+}
+
+/// Finds the end of the token starting at `pos`, for highlighting.
+fn token_end(source: &str, pos: usize, limit: usize) -> usize {
+    pos + source[pos..limit.max(pos)]
+        .find(|c: char| c.is_whitespace() || c == ',' || c == '}')
+        .unwrap_or(limit.max(pos) - pos)
+}
+
+/// The byte offsets for the start and end (sans newline) of the line holding
+/// `pos`.
+fn line_bounds(source: &str, pos: usize) -> (usize, usize) {
+    let start = source[..pos].rfind('\n').map_or(0, |i| i + 1);
+    let end = pos + source[pos..].find('\n').unwrap_or(source.len() - pos);
+    let end = if source[..end].ends_with('\r') {
+        end - 1
+    } else {
+        end
+    };
+    (start, end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Code;
+    use crate::parser::parse;
+
+    /// The code for the diagnostic produced by parsing `input`.
+    fn code(input: &str) -> Code {
+        parse(input).unwrap_err().diagnostic(input).code
+    }
+
+    #[test]
+    fn test_text_after_options_on_directive() {
+        let input = "title { label: \"x\" } header { label: \"y\" }\n";
+        let diagnostic = parse(input).unwrap_err().diagnostic(input);
+        assert_eq!(diagnostic.code, Code::TextAfterOptions);
+        assert_eq!(
+            diagnostic.span.unwrap().as_str(input),
+            r#"header { label: "y" }"#
+        );
+    }
+
+    #[test]
+    fn test_unclosed_options() {
+        let input = "[Foo]\nbar { label: \"x\"\n";
+        assert_eq!(code(input), Code::UnclosedOptions);
+    }
+
+    #[test]
+    fn test_option_values_must_be_double_quoted() {
+        assert_eq!(code(r#"title { label: '123' }"#), Code::UnquotedOptionValue);
+        assert_eq!(code(r#"title { label: 123 }"#), Code::UnquotedOptionValue);
+        assert_eq!(code("[Foo]\n  a { size: 12 }\n"), Code::UnquotedOptionValue);
+    }
+
+    #[test]
+    fn test_unknown_option() {
+        let input = r#"title { colour: "red" }"#;
+        let diagnostic = parse(input).unwrap_err().diagnostic(input);
+        assert_eq!(diagnostic.code, Code::UnknownOption);
+        assert_eq!(diagnostic.span.unwrap().as_str(input), "colour");
+    }
+
+    #[test]
+    fn test_missing_option_separator() {
+        let input = r#"title { label: "a" color: "red" }"#;
+        assert_eq!(code(input), Code::MissingOptionSeparator);
+    }
+
+    #[test]
+    fn test_invalid_option_value() {
+        let input = "[Foo]\n  a { size: \"abc\" }\n";
+        let diagnostic = parse(input).unwrap_err().diagnostic(input);
+        assert_eq!(diagnostic.code, Code::InvalidOptionValue);
+        assert_eq!(diagnostic.span.unwrap().as_str(input), r#"size: "abc""#);
+    }
+
+    #[test]
+    fn test_directive_after_body() {
+        let input = "Foo 1--1 Bar\ntitle { label: \"oops\" }\n";
+        let diagnostic = parse(input).unwrap_err().diagnostic(input);
+        assert_eq!(diagnostic.code, Code::DirectiveAfterBody);
+        assert_eq!(diagnostic.span.unwrap().as_str(input), "title");
+    }
+
+    #[test]
+    fn test_unknown_directive_after_multiline_directive() {
+        // Multi-line directives before it don't count as the body starting.
+        let input = "title {\n  label: \"x\"\n}\nunknown { label: \"y\" }\n";
+        assert_eq!(code(input), Code::UnknownDirective);
+    }
+
+    #[test]
+    fn test_invalid_cardinality() {
+        assert_eq!(code("Foo *--x Bar\n"), Code::InvalidCardinality);
+        assert_eq!(code("Foo *-- Bar\n"), Code::InvalidCardinality);
+    }
+
+    #[test]
+    fn test_unclosed_entity_header() {
+        assert_eq!(code("[Foo\nbar\n"), Code::UnclosedEntityHeader);
+    }
+
+    #[test]
+    fn test_unclosed_quote() {
+        assert_eq!(code("[Foo]\n  `a\n"), Code::UnclosedQuote);
+    }
+
+    #[test]
+    fn test_render() {
+        let input = "title { label: '123' }\n";
+        let rendered = parse(input)
+            .unwrap_err()
+            .diagnostic(input)
+            .render(input, "schema.er");
+        assert_eq!(
+            rendered,
+            "error[E0005]: option values must be double-quoted, even when they're numbers
+ --> schema.er:1:16
+  |
+1 | title { label: '123' }
+  |                ^^^^^
+"
+        );
+    }
 }
@@ -0,0 +1,169 @@
+//! Links foreign key attributes (those marked with `+`) to the relationships
+//! they implement.
+//!
+//! Nothing in an er file says which relationship a foreign key belongs to, so
+//! it's inferred from the usual naming conventions: `+birth_place_id` on
+//! `Person` is taken to implement a relationship between `Person` and
+//! `Birth Place`. Names are compared ignoring case, spaces, underscores and
+//! dashes, and the field may optionally end in `id`.
+
+use crate::er::{Attribute, Cardinality, Entity, Relation, ER};
+use crate::span::Span;
+
+/// A foreign key attribute, along with the relationship it was matched to.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ForeignKey {
+    /// The name of the entity the attribute belongs to.
+    pub entity: String,
+    /// The name of the attribute.
+    pub field: String,
+    /// The index into the relationships of the `ER` for the relationship this
+    /// key implements, or `None` when no relationship matches.
+    pub relation: Option<usize>,
+    /// Where the attribute was declared.
+    pub span: Span,
+}
+
+impl ER {
+    /// Finds every foreign key attribute and the relationship it implements.
+    ///
+    /// When several relationships could match (the same pair of entities
+    /// related twice, say), the first is used.
+    pub fn foreign_keys(&self) -> Vec<ForeignKey> {
+        self.entities
+            .iter()
+            .flat_map(|entity| {
+                entity
+                    .attribs
+                    .iter()
+                    .filter(|attr| attr.fk)
+                    .map(move |attr| ForeignKey {
+                        entity: entity.name.clone(),
+                        field: attr.field.clone(),
+                        relation: self
+                            .rels
+                            .iter()
+                            .position(|rel| implements(entity, attr, rel)),
+                        span: attr.span,
+                    })
+            })
+            .collect()
+    }
+}
+
+/// Whether `attr` on `entity` looks like the foreign key for `rel`.
+pub(crate) fn implements(entity: &Entity, attr: &Attribute, rel: &Relation) -> bool {
+    if !attr.fk {
+        return false;
+    }
+    let field = normalize(&attr.field);
+    let stripped = field.strip_suffix("id");
+    other_end(rel, &entity.name).is_some_and(|other| {
+        // The other entity's own name may end in `id`, eg. `+grid` for `Grid`.
+        let other = normalize(other);
+        other == field || stripped == Some(other.as_str())
+    })
+}
+
+/// The name of the entity at the other end of `rel` from `name`, if `name` is
+/// part of it at all.
+pub(crate) fn other_end<'r>(rel: &'r Relation, name: &str) -> Option<&'r str> {
+    if rel.entity1 == name {
+        Some(&rel.entity2)
+    } else if rel.entity2 == name {
+        Some(&rel.entity1)
+    } else {
+        None
+    }
+}
+
+/// The entity expected to hold the foreign key for `rel`, which is the "many"
+/// side of a one-to-many relationship.
+///
+/// Many-to-many relationships need a join entity and one-to-one relationships
+/// could put the key on either side, so neither has an answer.
+pub(crate) fn many_side(rel: &Relation) -> Option<&str> {
+    match (is_many(rel.card1), is_many(rel.card2)) {
+        (true, false) => Some(&rel.entity1),
+        (false, true) => Some(&rel.entity2),
+        _ => None,
+    }
+}
+
+fn is_many(card: Cardinality) -> bool {
+    matches!(card, Cardinality::ZeroPlus | Cardinality::OnePlus)
+}
+
+/// Lowercases `name` and drops the characters commonly used to separate
+/// words, so that `Birth Place`, `birth_place` and `BirthPlace` all match.
+fn normalize(name: &str) -> String {
+    name.chars()
+        .filter(|c| !matches!(c, ' ' | '_' | '-'))
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::normalize;
+    use crate::parser::parse;
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize("Birth Place"), "birthplace");
+        assert_eq!(normalize("birth_place_id"), "birthplaceid");
+        assert_eq!(normalize("BirthPlace-ID"), "birthplaceid");
+    }
+
+    #[test]
+    fn test_basics_sample_foreign_keys() {
+        let er = parse(include_str!("../samples/basics.erd")).unwrap();
+        let fks = er.foreign_keys();
+
+        assert_eq!(fks.len(), 1);
+        assert_eq!(fks[0].entity, "Person");
+        assert_eq!(fks[0].field, "birth_place_id");
+        assert_eq!(fks[0].relation, Some(0));
+    }
+
+    #[test]
+    fn test_foreign_key_naming_conventions() {
+        let er = parse(
+            r#"
+            [Person]
+            *id
+            +group
+            +GroupId
+            +`manager id`
+            +person_id
+            +team_id
+            +grid
+            +grid_id
+            Person *--1 Group
+            Person *--? Person
+            Person *--1 Grid
+            "#,
+        )
+        .unwrap();
+        let matched: Vec<_> = er
+            .foreign_keys()
+            .into_iter()
+            .map(|fk| (fk.field, fk.relation))
+            .collect();
+
+        assert_eq!(
+            matched,
+            vec![
+                ("group".to_string(), Some(0)),
+                ("GroupId".to_string(), Some(0)),
+                // Roles aren't written down anywhere, so only the entity's
+                // own name matches a relationship with itself.
+                ("manager id".to_string(), None),
+                ("person_id".to_string(), Some(1)),
+                ("team_id".to_string(), None),
+                ("grid".to_string(), Some(2)),
+                ("grid_id".to_string(), Some(2)),
+            ]
+        );
+    }
+}
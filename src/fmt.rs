@@ -0,0 +1,670 @@
+//! Formats er files in a consistent style.
+//!
+//! The formatter works from the text of the document rather than an
+//! [ER](../er/struct.ER.html) so that comments (which the parser otherwise
+//! discards) survive. The style is:
+//!
+//! - one item per line, with attributes indented four spaces under their
+//!   entity header,
+//! - a single blank line before each entity header, and blank lines elsewhere
+//!   kept but collapsed to one,
+//! - identifiers quoted with backticks, and only when they contain spaces,
+//! - keys written `*` then `+`, and relationships as `A 1--* B`,
+//! - option blocks on one line as `{ label: "x", color: "y" }`, unless they
+//!   hold comments, in which case each option goes on its own line with a
+//!   trailing comma.
+//!
+//! Formatting is idempotent: formatting an already formatted document gives
+//! it back unchanged.
+//!
+//! [ER::to_erd](../er/struct.ER.html#method.to_erd) writes a model out in the
+//! same style, for models which didn't come from an er file.
+
+use crate::er::{card_char, merge_opts, Directive, Options, ER};
+use crate::parser::{parse_pairs, Rule};
+use crate::Result;
+use pest::iterators::Pair;
+
+/// How far attributes (and options in a multi-line block) are indented.
+const INDENT: &str = "    ";
+
+/// Formats the er document `input`.
+///
+/// Fails when `input` doesn't parse; there's no telling what's meant to be
+/// what in a broken document.
+pub fn format(input: &str) -> Result<String> {
+    let document = parse_pairs(input)?.next().expect("document");
+
+    let mut items = vec![];
+    for pair in document.into_inner() {
+        match pair.as_rule() {
+            Rule::head => items.extend(pair.into_inner().map(|pair| to_item(input, pair))),
+            Rule::body => {
+                for pair in pair.into_inner() {
+                    match pair.as_rule() {
+                        Rule::entity => {
+                            for pair in pair.into_inner() {
+                                match pair.as_rule() {
+                                    Rule::entity_name => items.push(to_item(input, pair)),
+                                    Rule::attribs => items
+                                        .extend(pair.into_inner().map(|pair| to_item(input, pair))),
+                                    _ => unreachable!(),
+                                }
+                            }
+                        }
+                        Rule::rel => items.push(to_item(input, pair)),
+                        _ => unreachable!(),
+                    }
+                }
+            }
+            Rule::EOI => (),
+            _ => unreachable!(),
+        }
+    }
+
+    let mut out = Formatter {
+        input,
+        comments: comments(input),
+        next_comment: 0,
+        last_end: 0,
+        buf: String::new(),
+    };
+    let mut in_body = false;
+    for (i, item) in items.iter().enumerate() {
+        // Entity headers always get a blank line before them, as does the
+        // first thing after the directives.
+        let starts_body = !in_body && item.kind != Kind::Directive;
+        in_body |= starts_body;
+        let force_blank = i > 0 && (item.kind == Kind::Entity || starts_body);
+        out.item(item, force_blank);
+    }
+    out.rest();
+    Ok(out.buf)
+}
+
+impl ER {
+    /// Writes the model as an er document, in the same style as
+    /// [format](../fmt/fn.format.html).
+    ///
+    /// Doc comments are written as `##` comments; there's nothing else to
+    /// write since the model doesn't keep any.
+    pub fn to_erd(&self) -> String {
+        let mut buf = String::new();
+        for &directive in &Directive::ALL {
+            let options = self.globals.get(directive);
+            if !options.is_empty() {
+                buf.push_str(&format!("{}{}\n", directive.name(), write_block(options)));
+            }
+        }
+
+        for entity in &self.entities {
+            if !buf.is_empty() {
+                buf.push('\n');
+            }
+            write_doc(&mut buf, "", entity.doc());
+            buf.push_str(&format!(
+                "[{}]{}\n",
+                quote_name(&entity.name),
+                write_block(&merge_opts(&entity.hoptions, &entity.eoptions))
+            ));
+            for attr in &entity.attribs {
+                write_doc(&mut buf, INDENT, attr.doc());
+                let keys = match (attr.pk, attr.fk) {
+                    (true, true) => "*+",
+                    (true, false) => "*",
+                    (false, true) => "+",
+                    (false, false) => "",
+                };
+                buf.push_str(&format!(
+                    "{}{}{}{}\n",
+                    INDENT,
+                    keys,
+                    quote_name(&attr.field),
+                    write_block(&attr.options)
+                ));
+            }
+        }
+
+        for (i, rel) in self.rels.iter().enumerate() {
+            if i == 0 && !buf.is_empty() {
+                buf.push('\n');
+            }
+            write_doc(&mut buf, "", rel.doc());
+            buf.push_str(&format!(
+                "{} {}--{} {}{}\n",
+                quote_name(&rel.entity1),
+                card_char(rel.card1),
+                card_char(rel.card2),
+                quote_name(&rel.entity2),
+                write_block(&rel.options)
+            ));
+        }
+        buf
+    }
+}
+
+/// Writes an option block (with a leading space), or nothing when there are
+/// no options.
+fn write_block(options: &Options) -> String {
+    let options: Vec<_> = options
+        .iter()
+        .map(|opt| format!("{}: \"{}\"", opt.html_attr_name(), opt.value()))
+        .collect();
+    if options.is_empty() {
+        String::new()
+    } else {
+        format!(" {{ {} }}", options.join(", "))
+    }
+}
+
+/// Writes a doc comment as `##` lines.
+fn write_doc(buf: &mut String, indent: &str, doc: Option<&str>) {
+    for line in doc.into_iter().flat_map(str::lines) {
+        buf.push_str(indent);
+        buf.push_str("##");
+        if !line.is_empty() {
+            buf.push(' ');
+            buf.push_str(line);
+        }
+        buf.push('\n');
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Kind {
+    Directive,
+    Entity,
+    Attribute,
+    Relation,
+}
+
+/// One line's worth of the document (give or take a multi-line option block).
+struct Item {
+    kind: Kind,
+    /// Where the item starts and ends in the input, not counting any option
+    /// block.
+    start: usize,
+    end: usize,
+    /// The item, up to but not including any option block.
+    head: String,
+    block: Option<Block>,
+}
+
+/// An option block and where its braces were found.
+struct Block {
+    open: usize,
+    close: usize,
+    /// Each option's text, and where it ends in the input.
+    options: Vec<(String, usize)>,
+}
+
+fn to_item(input: &str, pair: Pair<'_, Rule>) -> Item {
+    let kind = match pair.as_rule() {
+        Rule::directive => Kind::Directive,
+        Rule::entity_name => Kind::Entity,
+        Rule::attr => Kind::Attribute,
+        Rule::rel => Kind::Relation,
+        _ => unreachable!(),
+    };
+    let start = pair.as_span().start();
+
+    let mut head = String::new();
+    let mut head_end = start;
+    let mut options = vec![];
+    let (mut pk, mut fk) = (false, false);
+    for pair in pair.into_inner() {
+        let end = pair.as_span().end();
+        match pair.as_rule() {
+            Rule::dir_type => head.push_str(pair.as_str()),
+            Rule::keys => {
+                for key in pair.into_inner() {
+                    match key.as_rule() {
+                        Rule::ispk => pk = true,
+                        Rule::isfk => fk = true,
+                        _ => unreachable!(),
+                    }
+                }
+            }
+            Rule::ident | Rule::entity1 | Rule::entity2 => {
+                let ident = match pair.as_rule() {
+                    Rule::ident => pair.clone(),
+                    _ => pair.clone().into_inner().next().expect("ident"),
+                };
+                if pair.as_rule() == Rule::entity2 {
+                    head.push(' ');
+                }
+                if pk {
+                    head.push('*');
+                }
+                if fk {
+                    head.push('+');
+                }
+                head.push_str(&quote_ident(ident));
+                if pair.as_rule() == Rule::entity1 {
+                    head.push(' ');
+                }
+            }
+            Rule::card1 => head.push_str(&format!("{}--", pair.as_str())),
+            Rule::card2 => head.push_str(pair.as_str()),
+            Rule::opt_list => {
+                for pair in pair.into_inner().flatten() {
+                    if pair.as_rule() == Rule::option {
+                        let mut inner = pair.clone().into_inner();
+                        let name = inner.next().expect("opt_name").as_str();
+                        let value = inner.next().expect("opt_value").as_str();
+                        options.push((format!("{}: {}", name, value), pair.as_span().end()));
+                    }
+                }
+                continue;
+            }
+            _ => unreachable!(),
+        }
+        head_end = end;
+    }
+    if kind == Kind::Entity {
+        head = format!("[{}]", head);
+        // Step past the closing bracket.
+        head_end = input[head_end..].find(']').expect("]") + head_end + 1;
+    }
+
+    // Any option block is the first thing after the head, if there is one.
+    let after = &input[head_end..];
+    let block = match after.trim_start_matches(|c: char| c.is_whitespace() && c != '\n') {
+        rest if rest.starts_with('{') => {
+            let open = input.len() - rest.len();
+            let from = options.last().map_or(open + 1, |(_, end)| *end);
+            let close = from + find_outside_comments(&input[from..], '}').expect("}");
+            Some(Block {
+                open,
+                close,
+                options,
+            })
+        }
+        _ => None,
+    };
+
+    Item {
+        kind,
+        start,
+        end: head_end,
+        head,
+        block,
+    }
+}
+
+/// Writes an identifier, quoted with backticks only when it needs quotes.
+fn quote_ident(ident: Pair<'_, Rule>) -> String {
+    let name = ident
+        .into_inner()
+        .next()
+        .expect("ident_no_space or ident_quoted");
+    quote_name(name.as_str())
+}
+
+/// Writes a name as an identifier, quoted with backticks only when it needs
+/// quotes.
+pub(crate) fn quote_name(name: &str) -> String {
+    if needs_quotes(name) {
+        format!("`{}`", name)
+    } else {
+        name.to_string()
+    }
+}
+
+/// Whether a name has to be quoted to be used as an identifier.
+pub(crate) fn needs_quotes(name: &str) -> bool {
+    !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// A comment in the input, from the `#` up to the end of the line.
+struct Comment {
+    start: usize,
+    end: usize,
+    /// Whether the comment is on a line of its own, rather than trailing
+    /// something else.
+    own_line: bool,
+}
+
+/// Finds every comment in `input`.
+fn comments(input: &str) -> Vec<Comment> {
+    let mut comments = vec![];
+    let mut chars = input.char_indices();
+    let mut line_start = 0;
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\n' => line_start = i + 1,
+            // Skip quoted identifiers and option values, which may well hold
+            // a `#` (colors, say).
+            '"' | '\'' | '`' => {
+                for (j, d) in chars.by_ref() {
+                    if d == '\n' {
+                        line_start = j + 1;
+                    }
+                    if d == c {
+                        break;
+                    }
+                }
+            }
+            '#' => {
+                let end = input[i..].find('\n').map_or(input.len(), |n| i + n);
+                comments.push(Comment {
+                    start: i,
+                    end: i + input[i..end].trim_end().len(),
+                    own_line: input[line_start..i].trim().is_empty(),
+                });
+                // Leave the newline to be seen by the loop.
+                while chars.as_str().len() > input.len() - end {
+                    chars.next();
+                }
+            }
+            _ => (),
+        }
+    }
+    comments
+}
+
+/// Finds the first `c` in `text` which isn't in a comment.
+fn find_outside_comments(text: &str, c: char) -> Option<usize> {
+    let mut in_comment = false;
+    for (i, d) in text.char_indices() {
+        match d {
+            '#' => in_comment = true,
+            '\n' => in_comment = false,
+            _ if d == c && !in_comment => return Some(i),
+            _ => (),
+        }
+    }
+    None
+}
+
+/// Whether there's a blank line in `text`, which is expected to hold only
+/// whitespace.
+fn has_blank_line(text: &str) -> bool {
+    text.matches('\n').count() > 1
+}
+
+struct Formatter<'i> {
+    input: &'i str,
+    comments: Vec<Comment>,
+    /// The first comment which hasn't been written yet.
+    next_comment: usize,
+    /// The end of the last thing written, as an offset into the input.
+    last_end: usize,
+    buf: String,
+}
+
+impl<'i> Formatter<'i> {
+    fn item(&mut self, item: &Item, force_blank: bool) {
+        let indent = match item.kind {
+            Kind::Attribute => INDENT,
+            _ => "",
+        };
+
+        let mut first = true;
+        while let Some(comment) = self.comment_before(item.start) {
+            let blank =
+                has_blank_line(&self.input[self.last_end..comment.0]) || first && force_blank;
+            self.line(blank, indent, comment.1);
+            self.last_end = comment.2;
+            first = false;
+        }
+        let blank = has_blank_line(&self.input[self.last_end..item.start]) || first && force_blank;
+        self.blank(blank);
+
+        self.buf.push_str(indent);
+        self.buf.push_str(&item.head);
+        let end = match &item.block {
+            Some(block) => {
+                self.block(indent, block);
+                block.close + 1
+            }
+            None => item.end,
+        };
+        self.last_end = end;
+        self.trailing_comment();
+        self.buf.push('\n');
+    }
+
+    /// Writes an option block, on one line when there are no comments in it.
+    fn block(&mut self, indent: &str, block: &Block) {
+        let comments_in_block = self.comments[self.next_comment..]
+            .iter()
+            .any(|c| c.start < block.close);
+        if !comments_in_block {
+            let options: Vec<_> = block.options.iter().map(|(opt, _)| opt.as_str()).collect();
+            if options.is_empty() {
+                self.buf.push_str(" {}");
+            } else {
+                self.buf.push_str(&format!(" {{ {} }}", options.join(", ")));
+            }
+            return;
+        }
+
+        self.buf.push_str(" {");
+        self.last_end = block.open + 1;
+        let inner = format!("{}{}", indent, INDENT);
+        for (opt, end) in &block.options {
+            while let Some(comment) = self.comment_before(*end) {
+                self.block_line(&inner, comment.1);
+                self.last_end = comment.2;
+            }
+            self.block_line(&inner, &format!("{},", opt));
+            self.last_end = *end;
+            self.trailing_comment();
+        }
+        while let Some(comment) = self.comment_before(block.close) {
+            self.block_line(&inner, comment.1);
+            self.last_end = comment.2;
+        }
+        self.block_line(indent, "}");
+    }
+
+    /// Writes any comment left over after the last item.
+    fn rest(&mut self) {
+        while let Some(comment) = self.comment_before(self.input.len()) {
+            let blank = has_blank_line(&self.input[self.last_end..comment.0]);
+            self.line(blank, "", comment.1);
+            self.last_end = comment.2;
+        }
+    }
+
+    /// Takes the next comment when it's on a line of its own and starts before
+    /// `pos`, giving where it starts, its text and where it ends.
+    fn comment_before(&mut self, pos: usize) -> Option<(usize, &'i str, usize)> {
+        let comment = self.comments.get(self.next_comment)?;
+        if comment.start >= pos || !comment.own_line {
+            return None;
+        }
+        self.next_comment += 1;
+        Some((
+            comment.start,
+            &self.input[comment.start..comment.end],
+            comment.end,
+        ))
+    }
+
+    /// Appends the next comment to the current line when it trails whatever
+    /// was last written.
+    fn trailing_comment(&mut self) {
+        if let Some(comment) = self.comments.get(self.next_comment) {
+            if !comment.own_line && !self.input[self.last_end..comment.start].contains('\n') {
+                self.buf.push(' ');
+                self.buf.push_str(&self.input[comment.start..comment.end]);
+                self.last_end = comment.end;
+                self.next_comment += 1;
+            }
+        }
+    }
+
+    /// Writes a whole line, with a blank line before it if asked.
+    fn line(&mut self, blank: bool, indent: &str, text: &str) {
+        self.blank(blank);
+        self.buf.push_str(indent);
+        self.buf.push_str(text);
+        self.buf.push('\n');
+    }
+
+    /// Starts a new line inside an option block.
+    fn block_line(&mut self, indent: &str, text: &str) {
+        self.buf.push('\n');
+        self.buf.push_str(indent);
+        self.buf.push_str(text);
+    }
+
+    fn blank(&mut self, blank: bool) {
+        if blank && !self.buf.is_empty() && !self.buf.ends_with("\n\n") {
+            self.buf.push('\n');
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::format;
+    use crate::parser::parse;
+
+    /// Formats `input`, checking that formatting again changes nothing and
+    /// that the meaning of the document is left alone.
+    fn check(input: &str) -> String {
+        let formatted = format(input).unwrap();
+        assert_eq!(format(&formatted).unwrap(), formatted);
+        assert_eq!(parse(&formatted).unwrap(), parse(input).unwrap());
+        formatted
+    }
+
+    #[test]
+    fn test_format_basics_sample() {
+        let formatted = check(include_str!("../samples/basics.erd"));
+        assert!(formatted.starts_with("# Entities are declared in '[' ... ']'."));
+        assert!(formatted.contains("[Person]\n    *name\n    height\n"));
+        assert!(formatted.contains("\n\n[`Birth Place`]\n    *id\n    `birth city`\n"));
+        assert!(formatted.contains("# 1 or more      +\nPerson *--1 `Birth Place`\n"));
+    }
+
+    #[test]
+    fn test_format_normalizes_layout() {
+        let input = r##"title {label: "People",size:"20"}
+  [Person]{bgcolor: "#fff"}
++*'name'
+  'birth date'   { label: "date" }
+[Group]
+*id
+
+
+Person   *--1   Group{ label: "in" }
+"##;
+        assert_eq!(
+            check(input),
+            r##"title { label: "People", size: "20" }
+
+[Person] { bgcolor: "#fff" }
+    *+name
+    `birth date` { label: "date" }
+
+[Group]
+    *id
+
+Person *--1 Group { label: "in" }
+"##
+        );
+    }
+
+    #[test]
+    fn test_format_keeps_comments() {
+        let input = r#"# first
+title { label: "x" } # after a directive
+
+# before an entity
+[Person] # after a header
+# before an attribute
+  name # after an attribute
+# at the end
+"#;
+        assert_eq!(
+            check(input),
+            r#"# first
+title { label: "x" } # after a directive
+
+# before an entity
+[Person] # after a header
+    # before an attribute
+    name # after an attribute
+# at the end
+"#
+        );
+    }
+
+    #[test]
+    fn test_format_option_block_with_comments() {
+        let input = r#"[Person] { label: "p", # the label
+    # the color
+    color: "blue" }
+"#;
+        assert_eq!(
+            check(input),
+            r#"[Person] {
+    label: "p", # the label
+    # the color
+    color: "blue",
+}
+"#
+        );
+    }
+
+    #[test]
+    fn test_format_hash_in_values_is_not_a_comment() {
+        let input = "[Person] { bgcolor: \"#fff\", label: \"'#1'\" }\n";
+        assert_eq!(check(input), input);
+    }
+
+    #[test]
+    fn test_format_bad_input_is_err() {
+        assert!(format("[Person").is_err());
+    }
+
+    #[test]
+    fn test_to_erd() {
+        let input = r##"
+title { label: "People" }
+header {size: "20"}
+## Somebody.
+##
+## Anybody.
+[Person] {bgcolor: "#fff"}
+*name
+## Where they're from.
+*+`birth place id` { label: "fk", cellpadding: "2" }
+['Birth Place']
+id
+Person *--1 "Birth Place"
+## Again.
+`Birth Place` ?--+ Person {color: "red"}
+"##;
+        let er = parse(input).unwrap();
+        let written = er.to_erd();
+        assert_eq!(
+            written,
+            r##"title { label: "People" }
+header { size: "20" }
+
+## Somebody.
+##
+## Anybody.
+[Person] { bgcolor: "#fff" }
+    *name
+    ## Where they're from.
+    *+`birth place id` { label: "fk", cellpadding: "2" }
+
+[`Birth Place`]
+    id
+
+Person *--1 `Birth Place`
+## Again.
+`Birth Place` ?--+ Person { color: "red" }
+"##
+        );
+        assert_eq!(parse(&written).unwrap(), er);
+        assert_eq!(format(&written).unwrap(), written);
+        assert_eq!(parse("").unwrap().to_erd(), "");
+    }
+}
@@ -0,0 +1,91 @@
+//! What's shared by the importers for other diagram formats,
+//! [mermaid](../mermaid/index.html) and [dbml](../dbml/index.html).
+
+use crate::rename::check_name;
+use crate::span::Span;
+use crate::Error;
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
+
+/// Something in an imported diagram which couldn't be carried over as it
+/// was.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Warning {
+    pub message: String,
+    /// Where in the imported source it was found.
+    pub span: Span,
+}
+
+impl Display for Warning {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+/// An error for a document which can't be imported, found at `span`.
+pub(crate) fn invalid(span: Span, message: &str) -> Error {
+    Error::InvalidDocument {
+        message: message.to_string(),
+        span: Some(span),
+    }
+}
+
+/// Gives each of `names`, found at the span alongside it, a distinct valid er
+/// name, warning about every one which had to be changed.
+///
+/// Valid names are kept as they are. Invalid ones have the offending
+/// characters replaced, plus a numbered suffix when that would clash with
+/// another name.
+pub(crate) fn checked_names(names: &[(&str, Span)], warnings: &mut Vec<Warning>) -> Vec<String> {
+    let mut taken: HashSet<String> = names
+        .iter()
+        .filter(|(name, _)| check_name(name).is_ok())
+        .map(|(name, _)| name.to_string())
+        .collect();
+    names
+        .iter()
+        .map(|&(name, span)| {
+            if check_name(name).is_ok() {
+                return name.to_string();
+            }
+            let base = fixed_name(name);
+            let mut fixed = base.clone();
+            let mut n = 2;
+            while !taken.insert(fixed.clone()) {
+                fixed = format!("{}_{}", base, n);
+                n += 1;
+            }
+            let message = if fixed == base {
+                format!(
+                    "`{}` isn't a valid er name, so it was renamed to `{}`",
+                    name, fixed
+                )
+            } else {
+                format!(
+                    "`{}` isn't a valid er name and `{}` is taken, so it was renamed to `{}`",
+                    name, base, fixed
+                )
+            };
+            warnings.push(Warning { message, span });
+            fixed
+        })
+        .collect()
+}
+
+/// `name` with anything an er name can't have replaced.
+fn fixed_name(name: &str) -> String {
+    let fixed: String = name
+        .trim()
+        .chars()
+        .map(|c| match c {
+            c if c.is_ascii_alphanumeric() || c == '_' || c == ' ' => c,
+            '\t' => ' ',
+            _ => '_',
+        })
+        .collect();
+    if fixed.is_empty() {
+        "_".to_string()
+    } else {
+        fixed
+    }
+}
@@ -1,9 +1,27 @@
 #[macro_use]
 extern crate pest_derive;
 
+pub mod builder;
+pub mod cst;
+pub mod dbml;
+pub mod diff;
+pub mod dot;
 pub mod er;
 mod errors;
+pub mod fk;
+pub mod fmt;
+pub mod import;
+#[cfg(feature = "lsp")]
+pub mod lsp;
+pub mod mermaid;
 pub mod parser;
+pub mod plantuml;
+pub mod rename;
+#[cfg(feature = "serde")]
+pub mod schema;
+mod span;
+pub mod validate;
 
-pub use errors::Error;
+pub use errors::{Code, Diagnostic, Error, FloatError};
+pub use span::Span;
 pub type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,837 @@
+//! A language server for er files, spoken over stdio.
+//!
+//! Offers diagnostics (both parse errors and the problems found by
+//! [ER::validate](../er/struct.ER.html#method.validate)), go to definition
+//! and find references for entities, renaming entities, hover for entities,
+//! completion for option names and directives, and document symbols.
+//!
+//! Documents are synced in full on every change, and everything is worked out
+//! afresh from the text for each request. Er files are small enough that
+//! this is plenty quick, and it means a half-typed document still gets as
+//! much help as [parse_recovering](../parser/fn.parse_recovering.html) can
+//! salvage.
+
+use crate::cst::Cst;
+use crate::er::{card_char, Directive, Entity, ER, OPTION_NAMES};
+use crate::errors::option_blocks;
+use crate::parser::parse_recovering;
+use crate::rename::unquote;
+use crate::span::{LineIndex, Span};
+use crate::validate::Severity;
+use lsp_server::{Connection, Message, Notification, Request, RequestId, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument,
+    Notification as NotificationTrait, PublishDiagnostics,
+};
+use lsp_types::request::{
+    Completion, DocumentSymbolRequest, GotoDefinition, HoverRequest, References, Rename,
+    Request as RequestTrait,
+};
+use lsp_types::{
+    CompletionItem, CompletionItemKind, CompletionOptions, CompletionParams, CompletionResponse,
+    DiagnosticSeverity, DocumentSymbol, DocumentSymbolParams, DocumentSymbolResponse,
+    GotoDefinitionParams, GotoDefinitionResponse, Hover, HoverContents, HoverParams,
+    HoverProviderCapability, Location, MarkupContent, MarkupKind, NumberOrString, OneOf, Position,
+    PublishDiagnosticsParams, Range, ReferenceParams, RenameParams, ServerCapabilities, SymbolKind,
+    TextDocumentSyncCapability, TextDocumentSyncKind, TextEdit, Url, WorkspaceEdit,
+};
+use std::collections::HashMap;
+
+/// Errors which stop the server.
+pub type ServerError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Runs the server over stdin and stdout until the client asks it to exit.
+pub fn serve_stdio() -> Result<(), ServerError> {
+    let (connection, io_threads) = Connection::stdio();
+    serve(&connection)?;
+    io_threads.join()?;
+    Ok(())
+}
+
+/// Runs the server over `connection` until the client asks it to exit.
+pub fn serve(connection: &Connection) -> Result<(), ServerError> {
+    connection.initialize(serde_json::to_value(capabilities())?)?;
+
+    let mut server = Server::default();
+    for msg in &connection.receiver {
+        match msg {
+            Message::Request(req) => {
+                if connection.handle_shutdown(&req)? {
+                    return Ok(());
+                }
+                connection
+                    .sender
+                    .send(Message::Response(server.request(req)))?;
+            }
+            Message::Notification(not) => {
+                let method = not.method.clone();
+                match server.notification(not) {
+                    Ok(nots) => {
+                        for not in nots {
+                            connection.sender.send(Message::Notification(not))?;
+                        }
+                    }
+                    // One bad message from the client isn't worth stopping for.
+                    Err(e) => eprintln!("erd-lsp: ignoring malformed `{}`: {}", method, e),
+                }
+            }
+            Message::Response(_) => (),
+        }
+    }
+    Ok(())
+}
+
+fn capabilities() -> ServerCapabilities {
+    ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        definition_provider: Some(OneOf::Left(true)),
+        references_provider: Some(OneOf::Left(true)),
+        rename_provider: Some(OneOf::Left(true)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        completion_provider: Some(CompletionOptions {
+            trigger_characters: Some(vec!["{".to_string(), ",".to_string()]),
+            ..CompletionOptions::default()
+        }),
+        document_symbol_provider: Some(OneOf::Left(true)),
+        ..ServerCapabilities::default()
+    }
+}
+
+/// The open documents.
+#[derive(Default)]
+struct Server {
+    docs: HashMap<Url, String>,
+}
+
+impl Server {
+    /// Keeps track of the open documents, giving the diagnostics to publish.
+    ///
+    /// Fails when the notification's params can't be decoded.
+    fn notification(&mut self, not: Notification) -> Result<Vec<Notification>, serde_json::Error> {
+        let uri = match not.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let params: lsp_types::DidOpenTextDocumentParams =
+                    serde_json::from_value(not.params)?;
+                let uri = params.text_document.uri;
+                self.docs.insert(uri.clone(), params.text_document.text);
+                uri
+            }
+            DidChangeTextDocument::METHOD => {
+                let params: lsp_types::DidChangeTextDocumentParams =
+                    serde_json::from_value(not.params)?;
+                let uri = params.text_document.uri;
+                // With full syncing the last change holds the whole document.
+                if let Some(change) = params.content_changes.into_iter().last() {
+                    self.docs.insert(uri.clone(), change.text);
+                }
+                uri
+            }
+            DidCloseTextDocument::METHOD => {
+                let params: lsp_types::DidCloseTextDocumentParams =
+                    serde_json::from_value(not.params)?;
+                self.docs.remove(&params.text_document.uri);
+                return Ok(vec![publish(params.text_document.uri, vec![])]);
+            }
+            _ => return Ok(vec![]),
+        };
+
+        let diagnostics = diagnostics(&self.docs[&uri]);
+        Ok(vec![publish(uri, diagnostics)])
+    }
+
+    fn request(&self, req: Request) -> Response {
+        let result = match req.method.as_str() {
+            GotoDefinition::METHOD => {
+                self.handle(req.params, |uri, text, p: GotoDefinitionParams| {
+                    let pos = p.text_document_position_params.position;
+                    definition(text, pos).map(|range| {
+                        GotoDefinitionResponse::Scalar(Location::new(uri.clone(), range))
+                    })
+                })
+            }
+            References::METHOD => self.handle(req.params, |uri, text, p: ReferenceParams| {
+                let pos = p.text_document_position.position;
+                let include_declaration = p.context.include_declaration;
+                let ranges = references(text, pos, include_declaration);
+                Some(
+                    ranges
+                        .into_iter()
+                        .map(|range| Location::new(uri.clone(), range))
+                        .collect::<Vec<_>>(),
+                )
+            }),
+            Rename::METHOD => self.try_handle(req.params, |uri, text, p: RenameParams| {
+                let pos = p.text_document_position.position;
+                let edits = rename(text, pos, &p.new_name)?;
+                Ok(edits.map(|edits| WorkspaceEdit {
+                    changes: Some(std::iter::once((uri.clone(), edits)).collect()),
+                    ..WorkspaceEdit::default()
+                }))
+            }),
+            HoverRequest::METHOD => self.handle(req.params, |_, text, p: HoverParams| {
+                hover(text, p.text_document_position_params.position)
+            }),
+            Completion::METHOD => self.handle(req.params, |_, text, p: CompletionParams| {
+                let items = completions(text, p.text_document_position.position);
+                Some(CompletionResponse::Array(items))
+            }),
+            DocumentSymbolRequest::METHOD => {
+                self.handle(req.params, |_, text, _: DocumentSymbolParams| {
+                    Some(DocumentSymbolResponse::Nested(symbols(text)))
+                })
+            }
+            _ => {
+                return Response::new_err(
+                    req.id,
+                    lsp_server::ErrorCode::MethodNotFound as i32,
+                    format!("unhandled method `{}`", req.method),
+                )
+            }
+        };
+        response(req.id, result)
+    }
+
+    /// Decodes the params for a request and runs `f` against the document
+    /// the request is for.
+    fn handle<P, R, F>(&self, params: serde_json::Value, f: F) -> Result<serde_json::Value, String>
+    where
+        P: serde::de::DeserializeOwned + HasUri,
+        R: serde::Serialize,
+        F: FnOnce(&Url, &str, P) -> Option<R>,
+    {
+        self.try_handle(params, |uri, text, params| Ok(f(uri, text, params)))
+    }
+
+    /// Like [handle](#method.handle), for requests which can fail.
+    fn try_handle<P, R, F>(
+        &self,
+        params: serde_json::Value,
+        f: F,
+    ) -> Result<serde_json::Value, String>
+    where
+        P: serde::de::DeserializeOwned + HasUri,
+        R: serde::Serialize,
+        F: FnOnce(&Url, &str, P) -> Result<Option<R>, String>,
+    {
+        let params: P = serde_json::from_value(params).map_err(|e| e.to_string())?;
+        let uri = params.uri().clone();
+        let text = self
+            .docs
+            .get(&uri)
+            .ok_or_else(|| format!("document `{}` isn't open", uri))?;
+        serde_json::to_value(f(&uri, text, params)?).map_err(|e| e.to_string())
+    }
+}
+
+/// Request params which are about a single document.
+trait HasUri {
+    fn uri(&self) -> &Url;
+}
+
+impl HasUri for GotoDefinitionParams {
+    fn uri(&self) -> &Url {
+        &self.text_document_position_params.text_document.uri
+    }
+}
+
+impl HasUri for ReferenceParams {
+    fn uri(&self) -> &Url {
+        &self.text_document_position.text_document.uri
+    }
+}
+
+impl HasUri for RenameParams {
+    fn uri(&self) -> &Url {
+        &self.text_document_position.text_document.uri
+    }
+}
+
+impl HasUri for HoverParams {
+    fn uri(&self) -> &Url {
+        &self.text_document_position_params.text_document.uri
+    }
+}
+
+impl HasUri for CompletionParams {
+    fn uri(&self) -> &Url {
+        &self.text_document_position.text_document.uri
+    }
+}
+
+impl HasUri for DocumentSymbolParams {
+    fn uri(&self) -> &Url {
+        &self.text_document.uri
+    }
+}
+
+fn response(id: RequestId, result: Result<serde_json::Value, String>) -> Response {
+    match result {
+        Ok(value) => Response::new_ok(id, value),
+        Err(msg) => Response::new_err(id, lsp_server::ErrorCode::InvalidParams as i32, msg),
+    }
+}
+
+fn publish(uri: Url, diagnostics: Vec<lsp_types::Diagnostic>) -> Notification {
+    Notification::new(
+        PublishDiagnostics::METHOD.to_string(),
+        PublishDiagnosticsParams {
+            uri,
+            diagnostics,
+            version: None,
+        },
+    )
+}
+
+/// Everything wrong with `text`.
+///
+/// Validation is only done when the document parses cleanly, since a partial
+/// diagram is bound to be full of undefined entities.
+fn diagnostics(text: &str) -> Vec<lsp_types::Diagnostic> {
+    let (er, parse_diagnostics) = parse_recovering(text);
+    let lines = LineIndex::new(text);
+    if !parse_diagnostics.is_empty() {
+        return parse_diagnostics
+            .into_iter()
+            .map(|d| lsp_types::Diagnostic {
+                range: d
+                    .span
+                    .map_or_else(Range::default, |span| range(&lines, span)),
+                severity: Some(DiagnosticSeverity::ERROR),
+                code: Some(NumberOrString::String(d.code.to_string())),
+                source: Some("erd".to_string()),
+                message: d.message,
+                ..lsp_types::Diagnostic::default()
+            })
+            .collect();
+    }
+
+    er.validate()
+        .into_iter()
+        .map(|issue| lsp_types::Diagnostic {
+            range: range(&lines, issue.span()),
+            severity: Some(match issue.severity() {
+                Severity::Error => DiagnosticSeverity::ERROR,
+                Severity::Warning => DiagnosticSeverity::WARNING,
+            }),
+            source: Some("erd".to_string()),
+            message: issue.to_string(),
+            ..lsp_types::Diagnostic::default()
+        })
+        .collect()
+}
+
+/// The edits which rename the entity named under `pos` to `new_name`.
+///
+/// Editors pass along whatever was typed, so any quotes around `new_name` are
+/// dropped; the right ones are worked out for each place the name appears.
+fn rename(text: &str, pos: Position, new_name: &str) -> Result<Option<Vec<TextEdit>>, String> {
+    let (er, _) = parse_recovering(text);
+    let old = match entity_name_at(&er, text, pos) {
+        Some(name) => name,
+        None => return Ok(None),
+    };
+    let mut cst = Cst::parse(text).map_err(|e| e.to_string())?;
+    let edits = cst
+        .rename_entity(&old, unquote(new_name.trim()))
+        .map_err(|e| e.to_string())?;
+    let lines = LineIndex::new(text);
+    Ok(Some(
+        edits
+            .into_iter()
+            .map(|(span, new_text)| TextEdit::new(range(&lines, span), new_text))
+            .collect(),
+    ))
+}
+
+/// Finds the entity name under `pos`, either in an entity header or at one
+/// end of a relationship.
+fn entity_name_at(er: &ER, text: &str, pos: Position) -> Option<String> {
+    let offset = offset(text, pos);
+    let within = |span: Span| span.start <= offset && offset <= span.end;
+
+    let header = er
+        .entities
+        .iter()
+        .find(|e| within(e.name_span))
+        .map(|e| e.name.clone());
+    header.or_else(|| {
+        er.rels.iter().find_map(|rel| {
+            let (span1, span2) = rel.endpoint_spans();
+            if within(span1) {
+                Some(rel.entity1.clone())
+            } else if within(span2) {
+                Some(rel.entity2.clone())
+            } else {
+                None
+            }
+        })
+    })
+}
+
+fn find_entity<'e>(er: &'e ER, name: &str) -> Option<&'e Entity> {
+    er.entities.iter().find(|e| e.name == name)
+}
+
+/// Where the entity named under `pos` is declared.
+fn definition(text: &str, pos: Position) -> Option<Range> {
+    let (er, _) = parse_recovering(text);
+    let name = entity_name_at(&er, text, pos)?;
+    find_entity(&er, &name).map(|e| range(&LineIndex::new(text), e.name_span))
+}
+
+/// Everywhere the entity named under `pos` is mentioned.
+fn references(text: &str, pos: Position, include_declaration: bool) -> Vec<Range> {
+    let (er, _) = parse_recovering(text);
+    let name = match entity_name_at(&er, text, pos) {
+        Some(name) => name,
+        None => return vec![],
+    };
+
+    let mut spans = vec![];
+    if include_declaration {
+        spans.extend(
+            er.entities
+                .iter()
+                .filter(|e| e.name == name)
+                .map(|e| e.name_span),
+        );
+    }
+    for rel in &er.rels {
+        let (span1, span2) = rel.endpoint_spans();
+        if rel.entity1 == name {
+            spans.push(span1);
+        }
+        if rel.entity2 == name {
+            spans.push(span2);
+        }
+    }
+    spans.sort_by_key(|span| span.start);
+    let lines = LineIndex::new(text);
+    spans.into_iter().map(|span| range(&lines, span)).collect()
+}
+
+/// Describes the entity under `pos`: its docs, attributes and options.
+fn hover(text: &str, pos: Position) -> Option<Hover> {
+    let (er, _) = parse_recovering(text);
+    let name = entity_name_at(&er, text, pos)?;
+    let entity = find_entity(&er, &name)?;
+
+    let mut value = format!("**{}**\n", entity.name);
+    if let Some(doc) = entity.doc() {
+        value.push_str(&format!("\n{}\n", doc));
+    }
+    if !entity.attribs.is_empty() {
+        value.push('\n');
+        for attr in &entity.attribs {
+            let keys = match (attr.pk, attr.fk) {
+                (true, true) => "*+",
+                (true, false) => "*",
+                (false, true) => "+",
+                (false, false) => "",
+            };
+            value.push_str(&format!("- `{}{}`", keys, attr.field));
+            if let Some(doc) = attr.doc() {
+                value.push_str(&format!(" — {}", doc.replace('\n', " ")));
+            }
+            value.push('\n');
+        }
+    }
+    for (what, opts) in [
+        ("Header", er.effective_header_options(entity)),
+        ("Entity", er.effective_entity_options(entity)),
+    ] {
+        let opts: Vec<_> = opts
+            .iter()
+            .map(|opt| format!("`{}: \"{}\"`", opt.html_attr_name(), opt.value()))
+            .collect();
+        if !opts.is_empty() {
+            value.push_str(&format!("\n{} options: {}\n", what, opts.join(", ")));
+        }
+    }
+
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value,
+        }),
+        range: None,
+    })
+}
+
+/// Option names when inside an option block (and not in a value), or
+/// directive names at the start of a line before any entities or
+/// relationships.
+fn completions(text: &str, pos: Position) -> Vec<CompletionItem> {
+    let offset = offset(text, pos);
+
+    let block = option_blocks(text)
+        .into_iter()
+        .find(|(open, close)| *open < offset && close.is_none_or(|close| offset <= close));
+    if let Some((open, _)) = block {
+        let since = &text[open + 1..offset];
+        let current = since.rsplit(',').next().unwrap_or(since);
+        if current.contains(':') {
+            return vec![];
+        }
+        return OPTION_NAMES
+            .iter()
+            .map(|name| CompletionItem {
+                label: name.to_string(),
+                kind: Some(CompletionItemKind::PROPERTY),
+                ..CompletionItem::default()
+            })
+            .collect();
+    }
+
+    let line_start = text[..offset].rfind('\n').map_or(0, |i| i + 1);
+    let word = text[line_start..offset].trim_start();
+    if !word.chars().all(|c| c.is_ascii_alphabetic()) {
+        return vec![];
+    }
+    let (er, _) = parse_recovering(text);
+    let body_start = er
+        .entities
+        .iter()
+        .map(|e| e.span.start)
+        .chain(er.rels.iter().map(|r| r.span.start))
+        .min();
+    if body_start.is_some_and(|start| start < line_start) {
+        return vec![];
+    }
+    Directive::ALL
+        .iter()
+        .map(|d| CompletionItem {
+            label: d.name().to_string(),
+            kind: Some(CompletionItemKind::KEYWORD),
+            ..CompletionItem::default()
+        })
+        .collect()
+}
+
+/// Entities (with their attributes), relationships and directives.
+#[allow(deprecated)] // `DocumentSymbol::deprecated` has to be given regardless.
+fn symbols(text: &str) -> Vec<DocumentSymbol> {
+    let (er, _) = parse_recovering(text);
+    let lines = LineIndex::new(text);
+    let symbol = |name: String, kind, span: Span, selection: Span, children| DocumentSymbol {
+        name,
+        detail: None,
+        kind,
+        tags: None,
+        deprecated: None,
+        range: range(&lines, span),
+        selection_range: range(&lines, selection),
+        children,
+    };
+
+    let mut symbols = vec![];
+    for (directive, span) in er.globals.directive_spans() {
+        let name = directive.name().to_string();
+        symbols.push(symbol(name, SymbolKind::PROPERTY, *span, *span, None));
+    }
+    for entity in &er.entities {
+        let attrs = entity
+            .attribs
+            .iter()
+            .map(|attr| {
+                let name = attr.field.clone();
+                symbol(name, SymbolKind::FIELD, attr.span, attr.span, None)
+            })
+            .collect();
+        // The entity's range covers its attributes too.
+        let end = entity.attribs.last().map_or(entity.span, |a| a.span);
+        let span = Span {
+            end: end.end,
+            ..entity.span
+        };
+        let name = entity.name.clone();
+        symbols.push(symbol(
+            name,
+            SymbolKind::STRUCT,
+            span,
+            entity.name_span,
+            Some(attrs),
+        ));
+    }
+    for rel in &er.rels {
+        let name = format!(
+            "{} {}--{} {}",
+            rel.entity1,
+            card_char(rel.card1),
+            card_char(rel.card2),
+            rel.entity2
+        );
+        symbols.push(symbol(
+            name,
+            SymbolKind::INTERFACE,
+            rel.span,
+            rel.span,
+            None,
+        ));
+    }
+    symbols.sort_by_key(|s| (s.range.start.line, s.range.start.character));
+    symbols
+}
+
+/// Converts a byte offset in the text `lines` indexes to an lsp position,
+/// which counts characters in utf-16 code units.
+fn position(lines: &LineIndex, offset: usize) -> Position {
+    let (line, character) = lines.utf16_position(offset);
+    Position::new(line as u32, character as u32)
+}
+
+/// Converts an lsp position to a byte offset in `text`, clamping to the end
+/// of the line (or document) when the position is past it.
+fn offset(text: &str, pos: Position) -> usize {
+    let mut line_start = 0;
+    for _ in 0..pos.line {
+        match text[line_start..].find('\n') {
+            Some(i) => line_start += i + 1,
+            None => return text.len(),
+        }
+    }
+
+    let mut units = 0;
+    for (i, c) in text[line_start..].char_indices() {
+        if units >= pos.character as usize || c == '\n' {
+            return line_start + i;
+        }
+        units += c.len_utf16();
+    }
+    text.len()
+}
+
+fn range(lines: &LineIndex, span: Span) -> Range {
+    Range::new(position(lines, span.start), position(lines, span.end))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lsp_types::{
+        DidOpenTextDocumentParams, InitializeParams, PartialResultParams, ReferenceContext,
+        TextDocumentIdentifier, TextDocumentItem, TextDocumentPositionParams,
+        WorkDoneProgressParams,
+    };
+    use std::thread;
+
+    const DOC: &str = r##"title { label: "people" }
+
+## Somebody.
+[Person] { bgcolor: "#fff" }
+    *name
+    +`birth place_id`
+
+[`birth place`]
+    *id
+
+Person *--1 `birth place`
+`birth place` ?--* Person
+"##;
+
+    fn uri() -> Url {
+        Url::parse("file:///test.erd").unwrap()
+    }
+
+    fn position_params(pos: Position) -> TextDocumentPositionParams {
+        TextDocumentPositionParams {
+            text_document: TextDocumentIdentifier { uri: uri() },
+            position: pos,
+        }
+    }
+
+    /// Drives the server through `connection` the way an editor would.
+    struct Client {
+        connection: Connection,
+        next_id: i32,
+    }
+
+    impl Client {
+        fn request<R: RequestTrait>(&mut self, params: R::Params) -> Response {
+            self.next_id += 1;
+            let req = Request::new(self.next_id.into(), R::METHOD.to_string(), params);
+            self.connection.sender.send(req.into()).unwrap();
+            match self.connection.receiver.recv().unwrap() {
+                Message::Response(resp) => resp,
+                msg => panic!("expected a response, got {:?}", msg),
+            }
+        }
+
+        fn notify<N: NotificationTrait>(&mut self, params: N::Params) {
+            let not = Notification::new(N::METHOD.to_string(), params);
+            self.connection.sender.send(not.into()).unwrap();
+        }
+
+        fn receive_notification(&mut self) -> Notification {
+            match self.connection.receiver.recv().unwrap() {
+                Message::Notification(not) => not,
+                msg => panic!("expected a notification, got {:?}", msg),
+            }
+        }
+    }
+
+    #[test]
+    fn test_scripted_session() {
+        let (server, client) = Connection::memory();
+        let handle = thread::spawn(move || serve(&server));
+        let mut client = Client {
+            connection: client,
+            next_id: 0,
+        };
+
+        #[allow(deprecated)]
+        let init = InitializeParams::default();
+        let resp = client.request::<lsp_types::request::Initialize>(init);
+        assert!(resp.result.unwrap()["capabilities"]["hoverProvider"] == true);
+        client.notify::<lsp_types::notification::Initialized>(lsp_types::InitializedParams {});
+
+        // A malformed notification is skipped rather than ending the session.
+        let bad = Notification::new(
+            DidOpenTextDocument::METHOD.to_string(),
+            serde_json::json!({ "textDocument": 5 }),
+        );
+        client.connection.sender.send(bad.into()).unwrap();
+
+        client.notify::<DidOpenTextDocument>(DidOpenTextDocumentParams {
+            text_document: TextDocumentItem::new(uri(), "erd".to_string(), 1, DOC.to_string()),
+        });
+        let not = client.receive_notification();
+        assert_eq!(not.method, PublishDiagnostics::METHOD);
+        let params: PublishDiagnosticsParams = serde_json::from_value(not.params).unwrap();
+        assert_eq!(params.diagnostics, vec![]);
+
+        let resp = client.request::<GotoDefinition>(GotoDefinitionParams {
+            text_document_position_params: position_params(Position::new(10, 15)),
+            work_done_progress_params: WorkDoneProgressParams::default(),
+            partial_result_params: PartialResultParams::default(),
+        });
+        let location: Location = serde_json::from_value(resp.result.unwrap()).unwrap();
+        assert_eq!(
+            location.range,
+            Range::new(Position::new(7, 1), Position::new(7, 14))
+        );
+
+        let resp = client.request::<References>(ReferenceParams {
+            text_document_position: position_params(Position::new(3, 3)),
+            work_done_progress_params: WorkDoneProgressParams::default(),
+            partial_result_params: PartialResultParams::default(),
+            context: ReferenceContext {
+                include_declaration: true,
+            },
+        });
+        let locations: Vec<Location> = serde_json::from_value(resp.result.unwrap()).unwrap();
+        let lines: Vec<_> = locations.iter().map(|l| l.range.start.line).collect();
+        assert_eq!(lines, vec![3, 10, 11]);
+
+        let resp = client.request::<Rename>(RenameParams {
+            text_document_position: position_params(Position::new(11, 2)),
+            new_name: "`place`".to_string(),
+            work_done_progress_params: WorkDoneProgressParams::default(),
+        });
+        let edit: WorkspaceEdit = serde_json::from_value(resp.result.unwrap()).unwrap();
+        let edits = &edit.changes.unwrap()[&uri()];
+        let edits: Vec<_> = edits
+            .iter()
+            .map(|e| (e.range.start.line, e.new_text.as_str()))
+            .collect();
+        assert_eq!(edits, vec![(7, "place"), (10, "place"), (11, "place")]);
+
+        let resp = client.request::<Rename>(RenameParams {
+            text_document_position: position_params(Position::new(3, 3)),
+            new_name: "birth place".to_string(),
+            work_done_progress_params: WorkDoneProgressParams::default(),
+        });
+        assert!(resp.error.unwrap().message.contains("already"));
+
+        let resp = client.request::<lsp_types::request::Shutdown>(());
+        assert!(resp.error.is_none());
+        client.notify::<lsp_types::notification::Exit>(());
+        handle.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let diagnostics = super::diagnostics("[Foo\n[Bar]\n  a { size: \"big\" }\n");
+        let lines: Vec<_> = diagnostics.iter().map(|d| d.range.start.line).collect();
+        assert_eq!(lines, vec![0, 2]);
+        assert_eq!(
+            diagnostics[1].code,
+            Some(NumberOrString::String("E0008".to_string()))
+        );
+
+        let diagnostics = super::diagnostics("[Foo]\n*id\nFoo 1--1 Bar\n");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity, Some(DiagnosticSeverity::ERROR));
+        assert_eq!(
+            diagnostics[0].range,
+            Range::new(Position::new(2, 9), Position::new(2, 12))
+        );
+    }
+
+    #[test]
+    fn test_hover() {
+        let hover = super::hover(DOC, Position::new(10, 2)).unwrap();
+        let value = match hover.contents {
+            HoverContents::Markup(markup) => markup.value,
+            _ => unreachable!(),
+        };
+        assert!(value.starts_with("**Person**\n\nSomebody.\n"));
+        assert!(value.contains("- `*name`\n- `+birth place_id`\n"));
+        assert!(value.contains(r##"`bgcolor: "#fff"`"##));
+        assert!(value.contains(r#"`font: "Helvetica"`"#));
+
+        assert!(super::hover(DOC, Position::new(0, 2)).is_none());
+    }
+
+    #[test]
+    fn test_completions() {
+        let labels = |pos| -> Vec<String> {
+            completions(DOC, pos)
+                .into_iter()
+                .map(|item| item.label)
+                .collect()
+        };
+        assert_eq!(labels(Position::new(3, 11)).len(), OPTION_NAMES.len());
+        assert!(labels(Position::new(3, 11)).contains(&"border-color".to_string()));
+        // In the middle of a value.
+        assert_eq!(labels(Position::new(3, 22)), Vec::<String>::new());
+        assert_eq!(
+            labels(Position::new(1, 0)),
+            vec!["title", "header", "entity", "relationship"]
+        );
+        // Too late for directives.
+        assert_eq!(labels(Position::new(9, 0)), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_symbols() {
+        let symbols = super::symbols(DOC);
+        let names: Vec<_> = symbols.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "title",
+                "Person",
+                "birth place",
+                "Person *--1 birth place",
+                "birth place ?--* Person"
+            ]
+        );
+        let person = &symbols[1];
+        assert_eq!(person.range.start, Position::new(3, 0));
+        assert_eq!(person.range.end, Position::new(5, 21));
+        assert_eq!(person.children.as_ref().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_positions() {
+        let text = "ab\n`é𝄞x`\n";
+        let lines = LineIndex::new(text);
+        for offset in [0, 2, 3, 4, 6, 10, 11, 12] {
+            assert_eq!(super::offset(text, position(&lines, offset)), offset);
+        }
+        assert_eq!(position(&lines, 10), Position::new(1, 4));
+        // Past the end of a line.
+        assert_eq!(super::offset(text, Position::new(0, 10)), 2);
+    }
+}
@@ -0,0 +1,887 @@
+//! Converts between an [ER](../er/struct.ER.html) and a Mermaid `erDiagram`.
+//!
+//! Mermaid is rendered natively by GitHub and GitLab markdown, so this is
+//! handy for docs where graphviz isn't available. It can express less than a
+//! dot document can: formatting options are dropped, except for the title and
+//! relationship labels.
+//!
+//! Mermaid identifiers can't contain spaces, so they're replaced with
+//! underscores (`Birth Place` becomes `Birth_Place`), and an underscore is
+//! added to the front of names starting with a digit. A name which would end
+//! up the same as another entity's gets a numbered suffix.
+//!
+//! Mermaid attributes need a type, which er files don't have, so every
+//! attribute is given the type [ATTRIBUTE_TYPE](constant.ATTRIBUTE_TYPE.html).
+//! Doc comments on attributes become their Mermaid comments.
+//!
+//! [import](fn.import.html) goes the other way. Attribute types become the
+//! attribute's label (apart from `ATTRIBUTE_TYPE`) and comments become their
+//! docs. Anything else which can't be carried over (styling, `UK` keys,
+//! non-identifying relationships and so on) is left out with a
+//! [Warning](../import/struct.Warning.html), as are names which had to be changed to
+//! be valid in an er file.
+
+use crate::er::{
+    Attribute, Cardinality, Directive, Entity, GlobalOptions, Opt, Options, Relation, ER,
+};
+use crate::import::{checked_names, invalid, Warning};
+use crate::span::{LineIndex, Span};
+use crate::{Error, Result};
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+
+/// The type given to every attribute.
+pub const ATTRIBUTE_TYPE: &str = "attr";
+
+impl ER {
+    /// Renders the diagram as a Mermaid `erDiagram`.
+    ///
+    /// See [render](../mermaid/fn.render.html).
+    pub fn to_mermaid(&self) -> String {
+        let mut buf = vec![];
+        render(self, &mut buf).expect("writing to a Vec can't fail");
+        String::from_utf8(buf).expect("mermaid output is always utf-8")
+    }
+}
+
+/// Writes the Mermaid representation of `er` to `out`.
+pub fn render<W: Write>(er: &ER, mut out: W) -> Result<()> {
+    if let Some(title) = er.effective_title_options().label() {
+        writeln!(out, "---")?;
+        writeln!(out, "title: {}", comment(title))?;
+        writeln!(out, "---")?;
+    }
+    writeln!(out, "erDiagram")?;
+
+    let ids = entity_ids(er);
+    for entity in &er.entities {
+        let id = &ids[entity.name.as_str()];
+        if entity.attribs.is_empty() {
+            writeln!(out, "    {}", id)?;
+            continue;
+        }
+        writeln!(out, "    {} {{", id)?;
+        for attr in &entity.attribs {
+            let mut line = format!("{} {}", ATTRIBUTE_TYPE, sanitize(&attr.field));
+            let keys: Vec<_> = [(attr.pk, "PK"), (attr.fk, "FK")]
+                .iter()
+                .filter(|(is_key, _)| *is_key)
+                .map(|(_, key)| *key)
+                .collect();
+            if !keys.is_empty() {
+                line.push(' ');
+                line.push_str(&keys.join(", "));
+            }
+            if let Some(doc) = attr.doc() {
+                line.push_str(&format!(r#" "{}""#, comment(doc)));
+            }
+            writeln!(out, "        {}", line)?;
+        }
+        writeln!(out, "    }}")?;
+    }
+
+    for rel in &er.rels {
+        let id = |name: &str| match ids.get(name) {
+            Some(id) => id.clone(),
+            // Mermaid adds entities it hasn't seen yet, like graphviz does.
+            None => sanitize(name),
+        };
+        let opts = er.effective_relation_options(rel);
+        let label = opts.label().map(|l| comment(l)).unwrap_or_default();
+        writeln!(
+            out,
+            r#"    {} {}--{} {} : "{}""#,
+            id(&rel.entity1),
+            left_marker(rel.card1),
+            right_marker(rel.card2),
+            id(&rel.entity2),
+            label
+        )?;
+    }
+    Ok(())
+}
+
+/// The marker for a cardinality on the left of a relationship.
+///
+/// PlantUML draws crow's feet with the same markers.
+pub(crate) fn left_marker(card: Cardinality) -> &'static str {
+    match card {
+        Cardinality::ZeroOne => "|o",
+        Cardinality::One => "||",
+        Cardinality::ZeroPlus => "}o",
+        Cardinality::OnePlus => "}|",
+    }
+}
+
+/// The marker for a cardinality on the right of a relationship, which is the
+/// left marker mirrored.
+pub(crate) fn right_marker(card: Cardinality) -> &'static str {
+    match card {
+        Cardinality::ZeroOne => "o|",
+        Cardinality::One => "||",
+        Cardinality::ZeroPlus => "o{",
+        Cardinality::OnePlus => "|{",
+    }
+}
+
+/// Picks a distinct Mermaid identifier for each entity name.
+pub(crate) fn entity_ids(er: &ER) -> HashMap<&str, String> {
+    let mut ids = HashMap::new();
+    let mut taken = HashSet::new();
+    for entity in &er.entities {
+        if ids.contains_key(entity.name.as_str()) {
+            continue;
+        }
+        let base = sanitize(&entity.name);
+        let mut id = base.clone();
+        let mut n = 2;
+        while !taken.insert(id.clone()) {
+            id = format!("{}_{}", base, n);
+            n += 1;
+        }
+        ids.insert(entity.name.as_str(), id);
+    }
+    ids
+}
+
+/// Turns a name into something Mermaid (or PlantUML) accepts as an
+/// identifier.
+pub(crate) fn sanitize(name: &str) -> String {
+    let id = name.split_whitespace().collect::<Vec<_>>().join("_");
+    if id.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("_{}", id)
+    } else {
+        id
+    }
+}
+
+/// Squashes text onto one line without double quotes, for writing in a
+/// Mermaid string.
+pub(crate) fn comment(text: &str) -> String {
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .replace('"', "'")
+}
+
+/// Reads a Mermaid `erDiagram`, along with a warning for everything which was
+/// left out or changed on the way.
+///
+/// Entities are added for names which only turn up in relationships, the same
+/// as Mermaid does. Fails with
+/// [Error::InvalidDocument](../enum.Error.html#variant.InvalidDocument) when
+/// `input` isn't an `erDiagram` Mermaid would accept.
+pub fn import(input: &str) -> Result<(ER, Vec<Warning>)> {
+    let index = LineIndex::new(input);
+    let mut warnings = vec![];
+    let (title, body) = front_matter(input, &index, &mut warnings);
+    let tokens = lex(input, body, &index)?;
+    let mut importer = Importer {
+        tokens,
+        pos: 0,
+        end: index.span(input.len(), input.len()),
+        entities: vec![],
+        ids: HashMap::new(),
+        rels: vec![],
+        warnings,
+    };
+    importer.diagram()?;
+    Ok(importer.finish(title))
+}
+
+/// Reads the title from any front matter (a block between `---` lines at the
+/// start), giving it along with the offset the diagram starts at.
+fn front_matter(
+    input: &str,
+    index: &LineIndex,
+    warnings: &mut Vec<Warning>,
+) -> (Option<String>, usize) {
+    let start = input.len() - input.trim_start().len();
+    if !input[start..].starts_with("---") {
+        return (None, 0);
+    }
+
+    let mut title = None;
+    let mut offset = start + input[start..].find('\n').map_or(input.len(), |i| i + 1);
+    while offset < input.len() {
+        let end = input[offset..]
+            .find('\n')
+            .map_or(input.len(), |i| offset + i + 1);
+        let line = &input[offset..end];
+        if line.trim() == "---" {
+            return (title, end);
+        }
+        let span = index.trimmed_span(offset, end);
+        // Anything indented is nested under a key, which has already been
+        // warned about if need be.
+        let nested = line.starts_with(char::is_whitespace);
+        match line.trim().split_once(':') {
+            Some(("title", value)) => {
+                let value = value.trim().trim_matches(|c| c == '"' || c == '\'');
+                if value.contains('"') {
+                    warnings.push(Warning {
+                        message: "titles can't contain a double quote".to_string(),
+                        span,
+                    });
+                } else {
+                    title = Some(value.to_string());
+                }
+            }
+            Some((key, _)) if !nested => warnings.push(Warning {
+                message: format!("front matter `{}` isn't supported", key.trim()),
+                span,
+            }),
+            _ => {}
+        }
+        offset = end;
+    }
+    (title, input.len())
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Tok {
+    Word(String),
+    Str(String),
+    /// The cardinality markers and line of a relationship, eg. `||--o{`.
+    Rel(String),
+    Punct(char),
+    Newline,
+    /// Anything else, which is only allowed in lines that are skipped.
+    Other(char),
+}
+
+/// Splits the diagram starting at `start` into tokens.
+///
+/// Attribute types can contain brackets (`string[]`, `varchar(255)`), so
+/// words are lexed differently inside an entity's `{ ... }`.
+fn lex(input: &str, start: usize, index: &LineIndex) -> Result<Vec<(Tok, Span)>> {
+    let word = |c: char, in_block: bool| {
+        c.is_alphanumeric() || c == '_' || c == '-' || (in_block && "*[]()".contains(c))
+    };
+    let mut tokens = vec![];
+    let mut in_block = false;
+    let mut pos = start;
+    while let Some(c) = input[pos..].chars().next() {
+        let rest = &input[pos..];
+        let len = if c == '\n' {
+            tokens.push((Tok::Newline, index.span(pos, pos + 1)));
+            1
+        } else if c.is_whitespace() {
+            c.len_utf8()
+        } else if rest.starts_with("%%") {
+            rest.find('\n').unwrap_or(rest.len())
+        } else if c == '"' {
+            let len = match rest[1..].find(['"', '\n']) {
+                Some(i) if rest[1 + i..].starts_with('"') => i + 2,
+                _ => return Err(invalid(index.span(pos, pos + 1), "unclosed string")),
+            };
+            let text = rest[1..len - 1].to_string();
+            tokens.push((Tok::Str(text), index.span(pos, pos + len)));
+            len
+        } else if !in_block && (c == '|' || c == '}') {
+            if !is_rel(rest) {
+                let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+                let message = format!("unknown relationship `{}`", &rest[..end]);
+                return Err(invalid(index.span(pos, pos + end), &message));
+            }
+            tokens.push((Tok::Rel(rest[..6].to_string()), index.span(pos, pos + 6)));
+            6
+        } else if "{}[]:,".contains(c) {
+            in_block = match c {
+                '{' => true,
+                '}' => false,
+                _ => in_block,
+            };
+            tokens.push((Tok::Punct(c), index.span(pos, pos + 1)));
+            1
+        } else if word(c, in_block) {
+            let len = rest.find(|c| !word(c, in_block)).unwrap_or(rest.len());
+            tokens.push((
+                Tok::Word(rest[..len].to_string()),
+                index.span(pos, pos + len),
+            ));
+            len
+        } else {
+            tokens.push((Tok::Other(c), index.span(pos, pos + c.len_utf8())));
+            c.len_utf8()
+        };
+        pos += len;
+    }
+    Ok(tokens)
+}
+
+/// Whether `text` starts with a relationship, eg. `||--o{`.
+fn is_rel(text: &str) -> bool {
+    let (left, line, right) = match (text.get(..2), text.get(2..4), text.get(4..6)) {
+        (Some(left), Some(line), Some(right)) => (left, line, right),
+        _ => return false,
+    };
+    card_for(left, left_marker).is_some()
+        && (line == "--" || line == "..")
+        && card_for(right, right_marker).is_some()
+}
+
+/// The cardinality drawn with `marker` by `markers`.
+fn card_for(marker: &str, markers: fn(Cardinality) -> &'static str) -> Option<Cardinality> {
+    use Cardinality::*;
+    [ZeroOne, One, ZeroPlus, OnePlus]
+        .iter()
+        .copied()
+        .find(|&card| markers(card) == marker)
+}
+
+/// An entity as it's found, before its name is checked.
+struct Found {
+    id: String,
+    /// Given with `id["alias"]`, which Mermaid shows in place of the id.
+    alias: Option<String>,
+    attribs: Vec<Attribute>,
+    span: Span,
+}
+
+struct Importer {
+    tokens: Vec<(Tok, Span)>,
+    pos: usize,
+    /// Where the input ends, for errors about things missing from the end.
+    end: Span,
+    entities: Vec<Found>,
+    /// Indexes into `entities` by id.
+    ids: HashMap<String, usize>,
+    /// The ids at each end, the cardinalities and the label.
+    rels: Vec<(String, String, Cardinality, Cardinality, Option<String>)>,
+    warnings: Vec<Warning>,
+}
+
+impl Importer {
+    fn peek(&self) -> Option<&Tok> {
+        self.tokens.get(self.pos).map(|(tok, _)| tok)
+    }
+
+    fn span(&self) -> Span {
+        self.tokens
+            .get(self.pos)
+            .map_or(self.end, |(_, span)| *span)
+    }
+
+    fn next(&mut self) -> Option<(Tok, Span)> {
+        let tok = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        tok
+    }
+
+    fn expect(&mut self, punct: char) -> Result<()> {
+        match self.next() {
+            Some((Tok::Punct(c), _)) if c == punct => Ok(()),
+            _ => Err(self.expected(&format!("`{}`", punct))),
+        }
+    }
+
+    /// An error for the last token taken not being what was expected.
+    fn expected(&self, what: &str) -> Error {
+        let span = match self.tokens.get(self.pos.saturating_sub(1)) {
+            Some((_, span)) if self.pos <= self.tokens.len() => *span,
+            _ => self.end,
+        };
+        invalid(span, &format!("expected {}", what))
+    }
+
+    fn warn(&mut self, span: Span, message: String) {
+        self.warnings.push(Warning { message, span });
+    }
+
+    fn skip_newlines(&mut self) {
+        while self.peek() == Some(&Tok::Newline) {
+            self.pos += 1;
+        }
+    }
+
+    /// Skips the rest of the line.
+    fn skip_line(&mut self) {
+        while !matches!(self.peek(), None | Some(Tok::Newline)) {
+            self.pos += 1;
+        }
+    }
+
+    fn diagram(&mut self) -> Result<()> {
+        self.skip_newlines();
+        match self.next() {
+            Some((Tok::Word(w), _)) if w == "erDiagram" => {}
+            _ => return Err(self.expected("`erDiagram`")),
+        }
+        loop {
+            self.skip_newlines();
+            let span = self.span();
+            match self.next() {
+                None => return Ok(()),
+                Some((Tok::Word(w), _)) if w == "direction" => {
+                    self.warn(span, "`direction` isn't supported".to_string());
+                    self.skip_line();
+                }
+                Some((Tok::Word(w), _))
+                    if ["style", "classDef", "class", "accTitle"].contains(&w.as_str()) =>
+                {
+                    self.warn(span, format!("`{}` isn't supported", w));
+                    self.skip_line();
+                }
+                Some((Tok::Word(w), _)) if w == "accDescr" => {
+                    self.warn(span, "`accDescr` isn't supported".to_string());
+                    if self.peek() == Some(&Tok::Punct('{')) {
+                        while !matches!(self.next(), None | Some((Tok::Punct('}'), _))) {}
+                    }
+                    self.skip_line();
+                }
+                Some((Tok::Word(id), _)) | Some((Tok::Str(id), _)) => self.statement(id, span)?,
+                _ => return Err(self.expected("an entity or relationship")),
+            }
+        }
+    }
+
+    /// Reads an entity or relationship starting with the name `id`.
+    fn statement(&mut self, id: String, span: Span) -> Result<()> {
+        let alias = if self.peek() == Some(&Tok::Punct('[')) {
+            self.pos += 1;
+            let alias = match self.next() {
+                Some((Tok::Str(alias), _)) => alias,
+                _ => return Err(self.expected("a quoted alias")),
+            };
+            self.expect(']')?;
+            Some(alias)
+        } else {
+            None
+        };
+        self.class_shorthand()?;
+
+        let index = self.found(id.clone(), span);
+        if alias.is_some() {
+            self.entities[index].alias = alias;
+        }
+        match self.peek() {
+            Some(Tok::Rel(_)) => self.relation(id),
+            Some(Tok::Punct('{')) => {
+                self.pos += 1;
+                self.attributes(index)
+            }
+            None | Some(Tok::Newline) => Ok(()),
+            _ => {
+                self.pos += 1;
+                Err(self.expected("`{`, a relationship or the end of the line"))
+            }
+        }
+    }
+
+    /// Skips `:::class` after an entity name, with a warning.
+    fn class_shorthand(&mut self) -> Result<()> {
+        let start = self.span();
+        let colons = self.tokens[self.pos..]
+            .iter()
+            .take_while(|(tok, _)| *tok == Tok::Punct(':'))
+            .count();
+        if colons != 3 {
+            return Ok(());
+        }
+        self.pos += 3;
+        match self.next() {
+            Some((Tok::Word(_), _)) => {
+                self.warn(start, "classes (`:::`) aren't supported".to_string());
+                Ok(())
+            }
+            _ => Err(self.expected("a class name")),
+        }
+    }
+
+    /// The index of the entity with `id`, adding it if it's new.
+    fn found(&mut self, id: String, span: Span) -> usize {
+        if let Some(&index) = self.ids.get(&id) {
+            return index;
+        }
+        self.ids.insert(id.clone(), self.entities.len());
+        self.entities.push(Found {
+            id,
+            alias: None,
+            attribs: vec![],
+            span,
+        });
+        self.entities.len() - 1
+    }
+
+    /// Reads a relationship from its cardinalities on, `id` being the entity
+    /// on the left.
+    fn relation(&mut self, id: String) -> Result<()> {
+        let (marker, span) = match self.next() {
+            Some((Tok::Rel(marker), span)) => (marker, span),
+            _ => unreachable!("only called at a relationship"),
+        };
+        if &marker[2..4] == ".." {
+            self.warn(
+                span,
+                "non-identifying relationships (`..`) are drawn like any other".to_string(),
+            );
+        }
+        let card1 = card_for(&marker[..2], left_marker).expect("checked by the lexer");
+        let card2 = card_for(&marker[4..], right_marker).expect("checked by the lexer");
+
+        let other_span = self.span();
+        let other = match self.next() {
+            Some((Tok::Word(other), _)) | Some((Tok::Str(other), _)) => other,
+            _ => return Err(self.expected("an entity")),
+        };
+        self.found(other.clone(), other_span);
+
+        // Mermaid needs a label, but there's no harm in leaving it out.
+        let mut label = None;
+        if self.peek() == Some(&Tok::Punct(':')) {
+            self.pos += 1;
+            let mut words = vec![];
+            while let Some((tok, _)) = self.tokens.get(self.pos) {
+                match tok {
+                    Tok::Word(w) | Tok::Str(w) => words.push(w.clone()),
+                    Tok::Newline => break,
+                    _ => return Err(self.expected("a label")),
+                }
+                self.pos += 1;
+            }
+            let text = words.join(" ");
+            if !text.is_empty() {
+                label = Some(text);
+            }
+        }
+        match self.peek() {
+            None | Some(Tok::Newline) => {}
+            _ => {
+                self.pos += 1;
+                return Err(self.expected("the end of the line"));
+            }
+        }
+        self.rels.push((id, other, card1, card2, label));
+        Ok(())
+    }
+
+    /// Reads the attributes in an entity's `{ ... }`, from after the `{`.
+    fn attributes(&mut self, index: usize) -> Result<()> {
+        loop {
+            self.skip_newlines();
+            let (ty, span) = match self.next() {
+                Some((Tok::Punct('}'), _)) => return Ok(()),
+                Some((Tok::Word(ty), span)) => (ty, span),
+                _ => return Err(self.expected("an attribute or `}`")),
+            };
+            let name = match self.next() {
+                Some((Tok::Word(name), _)) => name,
+                _ => return Err(self.expected("an attribute name")),
+            };
+
+            let mut attr = Attribute {
+                field: name,
+                pk: false,
+                fk: false,
+                options: Options::default(),
+                doc: None,
+                span: Default::default(),
+            };
+            if ty != ATTRIBUTE_TYPE {
+                attr.options.set(Opt::Label(ty));
+            }
+            self.keys(&mut attr)?;
+            if let Some(Tok::Str(comment)) = self.peek() {
+                attr.doc = Some(comment.clone());
+                self.pos += 1;
+            }
+            attr.span = span;
+            self.entities[index].attribs.push(attr);
+        }
+    }
+
+    /// Reads any `PK`, `FK` or `UK` markers following an attribute.
+    fn keys(&mut self, attr: &mut Attribute) -> Result<()> {
+        let is_key = |tok: Option<&Tok>| matches!(tok, Some(Tok::Word(w)) if ["PK", "FK", "UK"].contains(&w.as_str()));
+        if !is_key(self.peek()) {
+            return Ok(());
+        }
+        loop {
+            match self.next() {
+                Some((Tok::Word(key), span)) => match key.as_str() {
+                    "PK" => attr.pk = true,
+                    "FK" => attr.fk = true,
+                    "UK" => self.warn(span, "unique keys (`UK`) aren't supported".to_string()),
+                    _ => return Err(self.expected("`PK`, `FK` or `UK`")),
+                },
+                _ => return Err(self.expected("`PK`, `FK` or `UK`")),
+            }
+            if self.peek() != Some(&Tok::Punct(',')) {
+                return Ok(());
+            }
+            self.pos += 1;
+        }
+    }
+
+    /// Checks the names of everything found and puts together the model.
+    fn finish(mut self, title: Option<String>) -> (ER, Vec<Warning>) {
+        let found = std::mem::take(&mut self.entities);
+        let found_names: Vec<_> = found
+            .iter()
+            .map(|e| (e.alias.as_deref().unwrap_or(&e.id), e.span))
+            .collect();
+        let entity_names = checked_names(&found_names, &mut self.warnings);
+        let mut names = HashMap::new();
+        let mut entities = vec![];
+        for (entity, name) in found.into_iter().zip(entity_names) {
+            let fields: Vec<_> = entity
+                .attribs
+                .iter()
+                .map(|a| (a.field.as_str(), a.span))
+                .collect();
+            let fields = checked_names(&fields, &mut self.warnings);
+            let attribs = entity
+                .attribs
+                .into_iter()
+                .zip(fields)
+                .map(|(mut attr, field)| {
+                    attr.field = field;
+                    attr.span = Default::default();
+                    attr
+                })
+                .collect();
+            names.insert(entity.id, name.clone());
+            entities.push(Entity {
+                name,
+                attribs,
+                hoptions: Options::default(),
+                eoptions: Options::default(),
+                doc: None,
+                span: Default::default(),
+                name_span: Default::default(),
+            });
+        }
+
+        let rels = std::mem::take(&mut self.rels)
+            .into_iter()
+            .map(|(entity1, entity2, card1, card2, label)| Relation {
+                entity1: names[&entity1].clone(),
+                entity2: names[&entity2].clone(),
+                card1,
+                card2,
+                options: label.into_iter().map(Opt::Label).collect(),
+                doc: None,
+                span: Default::default(),
+                entity1_span: Default::default(),
+                entity2_span: Default::default(),
+            })
+            .collect();
+
+        let title = title.map(|label| {
+            (
+                Directive::Title,
+                std::iter::once(Opt::Label(label)).collect(),
+            )
+        });
+        let er = ER {
+            entities,
+            rels,
+            globals: GlobalOptions::from_directives(title),
+        };
+        (er, self.warnings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::import;
+    use crate::er::Cardinality;
+    use crate::parser::parse;
+    use crate::Error;
+
+    #[test]
+    fn test_render() {
+        let er = parse(
+            r#"title { label: "People" }
+
+[Person]
+*name
+## How tall, in cm.
+height
++`birth place id`
+
+[`Birth Place`]
+*+id
+[`2nd Place`]
+[Birth_Place]
+
+Person *--1 `Birth Place` { label: "born in" }
+`Birth Place` ?--+ `2nd Place`
+Birth_Place 1--* Nowhere
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            er.to_mermaid(),
+            r#"---
+title: People
+---
+erDiagram
+    Person {
+        attr name PK
+        attr height "How tall, in cm."
+        attr birth_place_id FK
+    }
+    Birth_Place {
+        attr id PK, FK
+    }
+    _2nd_Place
+    Birth_Place_2
+    Person }o--|| Birth_Place : "born in"
+    Birth_Place |o--|{ _2nd_Place : ""
+    Birth_Place_2 ||--o{ Nowhere : ""
+"#
+        );
+    }
+
+    #[test]
+    fn test_import() {
+        let (er, warnings) = import(
+            r#"---
+title: "Orders"
+config:
+  theme: dark
+---
+erDiagram
+    %% Who buys what.
+    direction LR
+    CUSTOMER ||--o{ ORDER : places
+    ORDER ||--|{ LINE-ITEM : "contains"
+    CUSTOMER }|..|{ DELIVERY-ADDRESS : uses
+    c["Loyal Customer"]:::gold {
+        int id PK, UK
+    }
+    c |o--|| CUSTOMER : ""
+    CUSTOMER {
+        string name PK "Their full name"
+        string[] emails
+        attr address_id FK
+    }
+    ORDER { int id PK int customer_id FK }
+    style CUSTOMER fill:#f9f
+"#,
+        )
+        .unwrap();
+
+        let expected = parse(
+            r#"title { label: "Orders" }
+
+[CUSTOMER]
+## Their full name
+*name { label: "string" }
+emails { label: "string[]" }
++address_id
+[ORDER]
+*id { label: "int" }
++customer_id { label: "int" }
+[LINE_ITEM]
+[DELIVERY_ADDRESS]
+[`Loyal Customer`]
+*id { label: "int" }
+
+CUSTOMER 1--* ORDER { label: "places" }
+ORDER 1--+ LINE_ITEM { label: "contains" }
+CUSTOMER +--+ DELIVERY_ADDRESS { label: "uses" }
+`Loyal Customer` ?--1 CUSTOMER
+"#,
+        )
+        .unwrap();
+        assert_eq!(er, expected);
+
+        let warnings: Vec<_> = warnings
+            .iter()
+            .map(|w| format!("{}: {}", w.span, w))
+            .collect();
+        assert_eq!(
+            warnings,
+            [
+                "3:1: front matter `config` isn't supported",
+                "8:5: `direction` isn't supported",
+                "11:14: non-identifying relationships (`..`) are drawn like any other",
+                "12:24: classes (`:::`) aren't supported",
+                "13:20: unique keys (`UK`) aren't supported",
+                "22:5: `style` isn't supported",
+                "10:18: `LINE-ITEM` isn't a valid er name, so it was renamed to `LINE_ITEM`",
+                "11:21: `DELIVERY-ADDRESS` isn't a valid er name, so it was renamed to `DELIVERY_ADDRESS`",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_import_round_trips() {
+        let er = parse(
+            "[Person]\n*name\n## cm\nheight\n+place_id\n[Place]\n*id\n\
+             Person *--1 Place { label: \"born in\" }\nPlace ?--+ Place\n",
+        )
+        .unwrap();
+        let (imported, warnings) = import(&er.to_mermaid()).unwrap();
+        assert_eq!(imported, er);
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn test_import_renames_clashes() {
+        let (er, warnings) = import(
+            r#"erDiagram
+    A-B ||--o{ A_B : x
+    A_B {
+        int a-b
+        int a_b
+    }
+"#,
+        )
+        .unwrap();
+        let expected = parse(
+            r#"[A_B_2]
+[A_B]
+a_b_2 { label: "int" }
+a_b { label: "int" }
+A_B_2 1--* A_B { label: "x" }
+"#,
+        )
+        .unwrap();
+        assert_eq!(er, expected);
+        let warnings: Vec<_> = warnings.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            warnings,
+            [
+                "`A-B` isn't a valid er name and `A_B` is taken, so it was renamed to `A_B_2`",
+                "`a-b` isn't a valid er name and `a_b` is taken, so it was renamed to `a_b_2`",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_import_errors() {
+        let err = |input| match import(input) {
+            Err(Error::InvalidDocument {
+                message,
+                span: Some(span),
+            }) => format!("{}: {}", span, message),
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(err("graph TD\n"), "1:1: expected `erDiagram`");
+        assert_eq!(err(""), "1:1: expected `erDiagram`");
+        assert_eq!(
+            err("erDiagram\n    A ||--x B\n"),
+            "2:7: unknown relationship `||--x`"
+        );
+        assert_eq!(
+            err("erDiagram\n    A {\n        string\n    }\n"),
+            "3:15: expected an attribute name"
+        );
+        assert_eq!(
+            err("erDiagram\n    A {\n"),
+            "3:1: expected an attribute or `}`"
+        );
+        assert_eq!(
+            err("erDiagram\n    A ||--o{ B : \"oops\n"),
+            "2:18: unclosed string"
+        );
+        assert_eq!(
+            import("erDiagram\n    A }|--o| B\n").unwrap().0.rels[0].card1,
+            Cardinality::OnePlus
+        );
+    }
+}
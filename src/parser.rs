@@ -1,5 +1,16 @@
-use crate::Result;
-use pest::{iterators::Pairs, Parser};
+use crate::er::{
+    card_by_name, directive_by_name, option_by_name, Attribute, Cardinality, Directive, Entity,
+    GlobalOptions, Options, Relation, ER,
+};
+use crate::errors::option_blocks;
+use crate::span::{LineIndex, Span};
+use crate::{Diagnostic, Error, Result};
+use pest::{
+    error::InputLocation,
+    iterators::{Pair, Pairs},
+    Parser,
+};
+use std::cell::RefCell;
 
 #[derive(Parser)]
 #[grammar = "er.pest"]
@@ -8,10 +19,377 @@ struct ErParser;
 /// Parse an er file to get some pairs.
 // TODO: Likely this will not be something we offer in the public API, but it's
 //   useful to keep the `dump` example compiling for now.
-pub fn parse_pairs(input: &str) -> Result<Pairs<Rule>> {
+pub fn parse_pairs(input: &str) -> Result<Pairs<'_, Rule>> {
     Ok(ErParser::parse(Rule::document, input)?)
 }
 
+/// Parse an er file into an [ER](../er/struct.ER.html).
+///
+/// Entities, attributes and relationships are kept in the order they appear in
+/// the document. Formatting options are kept exactly as written; neither the
+/// global options nor the defaults are merged in at this stage (see the
+/// `effective_*_options` methods on `ER` for that).
+pub fn parse(input: &str) -> Result<ER> {
+    let document = parse_pairs(input)?.next().expect("document");
+    let lines = LineIndex::new(input);
+    let walker = Walker {
+        lines: &lines,
+        offset: 0,
+        errors: None,
+    };
+
+    let mut er = ER {
+        entities: vec![],
+        rels: vec![],
+        globals: GlobalOptions::default(),
+    };
+    walker.walk_document(document, &mut er)?;
+    Ok(er)
+}
+
+/// Parse an er file, carrying on past any problems found to report them all.
+///
+/// When a syntax error is found, we skip ahead to the next line starting with
+/// an entity header or a relationship and pick up from there. Anything which
+/// parsed cleanly before the error is kept. Options with bad values are left
+/// off of whatever they were attached to.
+///
+/// The `ER` returned holds everything that could be salvaged, and the
+/// diagnostics are given in the order they appear in the document. When the
+/// list of diagnostics is empty, the `ER` is the same as what
+/// [parse](fn.parse.html) would have returned.
+pub fn parse_recovering(input: &str) -> (ER, Vec<Diagnostic>) {
+    let lines = LineIndex::new(input);
+    let errors = RefCell::new(vec![]);
+
+    let mut er = ER {
+        entities: vec![],
+        rels: vec![],
+        globals: GlobalOptions::default(),
+    };
+
+    let mut starts = sync_points(input);
+    if starts.first() != Some(&0) {
+        starts.insert(0, 0);
+    }
+    let ends = starts.iter().skip(1).cloned().chain(Some(input.len()));
+
+    for (start, seg_end) in starts.clone().into_iter().zip(ends) {
+        let walker = Walker {
+            lines: &lines,
+            offset: start,
+            errors: Some(&errors),
+        };
+
+        // Parse the whole chunk if we can, otherwise keep trimming it back to
+        // the line the error was found on to save what we can.
+        let mut end = seg_end;
+        loop {
+            match ErParser::parse(Rule::document, &input[start..end]) {
+                Ok(mut pairs) => {
+                    let document = pairs.next().expect("document");
+                    walker
+                        .walk_document(document, &mut er)
+                        .expect("option errors are collected when recovering");
+                    break;
+                }
+                Err(err) => {
+                    let pos = start + error_pos(&err);
+                    if end == seg_end {
+                        let err = Error::Parser {
+                            source: Box::new(err),
+                            span: lines.span(pos, pos),
+                        };
+                        errors.borrow_mut().push(err);
+                    }
+                    let line_start = input[..pos].rfind('\n').map_or(0, |i| i + 1);
+                    if line_start <= start || line_start >= end {
+                        break;
+                    }
+                    end = line_start;
+                }
+            }
+        }
+    }
+
+    let mut diagnostics: Vec<_> = errors
+        .into_inner()
+        .iter()
+        .map(|err| err.diagnostic(input))
+        .collect();
+    diagnostics.sort_by_key(|d| d.span.map(|s| s.start));
+    (er, diagnostics)
+}
+
+/// The byte offsets of the lines where parsing can pick up again after an
+/// error.
+fn sync_points(input: &str) -> Vec<usize> {
+    let blocks = option_blocks(input);
+    let mut points = vec![];
+    let mut offset = 0;
+    for line in input.split_inclusive('\n') {
+        let start = offset;
+        offset += line.len();
+        let in_block = blocks
+            .iter()
+            .any(|(open, close)| *open < start && close.is_none_or(|c| c >= start));
+        let trimmed = line.trim_start();
+        if !in_block && ErParser::parse(Rule::sync, trimmed).is_ok() {
+            points.push(start);
+        }
+    }
+    points
+}
+
+/// Where in the input pest gave up.
+fn error_pos(err: &pest::error::Error<Rule>) -> usize {
+    match err.location {
+        InputLocation::Pos(pos) => pos,
+        InputLocation::Span((start, _)) => start,
+    }
+}
+
+/// Walks the pairs produced by pest, converting them to the types in the `er`
+/// module.
+struct Walker<'a, 'i> {
+    lines: &'a LineIndex<'i>,
+    /// Where the text given to pest starts in the whole of the input.
+    offset: usize,
+    /// When set, option errors are collected here rather than returned, and
+    /// the options with errors are skipped.
+    errors: Option<&'a RefCell<Vec<Error>>>,
+}
+
+impl<'a, 'i> Walker<'a, 'i> {
+    /// Adds everything in a `document` pair to `er`.
+    fn walk_document(&self, document: Pair<'i, Rule>, er: &mut ER) -> Result<()> {
+        for pair in document.into_inner() {
+            match pair.as_rule() {
+                Rule::head => {
+                    let directives = pair
+                        .into_inner()
+                        .map(|pair| self.to_directive(pair))
+                        .collect::<Result<Vec<_>>>()?;
+                    // Only the first chunk of a recovering parse has a head
+                    // worth keeping, so don't let the others wipe it out.
+                    if directives.is_empty() {
+                        continue;
+                    }
+                    let spans = directives.iter().map(|(d, _, span)| (*d, *span)).collect();
+                    er.globals = GlobalOptions::from_directives(
+                        directives.into_iter().map(|(d, o, _)| (d, o)),
+                    );
+                    er.globals.spans = spans;
+                }
+                Rule::body => {
+                    for item in pair.into_inner() {
+                        match item.as_rule() {
+                            Rule::entity => er.entities.push(self.to_entity(item)?),
+                            Rule::rel => er.rels.push(self.to_relation(item)?),
+                            _ => unreachable!(),
+                        }
+                    }
+                }
+                Rule::EOI => (),
+                _ => unreachable!(),
+            }
+        }
+        Ok(())
+    }
+
+    /// The span covered by a pair, not counting any trailing whitespace.
+    fn span(&self, pair: &Pair<'i, Rule>) -> Span {
+        let span = pair.as_span();
+        self.lines
+            .trimmed_span(self.offset + span.start(), self.offset + span.end())
+    }
+
+    fn to_directive(&self, pair: Pair<'i, Rule>) -> Result<(Directive, Options, Span)> {
+        let span = self.span(&pair);
+        let mut inner = pair.into_inner();
+        let dir_type = inner.next().expect("dir_type");
+        // The grammar only admits the directives `directive_by_name` knows
+        // about.
+        let directive = directive_by_name(dir_type.as_str()).expect("valid directive");
+        Ok((directive, self.to_options(inner)?, span))
+    }
+
+    /// Collects the `##` doc comment on the lines just before `pair`, if
+    /// there is one.
+    ///
+    /// The `##` and a single space after it are dropped from each line, and
+    /// the lines are joined with newlines.
+    fn doc(&self, pair: &Pair<'i, Rule>) -> Option<String> {
+        let start = self.offset + pair.as_span().start();
+        let mut lines: Vec<_> = self
+            .lines
+            .lines_before(start)
+            .map_while(|line| line.trim().strip_prefix("##"))
+            .map(|line| line.strip_prefix(' ').unwrap_or(line))
+            .collect();
+        if lines.is_empty() {
+            return None;
+        }
+        lines.reverse();
+        Some(lines.join("\n"))
+    }
+
+    /// Unwraps the `ident` pair to the bare identifier, sans quotes.
+    fn to_ident(&self, pair: Pair<'i, Rule>) -> (String, Span) {
+        debug_assert_eq!(pair.as_rule(), Rule::ident);
+        let span = self.span(&pair);
+        let ident = pair
+            .into_inner()
+            .next()
+            .expect("ident_no_space or ident_quoted")
+            .as_str()
+            .to_string();
+        (ident, span)
+    }
+
+    /// Collects the options found in a run of `opt_list` pairs into an
+    /// `Options`.
+    ///
+    /// The grammar defines `opt_list` recursively (each list holds one
+    /// `option` followed by the rest of the list), so this flattens the whole
+    /// lot.
+    fn to_options(&self, pairs: impl Iterator<Item = Pair<'i, Rule>>) -> Result<Options> {
+        let mut options = Options::default();
+        for opt_list in pairs {
+            debug_assert_eq!(opt_list.as_rule(), Rule::opt_list);
+            for pair in opt_list.into_inner().flatten() {
+                if pair.as_rule() != Rule::option {
+                    continue;
+                }
+                let span = self.span(&pair);
+                let mut inner = pair.into_inner();
+                let name = inner.next().expect("opt_name").as_str();
+                let quoted = inner.next().expect("opt_value").as_str();
+                let value = &quoted[1..quoted.len() - 1];
+                match option_by_name(name, value) {
+                    Ok(opt) => {
+                        options.set_at(opt, span);
+                    }
+                    Err(err) => match self.errors {
+                        Some(errors) => errors.borrow_mut().push(err.at(span)),
+                        None => return Err(err.at(span)),
+                    },
+                }
+            }
+        }
+        Ok(options)
+    }
+
+    fn to_entity(&self, pair: Pair<'i, Rule>) -> Result<Entity> {
+        let mut inner = pair.into_inner();
+        let header = inner.next().expect("entity_name");
+        let span = self.span(&header);
+        let doc = self.doc(&header);
+        let mut header = header.into_inner();
+        let (name, name_span) = self.to_ident(header.next().expect("ident"));
+        let hoptions = self.to_options(header)?;
+
+        let attribs = match inner.next() {
+            Some(attribs) => attribs
+                .into_inner()
+                .map(|pair| self.to_attribute(pair))
+                .collect::<Result<_>>()?,
+            None => vec![],
+        };
+
+        // As with the haskell erd, options given on the entity header apply to
+        // both the header and the body of the entity.
+        Ok(Entity {
+            name,
+            attribs,
+            eoptions: hoptions.clone(),
+            hoptions,
+            doc,
+            span,
+            name_span,
+        })
+    }
+
+    fn to_attribute(&self, pair: Pair<'i, Rule>) -> Result<Attribute> {
+        let span = self.span(&pair);
+        let doc = self.doc(&pair);
+        let mut pk = false;
+        let mut fk = false;
+        let mut field = None;
+        let mut opt_lists = vec![];
+
+        for pair in pair.into_inner() {
+            match pair.as_rule() {
+                Rule::keys => {
+                    for key in pair.into_inner() {
+                        match key.as_rule() {
+                            Rule::ispk => pk = true,
+                            Rule::isfk => fk = true,
+                            _ => unreachable!(),
+                        }
+                    }
+                }
+                Rule::ident => field = Some(self.to_ident(pair).0),
+                Rule::opt_list => opt_lists.push(pair),
+                _ => unreachable!(),
+            }
+        }
+
+        Ok(Attribute {
+            field: field.expect("ident"),
+            pk,
+            fk,
+            options: self.to_options(opt_lists.into_iter())?,
+            doc,
+            span,
+        })
+    }
+
+    fn to_relation(&self, pair: Pair<'i, Rule>) -> Result<Relation> {
+        let span = self.span(&pair);
+        let doc = self.doc(&pair);
+        let mut inner = pair.into_inner();
+        let (entity1, entity1_span) = self.to_ident(
+            inner
+                .next()
+                .expect("entity1")
+                .into_inner()
+                .next()
+                .expect("ident"),
+        );
+        let card1 = to_cardinality(inner.next().expect("card1"));
+        let card2 = to_cardinality(inner.next().expect("card2"));
+        let (entity2, entity2_span) = self.to_ident(
+            inner
+                .next()
+                .expect("entity2")
+                .into_inner()
+                .next()
+                .expect("ident"),
+        );
+        let options = self.to_options(inner)?;
+
+        Ok(Relation {
+            entity1,
+            entity2,
+            card1,
+            card2,
+            options,
+            doc,
+            span,
+            entity1_span,
+            entity2_span,
+        })
+    }
+}
+
+/// Converts a `card1` or `card2` pair to the `Cardinality` it names.
+fn to_cardinality(pair: Pair<'_, Rule>) -> Cardinality {
+    let c = pair.as_str().chars().next().expect("card_type");
+    // The grammar only admits the four characters `card_by_name` knows about.
+    card_by_name(c).expect("valid cardinality")
+}
+
 #[cfg(test)]
 mod tests {
     //! The tests here aim to "prove the spec" described in the readme for the
@@ -31,7 +409,14 @@ mod tests {
     //! - options should start *on the same line* as the thing they are options
     //!   for, but can contain newlines.
 
-    use super::parse_pairs;
+    use super::{parse, parse_pairs, parse_recovering};
+    use crate::er::{Cardinality, Directive};
+    use crate::{Code, Error};
+
+    /// The diagnostic code for the error produced when parsing `input`.
+    fn code(input: &str) -> Code {
+        parse(input).unwrap_err().diagnostic(input).code
+    }
 
     /// Directives must appear before all other items in the er file.
     #[test]
@@ -62,7 +447,7 @@ mod tests {
         [Baz]
             qux
         "#;
-        assert!(parse_pairs(input).is_err()); // FIXME: inspect the Err
+        assert_eq!(code(input), Code::UnknownDirective);
     }
 
     /// When what would otherwise be a valid directive appears after an entity
@@ -87,7 +472,7 @@ mod tests {
         Foo 1--1 Bar
         title { label: "oops" }
         "#;
-        assert!(parse_pairs(input).is_err()); // FIXME: inspect the Err
+        assert_eq!(code(input), Code::DirectiveAfterBody);
     }
 
     /// I don't know why you'd have an er with relationships defined and no
@@ -139,7 +524,7 @@ mod tests {
         { label: "a person's name" }
         age
         "#;
-        assert!(parse_pairs(input).is_err());
+        assert_eq!(code(input), Code::OptionsOnOwnLine);
     }
 
     #[test]
@@ -149,7 +534,7 @@ mod tests {
         name { label: "a person's name" 
         } age
         "#;
-        assert!(parse_pairs(input).is_err());
+        assert_eq!(code(input), Code::TextAfterOptions);
     }
 
     #[test]
@@ -160,7 +545,7 @@ mod tests {
         } [Group]
         id
         "#;
-        assert!(parse_pairs(input).is_err());
+        assert_eq!(code(input), Code::TextAfterOptions);
     }
 
     #[test]
@@ -173,7 +558,7 @@ mod tests {
             bgcolor: "#ff33ff",
         } Person *--* Group
         "##;
-        assert!(parse_pairs(input).is_err());
+        assert_eq!(code(input), Code::TextAfterOptions);
     }
 
     #[test]
@@ -193,10 +578,10 @@ mod tests {
         parse_pairs(double_quote).unwrap();
 
         let single_quote = r#"title { label: '123' }"#; // Bad
-        assert!(parse_pairs(single_quote).is_err());
+        assert_eq!(code(single_quote), Code::UnquotedOptionValue);
 
         let unquoted = r#"title { label: 123 }"#; // Bad
-        assert!(parse_pairs(unquoted).is_err());
+        assert_eq!(code(unquoted), Code::UnquotedOptionValue);
     }
 
     /// This is really just to check that the `#` in the string value doesn't
@@ -205,12 +590,17 @@ mod tests {
     fn test_option_val_can_contain_pound() {
         let input = r##"header { bgcolor: "#663399" }"##; // Good
 
-        // FIXME: check the parsed value to confirm the bgcolor value is expected.
-        parse_pairs(input).unwrap();
+        let er = parse(input).unwrap();
+        assert_eq!(
+            er.globals
+                .get(Directive::Header)
+                .bg_color()
+                .map(String::as_str),
+            Some("#663399")
+        );
     }
 
     #[test]
-    #[ignore]
     fn test_option_can_belong_to_directive() {
         let input = r#"
         title { label: "Main title" }
@@ -223,32 +613,37 @@ mod tests {
         [Group]
         id
         "#;
-        // FIXME: check that label is actually associated with directive
-        parse_pairs(input).unwrap();
-        unimplemented!();
+        let er = parse(input).unwrap();
+        assert_eq!(
+            er.globals.get(Directive::Title).label().map(String::as_str),
+            Some("Main title")
+        );
     }
 
     #[test]
-    #[ignore]
     fn test_option_can_belong_to_entity() {
         let input = r#"
         title { label: "Main title" }
         
         Person 1--* Group { label: "A person belongs to zero or more groups" }
         
-        [Person]
+        [Person] { label: "The people" }
         name {label: "A person's name" }
         
         [Group]
         id
         "#;
-        // FIXME: check that label is actually associated with entity
-        parse_pairs(input).unwrap();
-        unimplemented!();
+        let er = parse(input).unwrap();
+        let person = &er.entities[0];
+        assert_eq!(person.name, "Person");
+        assert_eq!(
+            person.hoptions.label().map(String::as_str),
+            Some("The people")
+        );
+        assert!(er.entities[1].hoptions.is_empty());
     }
 
     #[test]
-    #[ignore]
     fn test_option_can_belong_to_attr() {
         let input = r#"
         title { label: "Main title" }
@@ -261,13 +656,16 @@ mod tests {
         [Group]
         id
         "#;
-        // FIXME: check that label is actually associated with attr
-        parse_pairs(input).unwrap();
-        unimplemented!();
+        let er = parse(input).unwrap();
+        let name = &er.entities[0].attribs[0];
+        assert_eq!(name.field, "name");
+        assert_eq!(
+            name.options.label().map(String::as_str),
+            Some("A person's name")
+        );
     }
 
     #[test]
-    #[ignore]
     fn test_option_can_belong_to_rel() {
         let input = r#"
         title { label: "Main title" }
@@ -278,9 +676,11 @@ mod tests {
         [Group]
         id
         "#;
-        // FIXME: check that label is actually associated with rel
-        parse_pairs(input).unwrap();
-        unimplemented!();
+        let er = parse(input).unwrap();
+        assert_eq!(
+            er.rels[0].options.label().map(String::as_str),
+            Some("A person belongs to zero or more groups")
+        );
     }
 
     #[test]
@@ -379,4 +779,253 @@ mod tests {
         "##;
         parse_pairs(input).unwrap();
     }
+
+    #[test]
+    fn test_parse_basics_sample() {
+        let er = parse(include_str!("../samples/basics.erd")).unwrap();
+        let names: Vec<_> = er.entities.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, vec!["Person", "Birth Place"]);
+
+        let person = &er.entities[0];
+        let fields: Vec<_> = person.attribs.iter().map(|a| a.field.as_str()).collect();
+        assert_eq!(
+            fields,
+            vec!["name", "height", "weight", "birth date", "birth_place_id"]
+        );
+        assert!(person.attribs[0].pk && !person.attribs[0].fk);
+        assert!(!person.attribs[4].pk && person.attribs[4].fk);
+
+        let birth_place = &er.entities[1];
+        let fields: Vec<_> = birth_place
+            .attribs
+            .iter()
+            .map(|a| a.field.as_str())
+            .collect();
+        assert_eq!(
+            fields,
+            vec!["id", "birth city", "birth state", "birth country"]
+        );
+
+        assert_eq!(er.rels.len(), 1);
+        let rel = &er.rels[0];
+        assert_eq!(rel.entity1, "Person");
+        assert_eq!(rel.entity2, "Birth Place");
+        assert_eq!(rel.card1, Cardinality::ZeroPlus);
+        assert_eq!(rel.card2, Cardinality::One);
+    }
+
+    #[test]
+    fn test_parse_keys_either_order() {
+        let input = r#"
+        [Foo]
+            *+a
+            +*b
+        "#;
+        let er = parse(input).unwrap();
+        for attr in &er.entities[0].attribs {
+            assert!(attr.pk && attr.fk, "{} should be pk and fk", attr.field);
+        }
+    }
+
+    #[test]
+    fn test_parse_option_values_are_typed() {
+        let input = r##"
+        [Foo] { size: "20.5", border: "2", bgcolor: "#663399" }
+            a
+        "##;
+        let er = parse(input).unwrap();
+        let opts = &er.entities[0].hoptions;
+        assert_eq!(opts.font_size(), Some(&20.5));
+        assert_eq!(opts.border(), Some(&2));
+        assert_eq!(opts.bg_color().map(String::as_str), Some("#663399"));
+    }
+
+    #[test]
+    fn test_parse_bad_option_value_is_err() {
+        let input = r#"
+        [Foo]
+            a { size: "abc" }
+        "#;
+        assert!(matches!(parse(input), Err(Error::InvalidFloat { .. })));
+
+        let input = r#"
+        [Foo]
+            a { border: "-1" }
+        "#;
+        assert!(matches!(parse(input), Err(Error::InvalidInt { .. })));
+
+        for size in ["NaN", "inf", "-inf", "-1"] {
+            let input = format!("[Foo]\na {{ size: \"{}\" }}\n", size);
+            assert!(
+                matches!(parse(&input), Err(Error::InvalidFloat { .. })),
+                "{}",
+                size
+            );
+        }
+    }
+
+    #[test]
+    fn test_spans() {
+        let input = r#"title { label: "People" }
+[Person] { size: "20" }
+  *name
+  `birth date` { label: "date" }
+Person *--1 `Birth Place`
+"#;
+        let er = parse(input).unwrap();
+
+        let (directive, span) = er.globals.directive_spans()[0];
+        assert_eq!(directive, Directive::Title);
+        assert_eq!(span.as_str(input), r#"title { label: "People" }"#);
+        let span = er.globals.get(Directive::Title).span("label").unwrap();
+        assert_eq!(span.as_str(input), r#"label: "People""#);
+        assert_eq!((span.line, span.col), (1, 9));
+
+        let person = &er.entities[0];
+        assert_eq!(person.span().as_str(input), r#"[Person] { size: "20" }"#);
+        assert_eq!(person.name_span().as_str(input), "Person");
+        assert_eq!((person.span().line, person.span().col), (2, 1));
+        let span = person.hoptions.span("size").unwrap();
+        assert_eq!(span.as_str(input), r#"size: "20""#);
+
+        let name = &person.attribs[0];
+        assert_eq!(name.span().as_str(input), "*name");
+        assert_eq!((name.span().line, name.span().col), (3, 3));
+        let birth_date = &person.attribs[1];
+        assert_eq!(
+            birth_date.span().as_str(input),
+            r#"`birth date` { label: "date" }"#
+        );
+
+        let rel = &er.rels[0];
+        assert_eq!(rel.span().as_str(input), "Person *--1 `Birth Place`");
+        let (e1, e2) = rel.endpoint_spans();
+        assert_eq!(e1.as_str(input), "Person");
+        assert_eq!(e2.as_str(input), "`Birth Place`");
+        assert_eq!((e2.line, e2.col), (5, 13));
+    }
+
+    #[test]
+    fn test_error_spans() {
+        let input = "[Foo]\n    a { size: \"abc\" }\n";
+        let err = parse(input).unwrap_err();
+        let span = err.span().unwrap();
+        assert_eq!(span.as_str(input), r#"size: "abc""#);
+        assert_eq!((span.line, span.col), (2, 9));
+        assert!(err.to_string().starts_with("2:9: Invalid value for `size`"));
+
+        let input = "[Foo]\n  a\n  { label: \"b\" }\n";
+        let err = parse(input).unwrap_err();
+        assert!(matches!(err, Error::Parser { .. }));
+        let span = err.span().unwrap();
+        assert_eq!((span.line, span.col), (3, 3));
+    }
+
+    #[test]
+    fn test_recovering_valid_input_matches_parse() {
+        let input = include_str!("../samples/basics.erd");
+        let (er, diagnostics) = parse_recovering(input);
+        assert!(diagnostics.is_empty());
+        assert_eq!(er, parse(input).unwrap());
+    }
+
+    #[test]
+    fn test_recovering_reports_every_syntax_error() {
+        let input = r#"title { label: "people" }
+
+[Person
+  name
+[Location]
+  *id
+  city { color: blue }
+[Group]
+  *id
+Person *--1 Location
+Person *-x Group
+Location 1--* Group
+"#;
+        let (er, diagnostics) = parse_recovering(input);
+
+        let lines: Vec<_> = diagnostics.iter().map(|d| d.span.unwrap().line).collect();
+        assert_eq!(lines, vec![3, 7, 11]);
+        assert_eq!(diagnostics[1].code, Code::UnquotedOptionValue);
+
+        assert_eq!(er.globals.get(Directive::Title).label().unwrap(), "people");
+        let names: Vec<_> = er.entities.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, vec!["Location", "Group"]);
+        // The attributes before the bad one are kept.
+        assert_eq!(er.entities[0].attribs.len(), 1);
+        let rels: Vec<_> = er
+            .rels
+            .iter()
+            .map(|r| (r.entity1.as_str(), r.entity2.as_str()))
+            .collect();
+        assert_eq!(rels, vec![("Person", "Location"), ("Location", "Group")]);
+    }
+
+    #[test]
+    fn test_recovering_collects_option_errors() {
+        let input = "[Foo]\n  a { size: \"big\", color: \"red\" }\n  b { border: \"x\" }\n";
+        let (er, diagnostics) = parse_recovering(input);
+
+        let codes: Vec<_> = diagnostics.iter().map(|d| d.code).collect();
+        assert_eq!(
+            codes,
+            vec![Code::InvalidOptionValue, Code::InvalidOptionValue]
+        );
+        assert_eq!(diagnostics[0].span.unwrap().line, 2);
+        assert_eq!(diagnostics[1].span.unwrap().line, 3);
+
+        let attribs = &er.entities[0].attribs;
+        assert_eq!(attribs.len(), 2);
+        assert_eq!(attribs[0].options.color().unwrap(), "red");
+        assert_eq!(attribs[0].options.font_size(), None);
+    }
+
+    #[test]
+    fn test_recovering_keeps_directives_before_an_error() {
+        let input = r#"title { label: "kept" }
+qux { label: "nope" }
+[Foo]
+  a
+Foo 1--1 Bar
+header { color: "red" }
+"#;
+        let (er, diagnostics) = parse_recovering(input);
+
+        let codes: Vec<_> = diagnostics.iter().map(|d| d.code).collect();
+        assert_eq!(
+            codes,
+            vec![Code::UnknownDirective, Code::DirectiveAfterBody]
+        );
+        assert_eq!(er.globals.get(Directive::Title).label().unwrap(), "kept");
+        assert_eq!(er.entities.len(), 1);
+        assert_eq!(er.rels.len(), 1);
+    }
+
+    #[test]
+    fn test_doc_comments() {
+        let input = r#"
+## A person.
+##
+##   Indented.
+[Person]
+## Their name.
+*name # not a doc comment
+# Nor is this.
+height
+## Not attached, there's a blank line.
+
+weight
+## Where they were born.
+Person *--1 Place
+"#;
+        let er = parse(input).unwrap();
+        let person = &er.entities[0];
+        assert_eq!(person.doc(), Some("A person.\n\n  Indented."));
+        assert_eq!(person.attribs[0].doc(), Some("Their name."));
+        assert_eq!(person.attribs[1].doc(), None);
+        assert_eq!(person.attribs[2].doc(), None);
+        assert_eq!(er.rels[0].doc(), Some("Where they were born."));
+    }
 }
@@ -0,0 +1,229 @@
+//! Renders an [ER](../er/struct.ER.html) as a PlantUML entity diagram.
+//!
+//! Each entity becomes an `entity` block listing its primary key attributes
+//! (marked with `*`) above a `--` separator and the rest below it. Foreign
+//! keys get a `<<FK>>` stereotype, and relationships are drawn with the
+//! crow's foot connectors for their cardinalities.
+//!
+//! Background colors given for an entity (or its header) become the entity's
+//! color when PlantUML understands them: `#rgb` and `#rrggbb` hex colors, and
+//! the color names it shares with CSS. Graphviz's own names (`gray50`,
+//! `palegreen3`, `/x11/red`) are dropped, as are all other formatting
+//! options, apart from the title and relationship labels.
+//!
+//! Names which PlantUML won't take as identifiers (`Birth Place`) are written
+//! in quotes with an identifier made up the same way as for
+//! [Mermaid](../mermaid/index.html) as an alias.
+
+use crate::er::{Attribute, Entity, ER};
+use crate::mermaid::{comment, entity_ids, left_marker, right_marker, sanitize};
+use crate::Result;
+use std::io::Write;
+
+impl ER {
+    /// Renders the diagram as a PlantUML entity diagram.
+    ///
+    /// See [render](../plantuml/fn.render.html).
+    pub fn to_plantuml(&self) -> String {
+        let mut buf = vec![];
+        render(self, &mut buf).expect("writing to a Vec can't fail");
+        String::from_utf8(buf).expect("plantuml output is always utf-8")
+    }
+}
+
+/// Writes the PlantUML representation of `er` to `out`.
+pub fn render<W: Write>(er: &ER, mut out: W) -> Result<()> {
+    writeln!(out, "@startuml")?;
+    if let Some(title) = er.effective_title_options().label() {
+        writeln!(out, "title {}", comment(title))?;
+    }
+
+    let ids = entity_ids(er);
+    for entity in &er.entities {
+        let id = &ids[entity.name.as_str()];
+        let mut header = if *id == entity.name {
+            format!("entity {}", id)
+        } else {
+            format!("entity \"{}\" as {}", entity.name, id)
+        };
+        if let Some(color) = bg_color(er, entity) {
+            header.push_str(&format!(" #{}", color.trim_start_matches('#')));
+        }
+
+        let (keys, rest): (Vec<_>, Vec<_>) = entity.attribs.iter().partition(|a| a.pk);
+        if keys.is_empty() && rest.is_empty() {
+            writeln!(out, "{}", header)?;
+            continue;
+        }
+        writeln!(out, "{} {{", header)?;
+        for attr in &keys {
+            writeln!(out, "    {}", attribute(attr))?;
+        }
+        if !keys.is_empty() && !rest.is_empty() {
+            writeln!(out, "    --")?;
+        }
+        for attr in &rest {
+            writeln!(out, "    {}", attribute(attr))?;
+        }
+        writeln!(out, "}}")?;
+    }
+
+    for rel in &er.rels {
+        let id = |name: &str| match ids.get(name) {
+            Some(id) => id.clone(),
+            None => sanitize(name),
+        };
+        let mut line = format!(
+            "{} {}--{} {}",
+            id(&rel.entity1),
+            left_marker(rel.card1),
+            right_marker(rel.card2),
+            id(&rel.entity2)
+        );
+        if let Some(label) = er.effective_relation_options(rel).label() {
+            line.push_str(&format!(" : {}", comment(label)));
+        }
+        writeln!(out, "{}", line)?;
+    }
+
+    writeln!(out, "@enduml")?;
+    Ok(())
+}
+
+/// The color for an entity: its body's background color, falling back to its
+/// header's, whichever PlantUML understands first.
+fn bg_color(er: &ER, entity: &Entity) -> Option<String> {
+    let body = er.effective_entity_options(entity).bg_color().cloned();
+    let header = er.effective_header_options(entity).bg_color().cloned();
+    body.into_iter().chain(header).find(|color| is_color(color))
+}
+
+/// Whether PlantUML takes `color` as a color.
+fn is_color(color: &str) -> bool {
+    match color.strip_prefix('#') {
+        Some(hex) => [3, 6].contains(&hex.len()) && hex.chars().all(|c| c.is_ascii_hexdigit()),
+        None => COLOR_NAMES
+            .split_whitespace()
+            .any(|name| name.eq_ignore_ascii_case(color)),
+    }
+}
+
+/// The color names PlantUML understands, which are the ones from CSS.
+const COLOR_NAMES: &str =
+    "aliceblue antiquewhite aqua aquamarine azure beige bisque black blanchedalmond blue \
+     blueviolet brown burlywood cadetblue chartreuse chocolate coral cornflowerblue \
+     cornsilk crimson cyan darkblue darkcyan darkgoldenrod darkgray darkgreen darkgrey \
+     darkkhaki darkmagenta darkolivegreen darkorange darkorchid darkred darksalmon \
+     darkseagreen darkslateblue darkslategray darkslategrey darkturquoise darkviolet \
+     deeppink deepskyblue dimgray dimgrey dodgerblue firebrick floralwhite forestgreen \
+     fuchsia gainsboro ghostwhite gold goldenrod gray green greenyellow grey honeydew \
+     hotpink indianred indigo ivory khaki lavender lavenderblush lawngreen lemonchiffon \
+     lightblue lightcoral lightcyan lightgoldenrodyellow lightgray lightgreen lightgrey \
+     lightpink lightsalmon lightseagreen lightskyblue lightslategray lightslategrey \
+     lightsteelblue lightyellow lime limegreen linen magenta maroon mediumaquamarine \
+     mediumblue mediumorchid mediumpurple mediumseagreen mediumslateblue \
+     mediumspringgreen mediumturquoise mediumvioletred midnightblue mintcream mistyrose \
+     moccasin navajowhite navy oldlace olive olivedrab orange orangered orchid \
+     palegoldenrod palegreen paleturquoise palevioletred papayawhip peachpuff peru pink \
+     plum powderblue purple red rosybrown royalblue saddlebrown salmon sandybrown \
+     seagreen seashell sienna silver skyblue slateblue slategray slategrey snow \
+     springgreen steelblue tan teal thistle tomato turquoise violet wheat white \
+     whitesmoke yellow yellowgreen";
+
+/// An attribute's line in its entity block.
+fn attribute(attr: &Attribute) -> String {
+    let mut line = attr.field.clone();
+    if attr.pk {
+        line = format!("* {}", line);
+    }
+    if attr.fk {
+        line.push_str(" <<FK>>");
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::er::Opt;
+    use crate::parser::parse;
+
+    #[test]
+    fn test_render() {
+        let er = parse(
+            r##"title { label: "People" }
+header { bgcolor: "#d0e0d0" }
+
+[Person] { bgcolor: "#ececfc" }
+height
+*name
++`birth place id`
+
+[`Birth Place`]
+*+id
+[Empty]
+
+Person *--1 `Birth Place` { label: "born in" }
+`Birth Place` ?--+ Empty
+"##,
+        )
+        .unwrap();
+        assert_eq!(
+            er.to_plantuml(),
+            r#"@startuml
+title People
+entity Person #ececfc {
+    * name
+    --
+    height
+    birth place id <<FK>>
+}
+entity "Birth Place" as Birth_Place #d0e0d0 {
+    * id <<FK>>
+}
+entity Empty #d0e0d0
+Person }o--|| Birth_Place : born in
+Birth_Place |o--|{ Empty
+@enduml
+"#
+        );
+    }
+
+    #[test]
+    fn test_render_colors_and_quoting() {
+        let mut er = parse(
+            r##"[A] { bgcolor: "gray50" }
+*id
+[B] { bgcolor: "PaleGreen" }
+[`2nd Place`] { bgcolor: "/x11/red" }
+[C] { bgcolor: "#abc" }
++a_id
++b_id
+[D] { bgcolor: "light blue" }
+C *--1 A
+C *--1 B
+"##,
+        )
+        .unwrap();
+        er.rels[0]
+            .options
+            .set(Opt::Label("the \"only\" one".to_string()));
+        assert_eq!(
+            er.to_plantuml(),
+            r#"@startuml
+entity A {
+    * id
+}
+entity B #PaleGreen
+entity "2nd Place" as _2nd_Place
+entity C #abc {
+    a_id <<FK>>
+    b_id <<FK>>
+}
+entity D
+C }o--|| A : the 'only' one
+C }o--|| B
+@enduml
+"#
+        );
+    }
+}
@@ -0,0 +1,253 @@
+//! Renaming entities.
+//!
+//! An entity's name turns up in its header and at the end of every
+//! relationship it's part of, each of which may have been quoted any of three
+//! ways. Renaming works on a [Cst](../cst/struct.Cst.html) so that everything
+//! else in the document (comments, layout, other quoting) is left as it was.
+
+use crate::cst::{Cst, Node, NodeKind};
+use crate::er::ER;
+use crate::fmt::needs_quotes;
+use crate::span::Span;
+use crate::{Error, Result};
+
+/// Renames the entity `old` to `new` in the er document `input`, giving the
+/// updated document.
+pub fn rename_entity(input: &str, old: &str, new: &str) -> Result<String> {
+    let mut cst = Cst::parse(input)?;
+    cst.rename_entity(old, new)?;
+    Ok(cst.to_string())
+}
+
+impl Cst {
+    /// Renames the entity `old` to `new`, in its header and at either end of
+    /// every relationship.
+    ///
+    /// Names which were quoted keep the same style of quotes, unless `new`
+    /// doesn't need any; names which weren't get backticks if `new` needs
+    /// them. Gives the span of each identifier which was changed along with
+    /// its new text, which is handy for turning into edits of the original
+    /// text.
+    ///
+    /// Fails when there's no entity (or relationship end) named `old`, when
+    /// there's already an entity named `new` or when `new` isn't a valid
+    /// identifier.
+    pub fn rename_entity(&mut self, old: &str, new: &str) -> Result<Vec<(Span, String)>> {
+        check_name(new)?;
+        if old != new && entity_idents(self.root()).any(|ident| unquote(ident) == new) {
+            return Err(Error::EntityExists {
+                name: new.to_string(),
+            });
+        }
+
+        let mut edits = vec![];
+        rename_idents(self.root_mut(), old, new, &mut edits);
+        if edits.is_empty() {
+            return Err(Error::UnknownEntity {
+                name: old.to_string(),
+            });
+        }
+        Ok(edits)
+    }
+}
+
+impl ER {
+    /// Renames the entity `old` to `new`, along with every relationship
+    /// which refers to it.
+    ///
+    /// Fails in the same cases as
+    /// [Cst::rename_entity](../cst/struct.Cst.html#method.rename_entity).
+    pub fn rename_entity(&mut self, old: &str, new: &str) -> Result<()> {
+        check_name(new)?;
+        if old != new && self.entities.iter().any(|e| e.name == new) {
+            return Err(Error::EntityExists {
+                name: new.to_string(),
+            });
+        }
+
+        let mut found = false;
+        for entity in self.entities.iter_mut().filter(|e| e.name == old) {
+            entity.name = new.to_string();
+            found = true;
+        }
+        for rel in &mut self.rels {
+            for name in [&mut rel.entity1, &mut rel.entity2] {
+                if name == old {
+                    *name = new.to_string();
+                    found = true;
+                }
+            }
+        }
+        if !found {
+            return Err(Error::UnknownEntity {
+                name: old.to_string(),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Checks `name` could be written as an identifier: words separated by
+/// spaces.
+pub(crate) fn check_name(name: &str) -> Result<()> {
+    let word = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    let valid = name.starts_with(word)
+        && name.ends_with(word)
+        && name.chars().all(|c| word(c) || c == ' ' || c == '\t');
+    if valid {
+        Ok(())
+    } else {
+        Err(Error::InvalidName {
+            name: name.to_string(),
+        })
+    }
+}
+
+/// The identifiers in entity headers.
+fn entity_idents(root: &Node) -> impl Iterator<Item = &str> {
+    root.children()
+        .iter()
+        .filter(|n| matches!(n.kind(), NodeKind::Entity { .. }))
+        .filter_map(|n| header_ident(n).and_then(Node::text))
+}
+
+/// The identifier in an entity's header. It's the only one directly under
+/// the entity node; the rest belong to attributes.
+fn header_ident(entity: &Node) -> Option<&Node> {
+    entity
+        .children()
+        .iter()
+        .find(|n| n.kind() == NodeKind::Ident)
+}
+
+fn rename_idents(root: &mut Node, old: &str, new: &str, edits: &mut Vec<(Span, String)>) {
+    for item in root.children_mut() {
+        let is_item = matches!(
+            item.kind(),
+            NodeKind::Entity { .. } | NodeKind::Relation { .. }
+        );
+        if !is_item {
+            continue;
+        }
+        let idents = item
+            .children_mut()
+            .iter_mut()
+            .filter(|n| n.kind() == NodeKind::Ident);
+        for ident in idents {
+            let text = ident.text().expect("token");
+            if unquote(text) != old {
+                continue;
+            }
+            let quoted = requote(text, new);
+            edits.push((ident.span(), quoted.clone()));
+            ident.set_text(quoted);
+        }
+    }
+}
+
+/// Strips the quotes (if any) from an identifier.
+pub(crate) fn unquote(ident: &str) -> &str {
+    match ident.chars().next() {
+        Some(q @ ('`' | '\'' | '"')) => ident.trim_start_matches(q).trim_end_matches(q),
+        _ => ident,
+    }
+}
+
+/// Writes `name` in place of the identifier `old`, keeping its quotes when
+/// they're still needed.
+fn requote(old: &str, name: &str) -> String {
+    if !needs_quotes(name) {
+        return name.to_string();
+    }
+    match old.chars().next() {
+        Some(q @ ('`' | '\'' | '"')) => format!("{}{}{}", q, name, q),
+        _ => format!("`{}`", name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::rename_entity;
+    use crate::parser::parse;
+    use crate::Error;
+
+    #[test]
+    fn test_rename_keeps_everything_else() {
+        let input = r#"# People.
+[Person]
+*name
++`birth place id` # where they're from
+
+['Birth Place'] { label: "where" }
+*id
+
+Person *--1 "Birth Place"
+`Birth Place` 1--* Person # the same again
+"#;
+        let renamed = rename_entity(input, "Birth Place", "Place of Birth").unwrap();
+        assert_eq!(
+            renamed,
+            r#"# People.
+[Person]
+*name
++`birth place id` # where they're from
+
+['Place of Birth'] { label: "where" }
+*id
+
+Person *--1 "Place of Birth"
+`Place of Birth` 1--* Person # the same again
+"#
+        );
+    }
+
+    #[test]
+    fn test_rename_picks_quoting() {
+        let input = "[Person]\n[`Birth Place`]\nPerson *--1 `Birth Place`\n";
+        assert_eq!(
+            rename_entity(input, "Birth Place", "Place").unwrap(),
+            "[Person]\n[Place]\nPerson *--1 Place\n"
+        );
+        assert_eq!(
+            rename_entity(input, "Person", "A Person").unwrap(),
+            "[`A Person`]\n[`Birth Place`]\n`A Person` *--1 `Birth Place`\n"
+        );
+    }
+
+    #[test]
+    fn test_rename_model() {
+        let input = "[Person]\n[Place]\nPerson *--1 Place\nPlace 1--1 Place\n";
+        let mut er = parse(input).unwrap();
+        er.rename_entity("Place", "Birth Place").unwrap();
+
+        let renamed = rename_entity(input, "Place", "Birth Place").unwrap();
+        assert_eq!(er, parse(&renamed).unwrap());
+        assert_eq!(er.rels[1].entity1, "Birth Place");
+        assert_eq!(er.rels[1].entity2, "Birth Place");
+    }
+
+    #[test]
+    fn test_rename_errors() {
+        let input = "[Person]\n[Place]\nPerson *--1 Place\n";
+        assert!(matches!(
+            rename_entity(input, "Nobody", "Somebody"),
+            Err(Error::UnknownEntity { .. })
+        ));
+        assert!(matches!(
+            rename_entity(input, "Person", "Place"),
+            Err(Error::EntityExists { .. })
+        ));
+        for bad in &["", " Place", "Pla-ce", "Place\nTwo", "`Place`"] {
+            assert!(matches!(
+                rename_entity(input, "Place", bad),
+                Err(Error::InvalidName { .. })
+            ));
+            assert!(matches!(
+                parse(input).unwrap().rename_entity("Place", bad),
+                Err(Error::InvalidName { .. })
+            ));
+        }
+        // Renaming to the same name is fine.
+        assert_eq!(rename_entity(input, "Place", "Place").unwrap(), input);
+    }
+}
@@ -0,0 +1,658 @@
+//! Serde support for the [ER](../er/struct.ER.html) model, enabled by the
+//! `serde` feature.
+//!
+//! The model serializes to a fixed schema, described below, which doesn't
+//! change when the types behind it do. It's meant for tools which want the
+//! contents of an er file without parsing it themselves, so it holds what the
+//! diagram describes and nothing about how the file was written (comments,
+//! layout and spans are all left out).
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "directives": {
+//!     "title": { "label": "People and places" },
+//!     "header": { "size": 20.0 }
+//!   },
+//!   "entities": [
+//!     {
+//!       "name": "Person",
+//!       "doc": "Somebody.",
+//!       "options": { "bgcolor": "#d0e0d0" },
+//!       "attributes": [
+//!         { "name": "name", "pk": true, "fk": false, "options": {} },
+//!         { "name": "birth_place_id", "pk": false, "fk": true, "options": {} }
+//!       ]
+//!     }
+//!   ],
+//!   "relationships": [
+//!     {
+//!       "entity1": "Person",
+//!       "entity2": "Birth Place",
+//!       "card1": "*",
+//!       "card2": "1",
+//!       "options": { "label": "born in" }
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! - `version` is the version of the schema, currently always `1`.
+//! - `directives` holds the options given by the `title`, `header`, `entity`
+//!   and `relationship` directives. Directives which weren't given are left
+//!   out.
+//! - `entities` and `relationships` are in the order they were written.
+//! - Cardinalities are written as in an er file: `?` (zero or one), `1`
+//!   (exactly one), `*` (zero or more) or `+` (one or more). `card1` belongs
+//!   to `entity1`.
+//! - `doc` is the text of the `##` comments before an item, and is left out
+//!   when there weren't any.
+//! - Every `options` is an object keyed by the option names used in er files
+//!   (`label`, `bgcolor`, `size`, `border-color` and so on). The numeric
+//!   options (`size`, `border`, `cellspacing`, `cellborder` and `cellpadding`)
+//!   are written as numbers, the rest as strings.
+//!
+//! When deserializing, `version`, `directives`, `relationships`, `doc`, `pk`,
+//! `fk`, `options` and `attributes` may all be left out. Option values may be
+//! given as strings or numbers.
+//!
+//! Imported documents are held to the same rules as er files: names have to
+//! be valid identifiers, and options are parsed just as they would be in an
+//! er file. [import](fn.import.html) reports problems with the same errors as
+//! the parser; the `Deserialize` impls only have the messages to go on.
+
+use crate::er::{
+    merge_opts, option_by_name, Attribute, Cardinality, Directive, Entity, GlobalOptions, Opt,
+    Options, Relation, ER,
+};
+use crate::rename::check_name;
+use crate::{Error, Result};
+use serde::de::{self, Deserializer, MapAccess, Visitor};
+use serde::ser::{SerializeMap, Serializer};
+use serde::{Deserialize, Serialize};
+use std::fmt::Formatter;
+
+/// The version of the schema written by this crate.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Reads an `ER` from any serde data format (JSON, YAML, ...).
+///
+/// ```ignore
+/// let mut deserializer = serde_json::Deserializer::from_str(json);
+/// let er = erd_rs::schema::import(&mut deserializer)?;
+/// // Otherwise anything after the document is ignored.
+/// deserializer.end()?;
+/// ```
+///
+/// A document which doesn't follow the schema fails with
+/// [Error::InvalidDocument](../enum.Error.html#variant.InvalidDocument).
+/// Names and options are checked the same way as in an er file, failing with
+/// the same errors.
+pub fn import<'de, D: Deserializer<'de>>(deserializer: D) -> Result<ER> {
+    let repr = ErRepr::deserialize(deserializer).map_err(|err| Error::InvalidDocument {
+        message: err.to_string(),
+        span: None,
+    })?;
+    repr.into_er()
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ErRepr {
+    #[serde(default = "version")]
+    version: u32,
+    #[serde(default, skip_serializing_if = "DirectivesRepr::is_empty")]
+    directives: DirectivesRepr,
+    entities: Vec<EntityRepr>,
+    #[serde(default)]
+    relationships: Vec<RelationRepr>,
+}
+
+fn version() -> u32 {
+    SCHEMA_VERSION
+}
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DirectivesRepr {
+    #[serde(default, skip_serializing_if = "RawOptions::is_empty")]
+    title: RawOptions,
+    #[serde(default, skip_serializing_if = "RawOptions::is_empty")]
+    header: RawOptions,
+    #[serde(default, skip_serializing_if = "RawOptions::is_empty")]
+    entity: RawOptions,
+    #[serde(default, skip_serializing_if = "RawOptions::is_empty")]
+    relationship: RawOptions,
+}
+
+impl DirectivesRepr {
+    fn is_empty(&self) -> bool {
+        self.title.is_empty()
+            && self.header.is_empty()
+            && self.entity.is_empty()
+            && self.relationship.is_empty()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EntityRepr {
+    name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    doc: Option<String>,
+    #[serde(default)]
+    options: RawOptions,
+    #[serde(default)]
+    attributes: Vec<AttributeRepr>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AttributeRepr {
+    name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    doc: Option<String>,
+    #[serde(default)]
+    pk: bool,
+    #[serde(default)]
+    fk: bool,
+    #[serde(default)]
+    options: RawOptions,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RelationRepr {
+    entity1: String,
+    entity2: String,
+    card1: Cardinality,
+    card2: Cardinality,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    doc: Option<String>,
+    #[serde(default)]
+    options: RawOptions,
+}
+
+impl Serialize for ER {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        ErRepr::from(self).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for ER {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        ErRepr::deserialize(deserializer)?
+            .into_er()
+            .map_err(de::Error::custom)
+    }
+}
+
+impl From<&ER> for ErRepr {
+    fn from(er: &ER) -> Self {
+        let globals = &er.globals;
+        ErRepr {
+            version: SCHEMA_VERSION,
+            directives: DirectivesRepr {
+                title: globals.get(Directive::Title).into(),
+                header: globals.get(Directive::Header).into(),
+                entity: globals.get(Directive::Entity).into(),
+                relationship: globals.get(Directive::Relationship).into(),
+            },
+            entities: er
+                .entities
+                .iter()
+                .map(|entity| EntityRepr {
+                    name: entity.name.clone(),
+                    doc: entity.doc.clone(),
+                    // The parser gives the header and body the same options,
+                    // but anything building an `ER` by hand might not.
+                    options: (&merge_opts(&entity.hoptions, &entity.eoptions)).into(),
+                    attributes: entity
+                        .attribs
+                        .iter()
+                        .map(|attr| AttributeRepr {
+                            name: attr.field.clone(),
+                            doc: attr.doc.clone(),
+                            pk: attr.pk,
+                            fk: attr.fk,
+                            options: (&attr.options).into(),
+                        })
+                        .collect(),
+                })
+                .collect(),
+            relationships: er
+                .rels
+                .iter()
+                .map(|rel| RelationRepr {
+                    entity1: rel.entity1.clone(),
+                    entity2: rel.entity2.clone(),
+                    card1: rel.card1,
+                    card2: rel.card2,
+                    doc: rel.doc.clone(),
+                    options: (&rel.options).into(),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl ErRepr {
+    /// Builds the model, checking everything the parser would have.
+    fn into_er(self) -> Result<ER> {
+        if self.version != SCHEMA_VERSION {
+            return Err(Error::InvalidDocument {
+                message: format!(
+                    "unsupported schema version {} (expected {})",
+                    self.version, SCHEMA_VERSION
+                ),
+                span: None,
+            });
+        }
+
+        let directives = self.directives;
+        let globals = GlobalOptions::from_directives(vec![
+            (Directive::Title, directives.title.parse()?),
+            (Directive::Header, directives.header.parse()?),
+            (Directive::Entity, directives.entity.parse()?),
+            (Directive::Relationship, directives.relationship.parse()?),
+        ]);
+
+        let mut entities = vec![];
+        for entity in self.entities {
+            check_name(&entity.name)?;
+            let mut attribs = vec![];
+            for attr in entity.attributes {
+                check_name(&attr.name)?;
+                attribs.push(Attribute {
+                    field: attr.name,
+                    pk: attr.pk,
+                    fk: attr.fk,
+                    options: attr.options.parse()?,
+                    doc: attr.doc,
+                    span: Default::default(),
+                });
+            }
+            let options = entity.options.parse()?;
+            entities.push(Entity {
+                name: entity.name,
+                attribs,
+                hoptions: options.clone(),
+                eoptions: options,
+                doc: entity.doc,
+                span: Default::default(),
+                name_span: Default::default(),
+            });
+        }
+
+        let mut rels = vec![];
+        for rel in self.relationships {
+            check_name(&rel.entity1)?;
+            check_name(&rel.entity2)?;
+            rels.push(Relation {
+                entity1: rel.entity1,
+                entity2: rel.entity2,
+                card1: rel.card1,
+                card2: rel.card2,
+                options: rel.options.parse()?,
+                doc: rel.doc,
+                span: Default::default(),
+                entity1_span: Default::default(),
+                entity2_span: Default::default(),
+            });
+        }
+
+        Ok(ER {
+            entities,
+            rels,
+            globals,
+        })
+    }
+}
+
+/// Options as written in a serialized document: an object keyed by option
+/// name, in whatever order they were given.
+#[derive(Default)]
+struct RawOptions(Vec<(String, ValueRepr)>);
+
+impl RawOptions {
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Parses each option the same way as the parser does.
+    fn parse(self) -> Result<Options> {
+        let mut options = Options::default();
+        for (name, value) in self.0 {
+            let value = match value {
+                // There's no way to write a double quote in an option value.
+                ValueRepr::String(s) if s.contains('"') => {
+                    return Err(Error::QuoteInValue { name })
+                }
+                ValueRepr::String(s) => s,
+                ValueRepr::Int(n) => n.to_string(),
+                ValueRepr::Float(n) => n.to_string(),
+            };
+            options.set(option_by_name(&name, &value)?);
+        }
+        Ok(options)
+    }
+}
+
+impl From<&Options> for RawOptions {
+    fn from(options: &Options) -> Self {
+        RawOptions(
+            options
+                .iter()
+                .map(|opt| (opt.html_attr_name().to_string(), ValueRepr::from(&opt)))
+                .collect(),
+        )
+    }
+}
+
+impl Serialize for RawOptions {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (name, value) in &self.0 {
+            map.serialize_entry(name, value)?;
+        }
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for RawOptions {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        struct RawOptionsVisitor;
+
+        impl<'de> Visitor<'de> for RawOptionsVisitor {
+            type Value = RawOptions;
+
+            fn expecting(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+                write!(f, "an object of formatting options")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(
+                self,
+                mut map: A,
+            ) -> std::result::Result<RawOptions, A::Error> {
+                let mut options = vec![];
+                while let Some(entry) = map.next_entry()? {
+                    options.push(entry);
+                }
+                Ok(RawOptions(options))
+            }
+        }
+
+        deserializer.deserialize_map(RawOptionsVisitor)
+    }
+}
+
+/// An option value, written as a number when it is one.
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum ValueRepr {
+    String(String),
+    Int(i64),
+    Float(f64),
+}
+
+impl From<&Opt> for ValueRepr {
+    fn from(opt: &Opt) -> Self {
+        match opt {
+            Opt::FontSize(n) => ValueRepr::Float(*n),
+            Opt::Border(n) | Opt::CellSpacing(n) | Opt::CellBorder(n) | Opt::CellPadding(n) => {
+                ValueRepr::Int(i64::from(*n))
+            }
+            opt => ValueRepr::String(opt.value()),
+        }
+    }
+}
+
+/// Options are written as an object keyed by option name.
+impl Serialize for Options {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        RawOptions::from(self).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Options {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        RawOptions::deserialize(deserializer)?
+            .parse()
+            .map_err(|err| match err {
+                Error::UnknownFormatOption { name, .. } => {
+                    de::Error::unknown_field(&name, &crate::er::OPTION_NAMES)
+                }
+                err => de::Error::custom(err),
+            })
+    }
+}
+
+/// A single option is written as an object with just the one key.
+impl Serialize for Opt {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(1))?;
+        map.serialize_entry(self.html_attr_name(), &ValueRepr::from(self))?;
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Opt {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let options = Options::deserialize(deserializer)?;
+        let mut opts = options.iter();
+        match (opts.next(), opts.next()) {
+            (Some(opt), None) => Ok(opt),
+            _ => Err(de::Error::custom("expected exactly one formatting option")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::import;
+    use crate::er::{Opt, ER};
+    use crate::parser::parse;
+    use crate::Error;
+    use serde_json::json;
+
+    #[test]
+    fn test_serialize_basics_sample() {
+        let er = parse(include_str!("../samples/basics.erd")).unwrap();
+        let value = serde_json::to_value(&er).unwrap();
+
+        assert_eq!(value["version"], 1);
+        assert_eq!(value["entities"][0]["name"], "Person");
+        assert_eq!(
+            value["entities"][0]["attributes"][0],
+            json!({"name": "name", "pk": true, "fk": false, "options": {}})
+        );
+        assert_eq!(
+            value["relationships"][0],
+            json!({
+                "entity1": "Person",
+                "entity2": "Birth Place",
+                "card1": "*",
+                "card2": "1",
+                "options": {},
+            })
+        );
+    }
+
+    #[test]
+    fn test_serialize_options_and_docs() {
+        let er = parse(
+            r##"
+            title { label: "People" }
+            header { size: "20" }
+
+            ## Somebody.
+            [Person] { bgcolor: "#fff", border: "2" }
+                *name { label: "text" }
+            "##,
+        )
+        .unwrap();
+        let value = serde_json::to_value(&er).unwrap();
+
+        assert_eq!(
+            value["directives"],
+            json!({"title": {"label": "People"}, "header": {"size": 20.0}})
+        );
+        assert_eq!(value["entities"][0]["doc"], "Somebody.");
+        assert_eq!(
+            value["entities"][0]["options"],
+            json!({"bgcolor": "#fff", "border": 2})
+        );
+        assert_eq!(
+            value["entities"][0]["attributes"][0]["options"],
+            json!({"label": "text"})
+        );
+        assert_eq!(value["relationships"], json!([]));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let er = parse(
+            r##"
+            entity { font: "Courier" }
+            ## Somebody.
+            [Person] { bgcolor: "#fff", size: "12.5" }
+                *name
+                +`birth place id`
+            [`Birth Place`]
+                *id
+            Person *--1 `Birth Place` { label: "born in" }
+            `Birth Place` ?--+ Person
+            "##,
+        )
+        .unwrap();
+
+        let json = serde_json::to_string(&er).unwrap();
+        assert_eq!(serde_json::from_str::<ER>(&json).unwrap(), er);
+        let yaml = serde_yaml::to_string(&er).unwrap();
+        assert_eq!(serde_yaml::from_str::<ER>(&yaml).unwrap(), er);
+    }
+
+    #[test]
+    fn test_deserialize_defaults_and_numbers() {
+        let er: ER = serde_json::from_value(json!({
+            "entities": [
+                {"name": "Person", "options": {"cellpadding": 4, "size": "16"}},
+            ],
+        }))
+        .unwrap();
+        assert_eq!(
+            er,
+            parse("[Person] {cellpadding: \"4\", size: \"16\"}").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_deserialize_rejects_bad_options() {
+        let err = serde_json::from_value::<ER>(json!({
+            "entities": [{"name": "Person", "options": {"cellpadding": "wide"}}],
+        }))
+        .unwrap_err();
+        assert!(err.to_string().contains("Invalid value for `cellpadding`"));
+
+        let err = serde_json::from_value::<ER>(json!({
+            "entities": [{"name": "Person", "options": {"colour": "red"}}],
+        }))
+        .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("Unknown formatting option: `colour`"));
+
+        let err = serde_json::from_value::<ER>(json!({"version": 2, "entities": []})).unwrap_err();
+        assert!(err.to_string().contains("unsupported schema version 2"));
+    }
+
+    #[test]
+    fn test_import_errors_match_the_parser() {
+        let json = |value: serde_json::Value| import(value).unwrap_err();
+
+        let err = json(json!({
+            "entities": [{"name": "Person", "options": {"cellpadding": "wide"}}],
+        }));
+        let source = "[Person] {cellpadding: \"wide\"}";
+        let parse_err = parse(source).unwrap_err();
+        assert!(matches!(err, Error::InvalidInt { .. }));
+        assert_eq!(
+            err.diagnostic("").message,
+            parse_err.diagnostic(source).message
+        );
+
+        let err = json(json!({
+            "entities": [{"name": "Person", "attributes": [{"name": "x", "options": {"colour": "red"}}]}],
+        }));
+        assert!(matches!(err, Error::UnknownFormatOption { name, .. } if name == "colour"));
+
+        let err = json(json!({"entities": [{"name": "Per-son"}]}));
+        assert!(matches!(err, Error::InvalidName { name } if name == "Per-son"));
+
+        let err = json(json!({"entities": [], "relationships": [
+            {"entity1": "A", "entity2": "`B`", "card1": "1", "card2": "1"},
+        ]}));
+        assert!(matches!(err, Error::InvalidName { .. }));
+
+        let err = json(json!({"entities": [{"name": "A", "options": {"label": "say \"hi\""}}]}));
+        assert!(matches!(err, Error::QuoteInValue { .. }));
+
+        let err = json(json!({"entities": [], "relationships": [
+            {"entity1": "A", "entity2": "B", "card1": "2", "card2": "1"},
+        ]}));
+        assert!(matches!(err, Error::InvalidDocument { .. }));
+
+        let err = json(json!({"entites": [{"name": "Person"}], "entities": []}));
+        assert!(
+            matches!(&err, Error::InvalidDocument { message, .. } if message.contains("entites"))
+        );
+
+        let err = json(
+            json!({"entities": [{"name": "Person", "attributes": [{"name": "x", "primary": true}]}]}),
+        );
+        assert!(
+            matches!(&err, Error::InvalidDocument { message, .. } if message.contains("primary"))
+        );
+
+        let err = json(json!({"entities": [], "relationships": [
+            {"entity1": "A", "entity2": "B", "card1": "1", "card2": "1", "label": "x"},
+        ]}));
+        assert!(matches!(err, Error::InvalidDocument { .. }));
+
+        let err = json(json!({"version": 2, "entities": []}));
+        assert!(err.to_string().contains("unsupported schema version 2"));
+    }
+
+    #[test]
+    fn test_import_yaml() {
+        let yaml = r##"
+entities:
+  - name: Person
+    options: { size: 20, bgcolor: "#fff" }
+    attributes:
+      - { name: name, pk: true }
+  - name: Birth Place
+relationships:
+  - { entity1: Person, entity2: Birth Place, card1: "*", card2: "1" }
+"##;
+        let er = import(serde_yaml::Deserializer::from_str(yaml)).unwrap();
+        let expected = parse(
+            r##"
+            [Person] { size: "20", bgcolor: "#fff" }
+                *name
+            [`Birth Place`]
+            Person *--1 `Birth Place`
+            "##,
+        )
+        .unwrap();
+        assert_eq!(er, expected);
+    }
+
+    #[test]
+    fn test_opt() {
+        let opt: Opt = serde_json::from_value(json!({"border": 3})).unwrap();
+        assert_eq!(opt, Opt::Border(3));
+        assert_eq!(serde_json::to_value(&opt).unwrap(), json!({"border": 3}));
+        assert!(serde_json::from_value::<Opt>(json!({})).is_err());
+    }
+}
@@ -0,0 +1,135 @@
+use std::fmt::{Display, Formatter};
+
+/// A region of the source text of an er file.
+///
+/// `start` and `end` are byte offsets into the source, while `line` and `col`
+/// give the (1-based) position of `start` in terms a person would use. The
+/// column is counted in characters rather than bytes.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub col: usize,
+}
+
+impl Span {
+    /// The slice of `source` covered by this span.
+    pub fn as_str<'s>(&self, source: &'s str) -> &'s str {
+        &source[self.start..self.end]
+    }
+}
+
+impl Display for Span {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.line, self.col)
+    }
+}
+
+/// Maps byte offsets to line/column positions for a given source.
+///
+/// Pest can do this for us, but it rescans the input from the start each time
+/// it's asked which gets slow with a span on everything in a large file.
+pub(crate) struct LineIndex<'s> {
+    source: &'s str,
+    /// Byte offset for the start of each line.
+    starts: Vec<usize>,
+}
+
+impl<'s> LineIndex<'s> {
+    pub fn new(source: &'s str) -> Self {
+        let starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        LineIndex { source, starts }
+    }
+
+    /// The (0-based) line holding `offset`.
+    fn line_of(&self, offset: usize) -> usize {
+        match self.starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(next) => next - 1,
+        }
+    }
+
+    /// Builds a span for the byte range `start..end`.
+    pub fn span(&self, start: usize, end: usize) -> Span {
+        let line = self.line_of(start);
+        let col = self.source[self.starts[line]..start].chars().count();
+        Span {
+            start,
+            end,
+            line: line + 1,
+            col: col + 1,
+        }
+    }
+
+    /// The lines before the one holding `offset`, nearest first and without
+    /// their line endings.
+    pub fn lines_before(&self, offset: usize) -> impl Iterator<Item = &'s str> + '_ {
+        let line = self.line_of(offset);
+        (0..line).rev().map(move |i| {
+            let text = &self.source[self.starts[i]..self.starts[i + 1] - 1];
+            text.strip_suffix('\r').unwrap_or(text)
+        })
+    }
+
+    /// The (0-based) line holding `offset`, and how far into it `offset` is
+    /// in utf-16 code units, which is how the language server protocol counts.
+    #[cfg(feature = "lsp")]
+    pub fn utf16_position(&self, offset: usize) -> (usize, usize) {
+        let line = self.line_of(offset);
+        let units = self.source[self.starts[line]..offset]
+            .encode_utf16()
+            .count();
+        (line, units)
+    }
+
+    /// Builds a span for the byte range `start..end`, less any trailing
+    /// whitespace.
+    ///
+    /// Several rules in the grammar consume the newline following them, which
+    /// isn't something anyone wants highlighted.
+    pub fn trimmed_span(&self, start: usize, end: usize) -> Span {
+        let trimmed = self.source[start..end].trim_end();
+        self.span(start, start + trimmed.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LineIndex;
+
+    #[test]
+    fn test_line_index() {
+        let source = "ab\ncdé\n\nf";
+        let lines = LineIndex::new(source);
+
+        let span = lines.span(0, 2);
+        assert_eq!((span.line, span.col), (1, 1));
+        let span = lines.span(3, 4);
+        assert_eq!((span.line, span.col), (2, 1));
+        assert_eq!(span.as_str(source), "c");
+        // The `é` is two bytes but one column.
+        let span = lines.span(7, 8);
+        assert_eq!((span.line, span.col), (2, 4));
+        let span = lines.span(8, 8);
+        assert_eq!((span.line, span.col), (3, 1));
+        let span = lines.span(9, 10);
+        assert_eq!((span.line, span.col), (4, 1));
+    }
+
+    #[test]
+    fn test_lines_before() {
+        let source = "a\r\nb\n\ncd";
+        let lines: Vec<_> = LineIndex::new(source).lines_before(8).collect();
+        assert_eq!(lines, vec!["", "b", "a"]);
+    }
+
+    #[test]
+    fn test_trimmed_span() {
+        let source = "[Person]   \n*name";
+        let span = LineIndex::new(source).trimmed_span(0, 12);
+        assert_eq!(span.as_str(source), "[Person]");
+    }
+}
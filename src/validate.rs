@@ -0,0 +1,381 @@
+//! Checks an [ER](../er/struct.ER.html) for problems the grammar can't catch.
+//!
+//! A document can parse cleanly and still not make much sense: relationships
+//! naming entities that were never declared, the same entity declared twice,
+//! and so on. None of these stop a diagram from being rendered (graphviz will
+//! happily invent a node for a missing entity), so they're left to a separate
+//! pass which can be run as a gate before rendering.
+
+use crate::er::{Cardinality, ER};
+use crate::fk::{implements, many_side, other_end};
+use crate::span::Span;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+
+/// How serious an [Issue](enum.Issue.html) is.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Severity {
+    /// Almost certainly a mistake in the er file.
+    Error,
+    /// Allowed, but worth a second look.
+    Warning,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// A problem found by [ER::validate](../er/struct.ER.html#method.validate).
+///
+/// Where a problem is about something being declared twice, `span` points at
+/// the second declaration and `first` at the original.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Issue {
+    /// A relationship names an entity which was never declared.
+    UndefinedEntity { name: String, span: Span },
+    /// Two entities share a name.
+    DuplicateEntity {
+        name: String,
+        span: Span,
+        first: Span,
+    },
+    /// An entity declares the same attribute twice.
+    DuplicateAttribute {
+        entity: String,
+        field: String,
+        span: Span,
+        first: Span,
+    },
+    /// An entity has no attribute marked as a primary key.
+    MissingPrimaryKey { entity: String, span: Span },
+    /// The same relationship is declared twice.
+    ///
+    /// `A 1--* B` and `B *--1 A` are the same relationship written from
+    /// either end, so count as duplicates too.
+    DuplicateRelation {
+        entity1: String,
+        entity2: String,
+        span: Span,
+        first: Span,
+    },
+    /// A foreign key attribute doesn't match any relationship.
+    ///
+    /// See the [fk](../fk/index.html) module for how they're matched.
+    UnmatchedForeignKey {
+        entity: String,
+        field: String,
+        span: Span,
+    },
+    /// The "many" side of a one-to-many relationship has no foreign key
+    /// attribute for it.
+    MissingForeignKey {
+        entity: String,
+        other: String,
+        span: Span,
+    },
+}
+
+impl Issue {
+    pub fn severity(&self) -> Severity {
+        match self {
+            Issue::UndefinedEntity { .. }
+            | Issue::DuplicateEntity { .. }
+            | Issue::DuplicateAttribute { .. } => Severity::Error,
+            Issue::MissingPrimaryKey { .. }
+            | Issue::DuplicateRelation { .. }
+            | Issue::UnmatchedForeignKey { .. }
+            | Issue::MissingForeignKey { .. } => Severity::Warning,
+        }
+    }
+
+    /// Where in the er file the problem was found.
+    pub fn span(&self) -> Span {
+        match self {
+            Issue::UndefinedEntity { span, .. }
+            | Issue::DuplicateEntity { span, .. }
+            | Issue::DuplicateAttribute { span, .. }
+            | Issue::MissingPrimaryKey { span, .. }
+            | Issue::DuplicateRelation { span, .. }
+            | Issue::UnmatchedForeignKey { span, .. }
+            | Issue::MissingForeignKey { span, .. } => *span,
+        }
+    }
+}
+
+impl Display for Issue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Issue::UndefinedEntity { name, .. } => {
+                write!(f, "relationship refers to undefined entity `{}`", name)
+            }
+            Issue::DuplicateEntity { name, first, .. } => write!(
+                f,
+                "entity `{}` is already declared (first declared at {})",
+                name, first
+            ),
+            Issue::DuplicateAttribute {
+                entity,
+                field,
+                first,
+                ..
+            } => write!(
+                f,
+                "attribute `{}` is declared twice in `{}` (first declared at {})",
+                field, entity, first
+            ),
+            Issue::MissingPrimaryKey { entity, .. } => {
+                write!(f, "entity `{}` has no primary key", entity)
+            }
+            Issue::DuplicateRelation {
+                entity1,
+                entity2,
+                first,
+                ..
+            } => write!(
+                f,
+                "relationship between `{}` and `{}` is already declared (first declared at {})",
+                entity1, entity2, first
+            ),
+            Issue::UnmatchedForeignKey { entity, field, .. } => write!(
+                f,
+                "foreign key `{}` in `{}` doesn't match any relationship",
+                field, entity
+            ),
+            Issue::MissingForeignKey { entity, other, .. } => write!(
+                f,
+                "`{}` has no foreign key for its relationship with `{}`",
+                entity, other
+            ),
+        }
+    }
+}
+
+impl ER {
+    /// Checks the diagram for problems which are syntactically fine but
+    /// probably not what was meant.
+    ///
+    /// Issues are given in the order they appear in the document. An empty
+    /// list means everything checks out.
+    pub fn validate(&self) -> Vec<Issue> {
+        let mut issues = vec![];
+
+        let mut entities: HashMap<&str, Span> = HashMap::new();
+        for entity in &self.entities {
+            if let Some(first) = entities.get(entity.name.as_str()) {
+                issues.push(Issue::DuplicateEntity {
+                    name: entity.name.clone(),
+                    span: entity.name_span,
+                    first: *first,
+                });
+            } else {
+                entities.insert(&entity.name, entity.name_span);
+            }
+
+            let mut fields: HashMap<&str, Span> = HashMap::new();
+            for attr in &entity.attribs {
+                if let Some(first) = fields.get(attr.field.as_str()) {
+                    issues.push(Issue::DuplicateAttribute {
+                        entity: entity.name.clone(),
+                        field: attr.field.clone(),
+                        span: attr.span,
+                        first: *first,
+                    });
+                } else {
+                    fields.insert(&attr.field, attr.span);
+                }
+            }
+
+            if !entity.attribs.iter().any(|attr| attr.pk) {
+                issues.push(Issue::MissingPrimaryKey {
+                    entity: entity.name.clone(),
+                    span: entity.name_span,
+                });
+            }
+        }
+
+        type RelKey<'a> = (&'a str, Cardinality, &'a str, Cardinality);
+        let mut rels: HashMap<RelKey, Span> = HashMap::new();
+        for rel in &self.rels {
+            let (span1, span2) = rel.endpoint_spans();
+            for (name, span) in [(&rel.entity1, span1), (&rel.entity2, span2)] {
+                if !entities.contains_key(name.as_str()) {
+                    issues.push(Issue::UndefinedEntity {
+                        name: name.clone(),
+                        span,
+                    });
+                }
+            }
+
+            // Write the key from whichever end sorts first so that a
+            // relationship matches itself written backwards.
+            let forward = (
+                rel.entity1.as_str(),
+                rel.card1,
+                rel.entity2.as_str(),
+                rel.card2,
+            );
+            let key = if rel.entity2 < rel.entity1 {
+                (forward.2, forward.3, forward.0, forward.1)
+            } else {
+                forward
+            };
+            if let Some(first) = rels.get(&key) {
+                issues.push(Issue::DuplicateRelation {
+                    entity1: rel.entity1.clone(),
+                    entity2: rel.entity2.clone(),
+                    span: rel.span,
+                    first: *first,
+                });
+            } else {
+                rels.insert(key, rel.span);
+            }
+        }
+
+        for fk in self
+            .foreign_keys()
+            .iter()
+            .filter(|fk| fk.relation.is_none())
+        {
+            issues.push(Issue::UnmatchedForeignKey {
+                entity: fk.entity.clone(),
+                field: fk.field.clone(),
+                span: fk.span,
+            });
+        }
+        for rel in &self.rels {
+            let entity = match many_side(rel) {
+                Some(entity) if entities.contains_key(entity) => entity,
+                _ => continue,
+            };
+            // Not just the keys matched to this relationship, since a key can
+            // only be matched to one and this may be a duplicate.
+            let has_fk = self
+                .entities
+                .iter()
+                .filter(|e| e.name == entity)
+                .any(|e| e.attribs.iter().any(|attr| implements(e, attr, rel)));
+            if !has_fk {
+                issues.push(Issue::MissingForeignKey {
+                    entity: entity.to_string(),
+                    other: other_end(rel, entity).unwrap_or(entity).to_string(),
+                    span: rel.span,
+                });
+            }
+        }
+
+        issues.sort_by_key(|issue| issue.span().start);
+        issues
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Issue, Severity};
+    use crate::parser::parse;
+
+    #[test]
+    fn test_basics_sample_is_valid() {
+        let er = parse(include_str!("../samples/basics.erd")).unwrap();
+        assert_eq!(er.validate(), vec![]);
+    }
+
+    #[test]
+    fn test_undefined_entity() {
+        let input = "[Person]\n*id\nPerson 1--1 `Birth Place`\n";
+        let er = parse(input).unwrap();
+        let issues = er.validate();
+
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].severity(), Severity::Error);
+        assert_eq!(issues[0].span().as_str(input), "`Birth Place`");
+        assert_eq!(
+            issues[0].to_string(),
+            "relationship refers to undefined entity `Birth Place`"
+        );
+    }
+
+    #[test]
+    fn test_duplicates() {
+        let input = r#"[Person]
+*id
+name
+name
+[Group]
+*id
+[Person]
+*id
++group_id
+Person *--1 Group
+Group 1--* Person
+Person 1--1 Group
+"#;
+        let er = parse(input).unwrap();
+        let issues = er.validate();
+
+        assert!(matches!(
+            &issues[0],
+            Issue::DuplicateAttribute { field, span, first, .. }
+                if field == "name" && span.line == 4 && first.line == 3
+        ));
+        assert!(matches!(
+            &issues[1],
+            Issue::DuplicateEntity { name, span, first }
+                if name == "Person" && span.line == 7 && first.line == 1
+        ));
+        assert!(matches!(
+            &issues[2],
+            Issue::DuplicateRelation { span, first, .. } if span.line == 11 && first.line == 10
+        ));
+        assert_eq!(issues.len(), 3);
+        assert_eq!(issues[2].severity(), Severity::Warning);
+    }
+
+    #[test]
+    fn test_missing_primary_key() {
+        let input = "[Person]\nname\n";
+        let er = parse(input).unwrap();
+        let issues = er.validate();
+
+        assert_eq!(
+            issues,
+            vec![Issue::MissingPrimaryKey {
+                entity: "Person".to_string(),
+                span: er.entities[0].name_span(),
+            }]
+        );
+        assert_eq!(issues[0].severity(), Severity::Warning);
+    }
+
+    #[test]
+    fn test_foreign_keys() {
+        let input = r#"[Person]
+*id
++team_id
+[Group]
+*id
+Person *--1 Group
+Person *--* Group
+"#;
+        let er = parse(input).unwrap();
+        let issues = er.validate();
+
+        assert_eq!(issues.len(), 2);
+        assert_eq!(
+            issues[0].to_string(),
+            "foreign key `team_id` in `Person` doesn't match any relationship"
+        );
+        assert_eq!(issues[0].span().as_str(input), "+team_id");
+        // Many-to-many relationships need a join entity rather than a key.
+        assert_eq!(
+            issues[1].to_string(),
+            "`Person` has no foreign key for its relationship with `Group`"
+        );
+        assert_eq!(issues[1].span().line, 6);
+        assert_eq!(issues[1].severity(), Severity::Warning);
+    }
+}